@@ -0,0 +1,2692 @@
+//! Analyses that can be applied to any kind of snapshots and trajectories.
+//!
+//! Functions in this module take snapshots through the `Snapshot` trait, so
+//! they work with any file format.
+//!
+//! Analyses that compare the same particle across snapshots, like
+//! `mean_square_displacement` and `MeanStructure`, require the number of
+//! particles to be fixed through the trajectory and fail otherwise.
+//! Analyses on each snapshot, like `radius_of_gyration`, work with any
+//! trajectory.
+//!
+//! To analyse a long trajectory without holding the whole data in memory,
+//! an `Accumulator` can be fed snapshots one by one while reading a file.
+//! ```no_run
+//! use trajan::xyz::XYZReader;
+//! use trajan::analysis::MeanRadiusOfGyration;
+//! let reader = XYZReader::open_pos("example.xyz").unwrap().f64();
+//! let rg = reader.accumulate(MeanRadiusOfGyration::new());
+//! ```
+use crate::boundary::SimulationBox;
+use crate::coordinate::{CoordKind, Coordinate};
+use crate::error::{Error, Result};
+use crate::neighbor::neighbor_pairs;
+use crate::particle::{Attribute, Particle};
+use crate::snapshot::Snapshot;
+use crate::trajectory::{Trajectory, VecTrajectory};
+use crate::xyz::{XYZParticle, XYZSnapshot};
+
+/// Calculates the center of mass of the snapshot.
+///
+/// If the snapshot does not have masses, the geometric center is returned.
+/// Returns None if the snapshot is empty or does not have positions.
+pub fn center_of_mass<T, S>(snap: &S) -> Option<nalgebra::Vector3<T>>
+where
+    T: nalgebra::Real,
+    S: Snapshot<T>,
+    <S as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    let positions = snap.positions()?;
+    if positions.is_empty() {
+        return None;
+    }
+    let masses = snap.masses()
+        .unwrap_or_else(|| vec![T::one(); positions.len()]);
+
+    let mut total  = T::zero();
+    let mut center = nalgebra::Vector3::zeros();
+    for (m, r) in masses.iter().zip(positions.iter()) {
+        total  += *m;
+        center += r * *m;
+    }
+    Some(center / total)
+}
+
+/// Calculates the radius of gyration of the snapshot.
+///
+/// If the snapshot does not have masses, all the particles are weighted
+/// equally. Returns None if the snapshot is empty or does not have positions.
+pub fn radius_of_gyration<T, S>(snap: &S) -> Option<T>
+where
+    T: nalgebra::Real,
+    S: Snapshot<T>,
+    <S as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    let center    = center_of_mass(snap)?;
+    let positions = snap.positions()?;
+    let masses    = snap.masses()
+        .unwrap_or_else(|| vec![T::one(); positions.len()]);
+
+    let mut total = T::zero();
+    let mut sum   = T::zero();
+    for (m, r) in masses.iter().zip(positions.iter()) {
+        total += *m;
+        sum   += (r - center).norm_squared() * *m;
+    }
+    Some((sum / total).sqrt())
+}
+
+/// Identifies a residue by the chain, the residue number and the name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ResidueId {
+    /// chain identifier. Empty if the format does not have chains, e.g. GRO.
+    pub chain:   std::string::String,
+    /// residue number.
+    pub resid:   i64,
+    /// residue name.
+    pub resname: std::string::String,
+}
+
+/// Calculates the center of mass of each residue, e.g. to map a snapshot
+/// onto coarse-grained beads.
+///
+/// Residues are identified by the "chain" (String, optional), "resid"
+/// (Integer) and "resname" (String) attributes of particles, and listed in
+/// the order of their first appearance. If the snapshot does not have
+/// masses, the geometric centers are returned. Returns None if the snapshot
+/// does not have positions or residue attributes.
+pub fn group_by_residue<T, S>(snap: &S)
+    -> Option<std::vec::Vec<(ResidueId, nalgebra::Vector3<T>)>>
+where
+    T: nalgebra::Real,
+    S: Snapshot<T>,
+    <S as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    let positions = snap.positions()?;
+    let masses    = snap.masses()
+        .unwrap_or_else(|| vec![T::one(); positions.len()]);
+
+    let mut order  = std::vec::Vec::new();
+    let mut groups = std::collections::HashMap::new();
+    for (i, (m, r)) in masses.iter().zip(positions.iter()).enumerate() {
+        let particle = &snap[i];
+        let id = ResidueId{
+            chain: match particle.attribute("chain") {
+                Some(chain) => chain.as_string()?.to_string(),
+                None        => std::string::String::new(),
+            },
+            resid:   particle.attribute("resid")?.as_integer()?,
+            resname: particle.attribute("resname")?.as_string()?.to_string(),
+        };
+        let (total, sum) = groups.entry(id.clone()).or_insert_with(|| {
+            order.push(id);
+            (T::zero(), nalgebra::Vector3::zeros())
+        });
+        *total += *m;
+        *sum   += r * *m;
+    }
+    Some(order.into_iter().map(|id| {
+        let (total, sum) = groups[&id];
+        (id, sum / total)
+    }).collect())
+}
+
+/// Calculates the gyration tensor `Σ m (r - r_c)(r - r_c)ᵀ / Σ m` of the
+/// snapshot, where `r_c` is the center of mass. Its trace is the square of
+/// the radius of gyration.
+///
+/// If the snapshot does not have masses, all the particles are weighted
+/// equally. Returns None if the snapshot is empty or does not have positions.
+pub fn gyration_tensor<T, S>(snap: &S) -> Option<nalgebra::Matrix3<T>>
+where
+    T: nalgebra::Real,
+    S: Snapshot<T>,
+    <S as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    let center    = center_of_mass(snap)?;
+    let positions = snap.positions()?;
+    let masses    = snap.masses()
+        .unwrap_or_else(|| vec![T::one(); positions.len()]);
+
+    let mut total  = T::zero();
+    let mut tensor = nalgebra::Matrix3::zeros();
+    for (m, r) in masses.iter().zip(positions.iter()) {
+        let dr = r - center;
+        total  += *m;
+        tensor += dr * dr.transpose() * *m;
+    }
+    Some(tensor / total)
+}
+
+/// Shape of a molecule derived from the eigenvalues of its gyration tensor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShapeDescriptors<T> {
+    /// eigenvalues of the gyration tensor in ascending order, `λ₁ ≤ λ₂ ≤ λ₃`.
+    pub eigenvalues: [T; 3],
+    /// `b = λ₃ - (λ₁ + λ₂) / 2`. It is zero if the shape is spherically
+    /// symmetric.
+    pub asphericity: T,
+    /// `c = λ₂ - λ₁`. It is zero if the shape is cylindrically symmetric.
+    pub acylindricity: T,
+    /// `κ² = 1 - 3 (λ₁λ₂ + λ₂λ₃ + λ₃λ₁) / (λ₁ + λ₂ + λ₃)²`. It is 0 for a
+    /// spherical shape and 1 for a linear one.
+    pub relative_anisotropy: T,
+}
+
+/// Calculates shape descriptors from the gyration tensor.
+///
+/// Returns None if the snapshot is empty, does not have positions, or all
+/// the particles are at the same position.
+pub fn shape_descriptors<T, S>(snap: &S) -> Option<ShapeDescriptors<T>>
+where
+    T: nalgebra::Real,
+    S: Snapshot<T>,
+    <S as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    let tensor = gyration_tensor(snap)?;
+    let values = tensor.symmetric_eigenvalues();
+    let mut l  = [values[0], values[1], values[2]];
+    l.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+    let trace = l[0] + l[1] + l[2];
+    if trace <= T::zero() {
+        return None;
+    }
+    let two   = nalgebra::convert::<f64, T>(2.0);
+    let three = nalgebra::convert::<f64, T>(3.0);
+    Some(ShapeDescriptors{
+        eigenvalues: l,
+        asphericity: l[2] - (l[0] + l[1]) / two,
+        acylindricity: l[1] - l[0],
+        relative_anisotropy: T::one() -
+            three * (l[0] * l[1] + l[1] * l[2] + l[2] * l[0]) / (trace * trace),
+    })
+}
+
+/// Calculates the volume of the convex hull of the positions.
+///
+/// The hull is built incrementally, that takes O(N²) time in the worst case.
+/// Returns None if the snapshot does not have positions or all the particles
+/// are on a plane (including the case of fewer than 4 particles).
+pub fn convex_hull_volume<T, S>(snap: &S) -> Option<T>
+where
+    T: nalgebra::Real,
+    S: Snapshot<T>,
+    <S as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    let p = snap.positions()?;
+    if p.len() < 4 {
+        return None;
+    }
+    // points closer than this to a line or a plane are considered to be on it
+    let lower = p.iter().fold(p[0], |acc, r| acc.zip_map(r, |a, b| a.min(b)));
+    let upper = p.iter().fold(p[0], |acc, r| acc.zip_map(r, |a, b| a.max(b)));
+    let eps   = (upper - lower).norm() * nalgebra::convert::<f64, T>(1e-10);
+
+    // initial tetrahedron, as large as possible to be robust
+    let farthest = |f: &dyn Fn(&nalgebra::Vector3<T>) -> T| -> (usize, T) {
+        p.iter().enumerate().fold((0, T::zero()), |(i, d), (j, r)| {
+            let dj = f(r);
+            if dj > d {(j, dj)} else {(i, d)}
+        })
+    };
+    let i0 = 0;
+    let (i1, d1) = farthest(&|r| (r - p[i0]).norm());
+    if d1 <= eps {
+        return None;
+    }
+    let axis = (p[i1] - p[i0]) / d1;
+    let (i2, d2) = farthest(&|r| (r - p[i0]).cross(&axis).norm());
+    if d2 <= eps {
+        return None;
+    }
+    let normal = (p[i1] - p[i0]).cross(&(p[i2] - p[i0])).normalize();
+    let (i3, d3) = farthest(&|r| (r - p[i0]).dot(&normal).abs());
+    if d3 <= eps {
+        return None;
+    }
+
+    let four   = nalgebra::convert::<f64, T>(4.0);
+    let inside = (p[i0] + p[i1] + p[i2] + p[i3]) / four;
+    let outward = |f: [usize; 3]| -> [usize; 3] {
+        let n = (p[f[1]] - p[f[0]]).cross(&(p[f[2]] - p[f[0]]));
+        if n.dot(&(p[f[0]] - inside)) < T::zero() {[f[0], f[2], f[1]]} else {f}
+    };
+    let mut faces = vec![outward([i0, i1, i2]), outward([i0, i1, i3]),
+                         outward([i0, i2, i3]), outward([i1, i2, i3])];
+
+    for (q, r) in p.iter().enumerate() {
+        if q == i0 || q == i1 || q == i2 || q == i3 {
+            continue;
+        }
+        let (visible, hidden): (std::vec::Vec<_>, std::vec::Vec<_>) =
+            faces.into_iter().partition(|f| {
+                let n = (p[f[1]] - p[f[0]]).cross(&(p[f[2]] - p[f[0]]));
+                n.dot(&(r - p[f[0]])) > eps * n.norm()
+            });
+        faces = hidden;
+        if visible.is_empty() {
+            continue; // inside the current hull
+        }
+        // edges on the boundary of the visible region are shared with a
+        // hidden face. connect them to the new point.
+        let edges: std::collections::HashSet<(usize, usize)> = visible.iter()
+            .flat_map(|f| vec![(f[0], f[1]), (f[1], f[2]), (f[2], f[0])])
+            .collect();
+        for &(a, b) in edges.iter() {
+            if !edges.contains(&(b, a)) {
+                faces.push([a, b, q]);
+            }
+        }
+    }
+
+    let six = nalgebra::convert::<f64, T>(6.0);
+    Some(faces.iter().fold(T::zero(), |acc, f| {
+        let (a, b, c) = (p[f[0]] - inside, p[f[1]] - inside, p[f[2]] - inside);
+        acc + a.dot(&b.cross(&c)) / six
+    }))
+}
+
+/// Calculates the dipole moment `Σ q r` of the snapshot, where charges are
+/// taken from the "charge" attribute.
+///
+/// If the net charge is not zero, the result depends on the origin. Use
+/// `dipole_moment_about` to specify it, e.g. the center of mass.
+/// Returns None if the snapshot does not have charges or positions.
+pub fn dipole_moment<T, S>(snap: &S) -> Option<nalgebra::Vector3<T>>
+where
+    T: nalgebra::Real,
+    S: Snapshot<T>,
+    <S as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    dipole_moment_about(snap, &nalgebra::Vector3::zeros())
+}
+
+/// Calculates the dipole moment `Σ q (r - origin)` of the snapshot. See
+/// `dipole_moment` for the details.
+pub fn dipole_moment_about<T, S>(snap: &S, origin: &nalgebra::Vector3<T>)
+    -> Option<nalgebra::Vector3<T>>
+where
+    T: nalgebra::Real,
+    S: Snapshot<T>,
+    <S as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    let positions = snap.positions()?;
+    let charges   = snap.attributes("charge")?;
+    charges.iter().zip(positions.iter()).try_fold(nalgebra::Vector3::zeros(), |acc, (q, r)| {
+        let q = nalgebra::convert::<f64, T>(q.as_float()?);
+        Some(acc + (r - origin) * q)
+    })
+}
+
+/// Calculates the nematic order parameter and the director from orientation
+/// vectors of molecules.
+///
+/// The Q-tensor `Q = (1/N) Σ (3/2 u⊗u - 1/2 I)` is built from the normalized
+/// vectors `u`, and its largest eigenvalue (the order parameter S) and the
+/// corresponding eigenvector (the director) are returned. S is 1 if all the
+/// vectors are parallel or anti-parallel, and close to 0 if they are random.
+/// Returns None if no vector is given or any of them is zero.
+pub fn nematic_order<T>(directors: &[nalgebra::Vector3<T>])
+    -> Option<(T, nalgebra::Vector3<T>)>
+where
+    T: nalgebra::Real
+{
+    if directors.is_empty() {
+        return None;
+    }
+    let half      = nalgebra::convert::<f64, T>(0.5);
+    let three_2nd = nalgebra::convert::<f64, T>(1.5);
+    let mut q = nalgebra::Matrix3::zeros();
+    for d in directors.iter() {
+        let u = d.try_normalize(T::zero())?;
+        q += u * u.transpose() * three_2nd - nalgebra::Matrix3::identity() * half;
+    }
+    q /= nalgebra::convert::<f64, T>(directors.len() as f64);
+
+    let eigen = q.symmetric_eigen();
+    let (i, s) = eigen.eigenvalues.iter().enumerate()
+        .fold((0, eigen.eigenvalues[0]), |(i, s), (j, &x)| if x > s {(j, x)} else {(i, s)});
+    Some((s, eigen.eigenvectors.column(i).into_owned()))
+}
+
+/// Collects the normalized vectors from the first particle to the second one
+/// of each pair, e.g. the orientations of rod-like molecules for
+/// `nematic_order`. If a box is given, the minimum image is used.
+/// Returns None if an index is out of range or the snapshot does not have
+/// positions.
+pub fn bond_directors<T, S>(snap: &S, pairs: &[(usize, usize)],
+                            boundary: Option<&SimulationBox<T>>)
+    -> Option<std::vec::Vec<nalgebra::Vector3<T>>>
+where
+    T: nalgebra::Real,
+    S: Snapshot<T>,
+    <S as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    pairs.iter()
+        .map(|&(i, j)| snap.displacement(i, j, boundary).map(|r| r.normalize()))
+        .collect()
+}
+
+/// Calculates the kinetic energy `Σ m v² / 2` of the snapshot.
+///
+/// Returns None if the snapshot does not have velocities or masses.
+pub fn kinetic_energy<T, S>(snap: &S) -> Option<T>
+where
+    T: nalgebra::Real,
+    S: Snapshot<T>,
+    <S as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    let velocities = snap.velocities()?;
+    let masses     = snap.masses()?;
+    let two        = T::one() + T::one();
+    Some(masses.iter().zip(velocities.iter())
+         .fold(T::zero(), |acc, (m, v)| acc + *m * v.norm_squared()) / two)
+}
+
+/// Counts the degrees of freedom of `n_atoms` point particles.
+///
+/// Each particle has 3 translational degrees of freedom, and each holonomic
+/// constraint (e.g. a bond fixed by SHAKE, or 3 per rigid water) removes
+/// one. If the motion of the center of mass is removed, 3 more are removed.
+/// That is, `3 N - N_c - (3 if remove_com)`, saturating at zero.
+///
+/// ```
+/// use trajan::analysis::degrees_of_freedom;
+/// // 100 rigid waters, each having 3 constraints
+/// assert_eq!(degrees_of_freedom(300, 300, true), 597);
+/// ```
+pub fn degrees_of_freedom(n_atoms: usize, n_constraints: usize, remove_com: bool) -> usize {
+    let removed = n_constraints + if remove_com {3} else {0};
+    (3 * n_atoms).saturating_sub(removed)
+}
+
+/// Calculates the instantaneous temperature `2 KE / (dof k_B)`.
+///
+/// The Boltzmann constant `k_b` should be given in the unit system of the
+/// snapshot. The degrees of freedom are counted by `degrees_of_freedom` from
+/// the number of particles in the snapshot, `n_constraints`, and whether the
+/// motion of the center of mass is removed. Returns None if the snapshot does
+/// not have velocities or masses, or no degree of freedom is left.
+pub fn temperature<T, S>(snap: &S, k_b: T, n_constraints: usize, remove_com: bool)
+    -> Option<T>
+where
+    T: nalgebra::Real,
+    S: Snapshot<T>,
+    <S as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    let dof = match degrees_of_freedom(snap.len(), n_constraints, remove_com) {
+        0   => return None,
+        dof => nalgebra::convert::<f64, T>(dof as f64),
+    };
+    let two = T::one() + T::one();
+    kinetic_energy(snap).map(|ke| two * ke / (dof * k_b))
+}
+
+/// Returns the largest magnitude of forces in each snapshot, to check the
+/// convergence of energy minimization. See `Snapshot::max_force`.
+///
+/// Fails if a snapshot is empty or does not have forces.
+pub fn max_force_series<T, Tr>(traj: &Tr) -> Result<std::vec::Vec<T>>
+where
+    T: nalgebra::Real,
+    Tr: Trajectory<T>,
+    <Tr as std::ops::Index<usize>>::Output: Snapshot<T>,
+    <<Tr as std::ops::Index<usize>>::Output as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    (0 .. traj.len()).map(|i| traj[i].max_force().ok_or_else(|| Error::invalid_condition(
+        format!("snapshot {} does not contain forces", i)))).collect()
+}
+
+/// Returns the volume of the box in each snapshot, e.g. to follow a
+/// simulation in the NPT ensemble. The box is the "box" metadata, like the
+/// one read from XTC, AMBER NetCDF or GSD files.
+///
+/// Fails if a snapshot does not have a box, instead of skipping it, so that
+/// the i-th volume always corresponds to the i-th snapshot.
+pub fn volume_series<T, Tr>(traj: &Tr) -> Result<std::vec::Vec<T>>
+where
+    T: nalgebra::Real,
+    Tr: Trajectory<T>,
+    <Tr as std::ops::Index<usize>>::Output: Snapshot<T>,
+    <<Tr as std::ops::Index<usize>>::Output as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    (0 .. traj.len()).map(|i| {
+        let lattice = traj[i].metadata("box").and_then(|b| b.as_matrix())
+            .ok_or_else(|| Error::invalid_condition(
+                format!("snapshot {} does not have a box", i)))?;
+        Ok(nalgebra::convert::<f64, T>(lattice.determinant().abs()))
+    }).collect()
+}
+
+/// Returns the density, `total_mass` divided by the volume of the box, in
+/// each snapshot. The mass is given because it does not change and often
+/// is not stored in trajectory files. See `volume_series` for the box.
+///
+/// Fails if a snapshot does not have a box.
+pub fn density_series<T, Tr>(traj: &Tr, total_mass: T) -> Result<std::vec::Vec<T>>
+where
+    T: nalgebra::Real,
+    Tr: Trajectory<T>,
+    <Tr as std::ops::Index<usize>>::Output: Snapshot<T>,
+    <<Tr as std::ops::Index<usize>>::Output as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    Ok(volume_series(traj)?.into_iter().map(|v| total_mass / v).collect())
+}
+
+/// The center of mass subtracted by `remove_com_motion`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComReference {
+    /// The center of mass of each frame. Every frame is centered at origin.
+    EachFrame,
+    /// The center of mass of the first frame. All frames are shifted by the
+    /// same vector, so the motion of the center of mass is kept.
+    FirstFrame,
+}
+
+/// Subtracts the center of mass from the positions in each frame.
+///
+/// The center of mass is weighted by masses if the snapshot has them. It is
+/// a standard preprocessing before calculating e.g. MSD, to remove the drift
+/// of the whole system. Empty frames are left as they are.
+///
+/// Fails if a non-empty snapshot does not have positions.
+pub fn remove_com_motion<T>(traj: &mut VecTrajectory<XYZSnapshot<T>>,
+                            reference: ComReference) -> Result<()>
+where
+    T: nalgebra::Real
+{
+    let mut first = None;
+    for snapshot in traj.snapshots.iter_mut() {
+        if snapshot.is_empty() {
+            continue;
+        }
+        let com = match (reference, first) {
+            (ComReference::FirstFrame, Some(com)) => com,
+            _ => center_of_mass(snapshot).ok_or_else(|| Error::invalid_condition(
+                    "snapshot does not contain positions".to_string()))?,
+        };
+        first.get_or_insert(com);
+        snapshot.apply_transform(|r| *r -= com);
+    }
+    Ok(())
+}
+
+/// Superimposes each frame onto the reference by the Kabsch algorithm, to
+/// remove the overall rotation and translation. If no reference is given,
+/// the first frame is used.
+///
+/// The optimal rotation is found using the particles in `fit_group` (all
+/// the particles if None), and then applied to all the particles. All
+/// particles are weighted equally.
+///
+/// Fails if the number of particles in a frame differs from the reference,
+/// an index in `fit_group` is out of range or the group is empty, or a
+/// snapshot does not have positions.
+pub fn align_trajectory<T>(traj: &mut VecTrajectory<XYZSnapshot<T>>,
+                           reference: Option<&XYZSnapshot<T>>,
+                           fit_group: Option<&[usize]>) -> Result<()>
+where
+    T: nalgebra::Real
+{
+    let first;
+    let reference = match reference {
+        Some(reference) => reference,
+        None => match traj.snapshots.first() {
+            Some(snapshot) => {
+                first = snapshot.clone();
+                &first
+            }
+            None => return Ok(()),
+        },
+    };
+    let all: std::vec::Vec<usize> = (0 .. reference.len()).collect();
+    let group = fit_group.unwrap_or(&all);
+    if group.is_empty() {
+        return Err(Error::invalid_condition("fit group is empty".to_string()));
+    }
+    if let Some(&i) = group.iter().find(|&&i| reference.len() <= i) {
+        return Err(Error::invalid_condition(format!(
+            "index {} is out of range: {} particles", i, reference.len())));
+    }
+    let select = |positions: &[nalgebra::Vector3<T>]| -> std::vec::Vec<nalgebra::Vector3<T>> {
+        group.iter().map(|&i| positions[i]).collect()
+    };
+
+    let q  = reference.positions().ok_or_else(|| Error::invalid_condition(
+        "snapshot does not contain positions".to_string()))?;
+    let q  = select(&q);
+    let qc = centroid(&q);
+    for snapshot in traj.snapshots.iter_mut() {
+        let (p, _) = paired_positions(snapshot, reference)?;
+        let p   = select(&p);
+        let pc  = centroid(&p);
+        let rot = kabsch_rotation(&p, &q);
+        snapshot.apply_transform(|r| *r = rot * (*r - pc) + qc);
+    }
+    Ok(())
+}
+
+/// Differences between the same frame of two trajectories.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameDiff<T> {
+    /// index of the frame.
+    pub frame: usize,
+    /// the maximum distance between the coordinates of the same particle.
+    /// It is NaN if any distance is NaN. None if the frame is missing in one
+    /// of the trajectories, or the numbers or kinds of coordinates differ.
+    pub max_deviation: std::option::Option<T>,
+    /// true if `max_deviation` exceeds the tolerance or is NaN.
+    pub exceeds_tol: bool,
+    /// true if the numbers of particles differ.
+    pub count_mismatch: bool,
+    /// true if the coordinate kind of any particle differs, e.g. a position
+    /// is compared to a velocity.
+    pub kind_mismatch: bool,
+    /// true if the name of any particle differs.
+    pub name_mismatch: bool,
+    /// true if only one of the trajectories has this frame.
+    pub missing: bool,
+}
+
+impl<T> FrameDiff<T> {
+    /// Returns true if any difference is found in the frame.
+    pub fn is_different(&self) -> bool {
+        self.exceeds_tol || self.count_mismatch || self.kind_mismatch ||
+            self.name_mismatch || self.missing
+    }
+}
+
+/// Compares two trajectories frame by frame.
+///
+/// For each frame, the maximum distance between the coordinates of the same
+/// particle is compared to `tol`. The names and the number of particles are
+/// also compared. If the numbers of frames differ, the frames that exist in
+/// only one of them are reported as `missing`. It is intended for regression
+/// tests, e.g. checking that a pipeline reproduces a reference trajectory.
+pub fn diff_trajectories<T>(a: &VecTrajectory<XYZSnapshot<T>>,
+                            b: &VecTrajectory<XYZSnapshot<T>>, tol: T)
+    -> std::vec::Vec<FrameDiff<T>>
+where
+    T: nalgebra::Real
+{
+    let frames = std::cmp::max(a.snapshots.len(), b.snapshots.len());
+    (0 .. frames).map(|frame| {
+        let (sa, sb) = match (a.snapshots.get(frame), b.snapshots.get(frame)) {
+            (Some(sa), Some(sb)) => (sa, sb),
+            _ => return FrameDiff{frame, max_deviation: None, exceeds_tol: false,
+                                  count_mismatch: false, kind_mismatch: false,
+                                  name_mismatch: false, missing: true},
+        };
+        let count_mismatch = sa.len() != sb.len();
+        let kind_mismatch  = sa.particles.iter().zip(sb.particles.iter())
+            .any(|(pa, pb)| pa.xyz.which() != pb.xyz.which());
+        let name_mismatch  = sa.particles.iter().zip(sb.particles.iter())
+            .any(|(pa, pb)| pa.name != pb.name);
+        let max_deviation  = if count_mismatch || kind_mismatch {
+            None
+        } else {
+            Some(sa.particles.iter().zip(sb.particles.iter())
+                .map(|(pa, pb)| (nalgebra::Vector3::from(pa.xyz) -
+                                 nalgebra::Vector3::from(pb.xyz)).norm())
+                .fold(T::zero(), |acc, d| match acc.partial_cmp(&d) {
+                    Some(std::cmp::Ordering::Less) => d,
+                    Some(_) => acc,
+                    // either is NaN. keep it, not to hide a broken coordinate
+                    None => acc + d,
+                }))
+        };
+        let exceeds_tol = max_deviation.is_some_and(|d| matches!(d.partial_cmp(&tol),
+            Some(std::cmp::Ordering::Greater) | None));
+        FrameDiff{frame, max_deviation, exceeds_tol, count_mismatch, kind_mismatch,
+                  name_mismatch, missing: false}
+    }).collect()
+}
+
+/// Resamples a trajectory onto a uniform time grid by linear interpolation.
+///
+/// `times` is the time of each frame, and should be strictly increasing.
+/// The new frames are at `times[0] + k * target_dt` up to the last time, and
+/// coordinates of each particle are interpolated between the two frames
+/// that bracket the time. Names are taken from the earlier frame. Each new
+/// frame has the time in its metadata ("time") and comment (`t = ...`).
+///
+/// Fails if the number of times differs from the number of frames, the
+/// times are not increasing, `target_dt` is not positive, or the number of
+/// particles changes.
+pub fn resample_by_time<T>(traj: &VecTrajectory<XYZSnapshot<T>>, times: &[T], target_dt: T)
+    -> Result<VecTrajectory<XYZSnapshot<T>>>
+where
+    T: nalgebra::Real
+{
+    if times.len() != traj.snapshots.len() {
+        return Err(Error::invalid_condition(format!(
+            "{} times are given for {} frames", times.len(), traj.snapshots.len())));
+    }
+    if target_dt <= T::zero() {
+        return Err(Error::invalid_condition("target_dt must be positive".to_string()));
+    }
+    if let Some(i) = times.windows(2).position(|t| t[1] <= t[0]) {
+        return Err(Error::invalid_condition(format!(
+            "times are not increasing at frame {}", i + 1)));
+    }
+    if !traj.is_fixed_count() {
+        return Err(Error::invalid_condition(
+            "resampling requires a fixed number of particles".to_string()));
+    }
+    let (first, last) = match (times.first(), times.last()) {
+        (Some(first), Some(last)) => (*first, *last),
+        _ => return Ok(VecTrajectory::new(std::vec::Vec::new())),
+    };
+
+    // a tolerance to include the last time in spite of rounding errors
+    let eps = target_dt * nalgebra::convert::<f64, T>(1e-9);
+    let mut frames = std::vec::Vec::new();
+    let mut j = 0;
+    let mut k = 0;
+    loop {
+        let t = first + target_dt * nalgebra::convert::<f64, T>(k as f64);
+        if last + eps < t {
+            break;
+        }
+        let t = if last < t {last} else {t};
+        while j + 2 < times.len() && times[j + 1] < t {
+            j += 1;
+        }
+        let (lower, upper) = (&traj.snapshots[j], &traj.snapshots[(j + 1).min(times.len() - 1)]);
+        let w = if j + 1 < times.len() {(t - times[j]) / (times[j + 1] - times[j])} else {T::zero()};
+
+        let particles = lower.particles.iter().zip(upper.particles.iter()).map(|(p, q)| {
+            let mut xyz = p.xyz;
+            for c in 0 .. 3 {
+                xyz[c] = p.xyz[c] + (q.xyz[c] - p.xyz[c]) * w;
+            }
+            XYZParticle::new(p.name.clone(), xyz)
+        }).collect();
+        let time = nalgebra::try_convert::<T, f64>(t).unwrap_or(f64::NAN);
+        let mut snapshot = XYZSnapshot::new(format!("t = {}", time), particles);
+        snapshot.set_metadata("time", Attribute::Float(time));
+        frames.push(snapshot);
+        k += 1;
+    }
+    Ok(VecTrajectory::new(frames))
+}
+
+/// Calculates the length of each bond.
+///
+/// A bond is a pair of indices of particles. If a box is given, the length
+/// is calculated with the minimum image convention, so bonds stretched across
+/// the boundary can be detected by comparing the results with and without
+/// the box.
+///
+/// Fails if an index is out of range or the snapshot does not have positions.
+pub fn bond_lengths<T, S>(snap: &S, bonds: &[(usize, usize)],
+                          boundary: Option<&SimulationBox<T>>)
+    -> Result<std::vec::Vec<T>>
+where
+    T: nalgebra::Real,
+    S: Snapshot<T>,
+    <S as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    let positions = snap.positions().ok_or_else(|| Error::invalid_condition(
+        "snapshot does not contain positions".to_string()))?;
+
+    bonds.iter().map(|&(i, j)| {
+        if i >= positions.len() || j >= positions.len() {
+            return Err(Error::invalid_condition(format!(
+                "bond ({}, {}) is out of range: snapshot has {} particles",
+                i, j, positions.len())));
+        }
+        let dr = positions[j] - positions[i];
+        let dr = match boundary {
+            Some(b) => b.minimum_image(dr),
+            None    => dr,
+        };
+        Ok(dr.norm())
+    }).collect()
+}
+
+/// Calculates the angle of each triplet in radian.
+///
+/// A triplet `(i, j, k)` is the angle between `i - j` and `k - j` at the
+/// particle `j`, in [0, π]. If a box is given, the vectors are calculated
+/// with the minimum image convention. The angle is NaN if `i` or `k` is at
+/// the same position as `j`.
+///
+/// Fails if an index is out of range or the snapshot does not have positions.
+pub fn bond_angles<T, S>(snap: &S, triplets: &[(usize, usize, usize)],
+                         boundary: Option<&SimulationBox<T>>)
+    -> Result<std::vec::Vec<T>>
+where
+    T: nalgebra::Real,
+    S: Snapshot<T> + ?Sized,
+    <S as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    let positions = snap.positions().ok_or_else(|| Error::invalid_condition(
+        "snapshot does not contain positions".to_string()))?;
+    let vector = |from: usize, to: usize| {
+        let dr = positions[to] - positions[from];
+        match boundary {
+            Some(b) => b.minimum_image(dr),
+            None    => dr,
+        }
+    };
+
+    triplets.iter().map(|&(i, j, k)| {
+        if i >= positions.len() || j >= positions.len() || k >= positions.len() {
+            return Err(Error::invalid_condition(format!(
+                "triplet ({}, {}, {}) is out of range: snapshot has {} particles",
+                i, j, k, positions.len())));
+        }
+        let (a, b) = (vector(j, i), vector(j, k));
+        let cos = a.dot(&b) / (a.norm() * b.norm());
+        // rounding errors may make |cos| slightly larger than 1
+        Ok(cos.max(-T::one()).min(T::one()).acos())
+    }).collect()
+}
+
+/// Makes a histogram of the angles of triplets over all the snapshots in a
+/// trajectory, with `n_bins` bins in [0, π]. An angle of exactly π is
+/// counted in the last bin.
+///
+/// See `bond_angles` for the details. Fails if an index is out of range or
+/// a snapshot does not have positions.
+pub fn angle_distribution<T, Tr>(traj: &Tr, triplets: &[(usize, usize, usize)],
+                                 boundary: Option<&SimulationBox<T>>, n_bins: usize)
+    -> Result<Histogram<T>>
+where
+    T: nalgebra::Real,
+    Tr: Trajectory<T>,
+    <Tr as std::ops::Index<usize>>::Output: Snapshot<T>,
+    <<Tr as std::ops::Index<usize>>::Output as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    let mut histogram = Histogram::new(T::zero(), T::pi(), n_bins)?;
+    // the center of the last bin, to count π that is out of [0, π)
+    let last = T::pi() - histogram.width() / (T::one() + T::one());
+    for i in 0 .. traj.len() {
+        for angle in bond_angles(&traj[i], triplets, boundary)? {
+            histogram.add(if angle < T::pi() {angle} else {last});
+        }
+    }
+    Ok(histogram)
+}
+
+/// Makes a histogram of bond lengths in [min, max) with `n_bins` bins.
+///
+/// See `bond_lengths` for the details.
+pub fn bond_length_histogram<T, S>(snap: &S, bonds: &[(usize, usize)],
+                                   boundary: Option<&SimulationBox<T>>,
+                                   min: T, max: T, n_bins: usize)
+    -> Result<Histogram<T>>
+where
+    T: nalgebra::Real,
+    S: Snapshot<T>,
+    <S as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    let mut histogram = Histogram::new(min, max, n_bins)?;
+    for length in bond_lengths(snap, bonds, boundary)? {
+        histogram.add(length);
+    }
+    Ok(histogram)
+}
+
+/// Finds pairs of particles closer than `min_dist`, e.g. overlapping atoms in
+/// an initial configuration.
+///
+/// Returns `(i, j, distance)` with `i < j`, sorted by `(i, j)`. If a box is
+/// given, the distance is calculated with the minimum image convention. See
+/// `neighbor::neighbor_pairs` for the details.
+///
+/// Fails if the snapshot does not have positions.
+pub fn find_clashes<T, S>(snap: &S, boundary: Option<&SimulationBox<T>>, min_dist: T)
+    -> Result<std::vec::Vec<(usize, usize, T)>>
+where
+    T: nalgebra::Real,
+    S: Snapshot<T>,
+    <S as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    let positions = snap.positions().ok_or_else(|| Error::invalid_condition(
+        "snapshot does not contain positions".to_string()))?;
+    Ok(neighbor_pairs(&positions, boundary, min_dist))
+}
+
+/// Counts the particles in `shell_indices` within `cutoff` from each particle
+/// in `center_indices`, e.g. solvent atoms around a solute.
+///
+/// The i-th element is the count around `center_indices[i]`. A particle is
+/// not counted around itself even if it is in both groups. If a box is
+/// given, the distance is calculated with the minimum image convention.
+/// See `neighbor::neighbor_pairs` for the details.
+///
+/// Fails if an index is out of range or the snapshot does not have positions.
+pub fn coordination_number<T, S>(snap: &S, boundary: Option<&SimulationBox<T>>,
+                                 center_indices: &[usize], shell_indices: &[usize],
+                                 cutoff: T) -> Result<std::vec::Vec<usize>>
+where
+    T: nalgebra::Real,
+    S: Snapshot<T> + ?Sized,
+    <S as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    let positions = snap.positions().ok_or_else(|| Error::invalid_condition(
+        "snapshot does not contain positions".to_string()))?;
+    let indices: std::vec::Vec<usize> =
+        center_indices.iter().chain(shell_indices.iter()).cloned().collect();
+    if let Some(&i) = indices.iter().find(|&&i| positions.len() <= i) {
+        return Err(Error::invalid_condition(format!(
+            "index {} is out of range: {} particles", i, positions.len())));
+    }
+    // centers come first, followed by shells
+    let selected: std::vec::Vec<_> = indices.iter().map(|&i| positions[i]).collect();
+    let n_centers = center_indices.len();
+
+    let mut counts = vec![0; n_centers];
+    for (i, j, _) in neighbor_pairs(&selected, boundary, cutoff) {
+        if i < n_centers && n_centers <= j && indices[i] != indices[j] {
+            counts[i] += 1;
+        }
+    }
+    Ok(counts)
+}
+
+/// Calculates the coordination number (see `coordination_number`) averaged
+/// over the center particles and the snapshots. Returns zero for an empty
+/// trajectory or no center particles.
+pub fn mean_coordination_number<T, Tr>(traj: &Tr, boundary: Option<&SimulationBox<T>>,
+                                       center_indices: &[usize], shell_indices: &[usize],
+                                       cutoff: T) -> Result<T>
+where
+    T: nalgebra::Real,
+    Tr: Trajectory<T>,
+    <Tr as std::ops::Index<usize>>::Output: Snapshot<T>,
+    <<Tr as std::ops::Index<usize>>::Output as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    let mut total = 0usize;
+    for i in 0 .. traj.len() {
+        let counts = coordination_number(&traj[i], boundary, center_indices,
+                                         shell_indices, cutoff)?;
+        total += counts.iter().sum::<usize>();
+    }
+    let samples = traj.len() * center_indices.len();
+    if samples == 0 {
+        return Ok(T::zero());
+    }
+    Ok(nalgebra::convert::<f64, T>(total as f64 / samples as f64))
+}
+
+/// Groups particles into clusters. Two particles belong to the same cluster
+/// if they are connected by a chain of pairs closer than `cutoff`.
+///
+/// If a box is given, the distance is calculated with the minimum image
+/// convention, so a cluster can extend across the boundary. Each cluster is
+/// a list of indices in ascending order, and the clusters are sorted by their
+/// first index. An isolated particle forms a cluster by itself.
+///
+/// Fails if the snapshot does not have positions.
+pub fn clusters<T, S>(snap: &S, boundary: Option<&SimulationBox<T>>, cutoff: T)
+    -> Result<std::vec::Vec<std::vec::Vec<usize>>>
+where
+    T: nalgebra::Real,
+    S: Snapshot<T> + ?Sized,
+    <S as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    let positions = snap.positions().ok_or_else(|| Error::invalid_condition(
+        "snapshot does not contain positions".to_string()))?;
+
+    // union-find. each root is the smallest index in the cluster.
+    fn root(parents: &mut [usize], mut i: usize) -> usize {
+        while parents[i] != i {
+            parents[i] = parents[parents[i]];
+            i = parents[i];
+        }
+        i
+    }
+    let mut parents: std::vec::Vec<usize> = (0 .. positions.len()).collect();
+    for (i, j, _) in neighbor_pairs(&positions, boundary, cutoff) {
+        let (ri, rj) = (root(&mut parents, i), root(&mut parents, j));
+        parents[std::cmp::max(ri, rj)] = std::cmp::min(ri, rj);
+    }
+
+    let mut clusters = std::vec::Vec::new();
+    let mut cluster_of = vec![0; positions.len()];
+    for i in 0 .. positions.len() {
+        let r = root(&mut parents, i);
+        if r == i {
+            cluster_of[i] = clusters.len();
+            clusters.push(vec![i]);
+        } else {
+            clusters[cluster_of[r]].push(i);
+        }
+    }
+    Ok(clusters)
+}
+
+/// Returns the number of particles in the largest cluster found by
+/// `clusters`, or 0 if the snapshot is empty.
+pub fn largest_cluster_size<T, S>(snap: &S, boundary: Option<&SimulationBox<T>>, cutoff: T)
+    -> Result<usize>
+where
+    T: nalgebra::Real,
+    S: Snapshot<T> + ?Sized,
+    <S as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    Ok(clusters(snap, boundary, cutoff)?.iter().map(|c| c.len()).max().unwrap_or(0))
+}
+
+/// Calculates the number density around each particle, i.e. the number of
+/// other particles within `radius` divided by the volume of the sphere.
+///
+/// It distinguishes dense and sparse regions, e.g. bulk and surface atoms,
+/// without calculating the radial distribution. If a box is given, the
+/// distance is calculated with the minimum image convention, so `radius`
+/// should not exceed a half of the box width.
+///
+/// Fails if the snapshot does not have positions.
+pub fn local_density<T, S>(snap: &S, boundary: Option<&SimulationBox<T>>, radius: T)
+    -> Result<std::vec::Vec<T>>
+where
+    T: nalgebra::Real,
+    S: Snapshot<T> + ?Sized,
+    <S as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    let positions = snap.positions().ok_or_else(|| Error::invalid_condition(
+        "snapshot does not contain positions".to_string()))?;
+    let mut counts = vec![0usize; positions.len()];
+    for (i, j, _) in neighbor_pairs(&positions, boundary, radius) {
+        counts[i] += 1;
+        counts[j] += 1;
+    }
+    let volume = nalgebra::convert::<f64, T>(4.0 / 3.0) * T::pi() * radius.powi(3);
+    Ok(counts.iter().map(|&n| nalgebra::convert::<f64, T>(n as f64) / volume).collect())
+}
+
+/// Estimates velocities from positions by the central difference.
+///
+/// `prev` and `next` are snapshots at `t - dt` and `t + dt`, and the velocity
+/// at `t` is estimated as `(next - prev) / (2 dt)`. If a box is given, the
+/// displacement is calculated with the minimum image convention. The result
+/// has the names of `prev` and an empty comment.
+///
+/// Fails if the numbers of particles differ or a snapshot does not have
+/// positions.
+pub fn finite_difference_velocities<T>(prev: &XYZSnapshot<T>, next: &XYZSnapshot<T>,
+                                       dt: T, boundary: Option<&SimulationBox<T>>)
+    -> Result<XYZSnapshot<T>>
+where
+    T: nalgebra::Real
+{
+    if prev.particles.len() != next.particles.len() {
+        return Err(Error::invalid_condition(format!(
+            "number of particles differs: {} and {}",
+            prev.particles.len(), next.particles.len())));
+    }
+    let no_positions = || Error::invalid_condition(
+        "snapshot does not contain positions".to_string());
+    let r0 = prev.positions().ok_or_else(no_positions)?;
+    let r1 = next.positions().ok_or_else(no_positions)?;
+
+    let two_dt = dt + dt;
+    let particles = prev.particles.iter().zip(r0.iter().zip(r1.iter()))
+        .map(|(p, (r0, r1))| {
+            let dr = r1 - r0;
+            let dr = match boundary {
+                Some(b) => b.minimum_image(dr),
+                None    => dr,
+            };
+            XYZParticle::new(p.name.clone(),
+                             Coordinate::from_vector(CoordKind::Velocity, dr / two_dt))
+        }).collect();
+    Ok(XYZSnapshot::new(std::string::String::new(), particles))
+}
+
+/// Calculates the static structure factor S(q) by the direct method.
+///
+/// S(q) is calculated as `|Σ_j exp(i q·r_j)|² / N` over the wave vectors
+/// commensurate with the box, i.e. the reciprocal lattice vectors. Each wave
+/// vector is assigned to the nearest value in `q_values`, and S(q) is
+/// averaged over the wave vectors assigned to q. The shell of q extends to a
+/// half of the distance to the nearest other value in `q_values`, but not
+/// more than a half of the shortest reciprocal lattice vector. So the
+/// resolution of the result is controlled by `q_values`. The value is zero
+/// if no wave vector is assigned.
+///
+/// Returns None if the snapshot is empty or does not have positions.
+pub fn structure_factor<T, S>(snap: &S, boundary: &SimulationBox<T>, q_values: &[T])
+    -> Option<std::vec::Vec<T>>
+where
+    T: nalgebra::Real,
+    S: Snapshot<T>,
+    <S as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    let positions = snap.positions()?;
+    if positions.is_empty() {
+        return None;
+    }
+    let mut sum   = vec![T::zero(); q_values.len()];
+    let mut count = vec![0usize;    q_values.len()];
+    if q_values.is_empty() {
+        return Some(sum);
+    }
+
+    let reciprocal = boundary.reciprocal();
+    let lattice    = boundary.lattice();
+    let two        = nalgebra::convert::<f64, T>(2.0);
+    let half_width = (0 .. 3).map(|i| reciprocal.column(i).norm())
+        .fold(T::max_value(), |a, b| a.min(b)) / two;
+    let shell_widths: std::vec::Vec<T> = q_values.iter().enumerate().map(|(i, qi)| {
+        q_values.iter().enumerate().filter(|&(j, _)| i != j)
+            .fold(half_width, |w, (_, qj)| w.min((*qi - *qj).abs() / two))
+    }).collect();
+    let q_max = q_values.iter().fold(T::zero(), |a, b| a.max(*b)) + half_width;
+
+    // n_i = q · a_i / 2π, so |n_i| <= q_max |a_i| / 2π.
+    let n_max: std::vec::Vec<i64> = (0 .. 3).map(|i| {
+        nalgebra::try_convert::<T, f64>(q_max * lattice.column(i).norm() / T::two_pi())
+            .map(|x| x.ceil() as i64).unwrap_or(0)
+    }).collect();
+
+    let n_particles = nalgebra::convert::<f64, T>(positions.len() as f64);
+    for n0 in -n_max[0] ..= n_max[0] {
+        for n1 in -n_max[1] ..= n_max[1] {
+            for n2 in -n_max[2] ..= n_max[2] {
+                if n0 == 0 && n1 == 0 && n2 == 0 {
+                    continue;
+                }
+                let n = nalgebra::Vector3::new(nalgebra::convert::<f64, T>(n0 as f64),
+                                               nalgebra::convert::<f64, T>(n1 as f64),
+                                               nalgebra::convert::<f64, T>(n2 as f64));
+                let q = reciprocal * n;
+                let q_norm = q.norm();
+
+                let (idx, dist) = q_values.iter().enumerate()
+                    .map(|(i, qv)| (i, (*qv - q_norm).abs()))
+                    .fold((0, T::max_value()), |a, b| if b.1 < a.1 {b} else {a});
+                if dist >= shell_widths[idx] {
+                    continue;
+                }
+                let (mut re, mut im) = (T::zero(), T::zero());
+                for r in positions.iter() {
+                    let (sin, cos) = q.dot(r).sin_cos();
+                    re += cos;
+                    im += sin;
+                }
+                sum[idx]   += (re * re + im * im) / n_particles;
+                count[idx] += 1;
+            }
+        }
+    }
+    Some(sum.into_iter().zip(count).map(|(s, c)| {
+        if c == 0 {T::zero()} else {s / nalgebra::convert::<f64, T>(c as f64)}
+    }).collect())
+}
+
+/// Calculates the mean square displacement for each lag time.
+///
+/// The i-th element is the MSD between snapshots separated by i frames,
+/// averaged over all the particles and time origins. Positions should be
+/// unwrapped beforehand (see `boundary::unwrap_trajectory`).
+///
+/// Fails if the number of particles is not fixed or any snapshot does not
+/// have positions.
+pub fn mean_square_displacement<T, Tr>(traj: &Tr) -> Result<std::vec::Vec<T>>
+where
+    T: nalgebra::Real,
+    Tr: Trajectory<T>,
+    <Tr as std::ops::Index<usize>>::Output: Snapshot<T>,
+    <<Tr as std::ops::Index<usize>>::Output as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    if !traj.is_fixed_count() {
+        return Err(Error::invalid_condition(
+            "MSD requires a fixed number of particles".to_string()));
+    }
+    let positions = (0 .. traj.len()).map(|i| traj[i].positions())
+        .collect::<std::option::Option<std::vec::Vec<_>>>()
+        .ok_or_else(|| Error::invalid_condition(
+            "snapshot does not contain positions".to_string()))?;
+
+    let n_frames = positions.len();
+    let mut msd = vec![T::zero(); n_frames];
+    for (lag, value) in msd.iter_mut().enumerate().skip(1) {
+        let mut sum   = T::zero();
+        let mut count = 0usize;
+        for t in 0 .. n_frames - lag {
+            for (r0, r1) in positions[t].iter().zip(positions[t + lag].iter()) {
+                sum   += (r1 - r0).norm_squared();
+                count += 1;
+            }
+        }
+        if count != 0 {
+            *value = sum / nalgebra::convert::<f64, T>(count as f64);
+        }
+    }
+    Ok(msd)
+}
+
+/// Calculates the autocorrelation of the direction of the end-to-end vector
+/// `r(last) - r(first)`, e.g. of a polymer chain.
+///
+/// The i-th element is `<u(t) · u(t + i)>` where `u` is the unit end-to-end
+/// vector, averaged over time origins. The first element is always 1.
+///
+/// Fails if the number of particles is not fixed, an index is out of range,
+/// a snapshot does not have positions, or the end-to-end vector vanishes.
+pub fn end_to_end_autocorrelation<T, Tr>(traj: &Tr, first: usize, last: usize)
+    -> Result<std::vec::Vec<T>>
+where
+    T: nalgebra::Real,
+    Tr: Trajectory<T>,
+    <Tr as std::ops::Index<usize>>::Output: Snapshot<T>,
+    <<Tr as std::ops::Index<usize>>::Output as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    if !traj.is_fixed_count() {
+        return Err(Error::invalid_condition(
+            "autocorrelation requires a fixed number of particles".to_string()));
+    }
+    if !traj.is_empty() {
+        let n = traj[0].len();
+        if n <= first || n <= last {
+            return Err(Error::invalid_condition(format!(
+                "index out of range: {} and {} for {} particles", first, last, n)));
+        }
+    }
+    let directions = (0 .. traj.len()).map(|i| {
+        let r = traj[i].displacement(first, last, None).ok_or_else(|| {
+            Error::invalid_condition("snapshot does not contain positions".to_string())
+        })?;
+        r.try_normalize(T::zero()).ok_or_else(|| {
+            Error::invalid_condition(format!("end-to-end vector vanishes in snapshot {}", i))
+        })
+    }).collect::<Result<std::vec::Vec<_>>>()?;
+
+    let n_frames = directions.len();
+    Ok((0 .. n_frames).map(|lag| {
+        let sum = (0 .. n_frames - lag)
+            .fold(T::zero(), |acc, t| acc + directions[t].dot(&directions[t + lag]));
+        sum / nalgebra::convert::<f64, T>((n_frames - lag) as f64)
+    }).collect())
+}
+
+/// Finds the rotation that maps `reference` onto `frame` optimally.
+///
+/// Both snapshots are centered at their geometric centers, and the rotation
+/// that minimizes the RMSD between them is found by the Kabsch algorithm.
+/// All particles are weighted equally. It is meaningful for a rigid (or
+/// nearly rigid) group of particles.
+///
+/// Fails if the numbers of particles differ or a snapshot does not have
+/// positions.
+pub fn orientation_quaternion<T, S>(reference: &S, frame: &S)
+    -> Result<nalgebra::UnitQuaternion<T>>
+where
+    T: nalgebra::Real,
+    S: Snapshot<T> + ?Sized,
+    <S as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    let (p, q) = paired_positions(reference, frame)?;
+    let rot = kabsch_rotation(&p, &q);
+    Ok(nalgebra::UnitQuaternion::from_rotation_matrix(
+        &nalgebra::Rotation3::from_matrix_unchecked(rot)))
+}
+
+/// Finds the orientation of each snapshot relative to the first one.
+///
+/// See `orientation_quaternion` for the details. The first element is always
+/// the identity. Fails if the number of particles is not fixed or any
+/// snapshot does not have positions.
+pub fn orientation_series<T, Tr>(traj: &Tr)
+    -> Result<std::vec::Vec<nalgebra::UnitQuaternion<T>>>
+where
+    T: nalgebra::Real,
+    Tr: Trajectory<T>,
+    <Tr as std::ops::Index<usize>>::Output: Snapshot<T>,
+    <<Tr as std::ops::Index<usize>>::Output as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    if !traj.is_fixed_count() {
+        return Err(Error::invalid_condition(
+            "orientation tracking requires a fixed number of particles".to_string()));
+    }
+    (0 .. traj.len()).map(|i| orientation_quaternion(&traj[0], &traj[i])).collect()
+}
+
+/// Calculates the RMSD between two snapshots after superimposing `frame`
+/// onto `reference` by the Kabsch algorithm. All particles are weighted
+/// equally.
+///
+/// Fails if the numbers of particles differ, the snapshots are empty, or a
+/// snapshot does not have positions.
+pub fn rmsd<T, S, R>(frame: &S, reference: &R) -> Result<T>
+where
+    T: nalgebra::Real,
+    S: Snapshot<T> + ?Sized,
+    R: Snapshot<T> + ?Sized,
+    <S as std::ops::Index<usize>>::Output: Particle<T>,
+    <R as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    let (p, q) = paired_positions(frame, reference)?;
+    if p.is_empty() {
+        return Err(Error::invalid_condition("snapshot is empty".to_string()));
+    }
+    let n   = nalgebra::convert::<f64, T>(p.len() as f64);
+    let pc  = centroid(&p);
+    let qc  = centroid(&q);
+    let rot = kabsch_rotation(&p, &q);
+    let sum = p.iter().zip(q.iter()).fold(T::zero(), |acc, (pi, qi)| {
+        acc + (rot * (pi - pc) - (qi - qc)).norm_squared()
+    });
+    Ok((sum / n).sqrt())
+}
+
+/// Calculates the RMSD between two snapshots as they are, without
+/// superimposing them.
+///
+/// Fails if the numbers of particles differ, the snapshots are empty, or a
+/// snapshot does not have positions.
+pub fn rmsd_no_align<T, S, R>(frame: &S, reference: &R) -> Result<T>
+where
+    T: nalgebra::Real,
+    S: Snapshot<T> + ?Sized,
+    R: Snapshot<T> + ?Sized,
+    <S as std::ops::Index<usize>>::Output: Particle<T>,
+    <R as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    let (p, q) = paired_positions(frame, reference)?;
+    if p.is_empty() {
+        return Err(Error::invalid_condition("snapshot is empty".to_string()));
+    }
+    let n   = nalgebra::convert::<f64, T>(p.len() as f64);
+    let sum = p.iter().zip(q.iter())
+        .fold(T::zero(), |acc, (pi, qi)| acc + (pi - qi).norm_squared());
+    Ok((sum / n).sqrt())
+}
+
+/// Calculates the RMSD of each snapshot against `reference`, i.e. the
+/// "RMSD vs time" series. If `align` is true, each snapshot is superimposed
+/// onto the reference (see `rmsd`), otherwise not (see `rmsd_no_align`).
+///
+/// Fails if the number of particles in a snapshot differs from the reference
+/// or a snapshot does not have positions.
+pub fn rmsd_series<T, Tr, R>(traj: &Tr, reference: &R, align: bool)
+    -> Result<std::vec::Vec<T>>
+where
+    T: nalgebra::Real,
+    Tr: Trajectory<T>,
+    R: Snapshot<T> + ?Sized,
+    <R as std::ops::Index<usize>>::Output: Particle<T>,
+    <Tr as std::ops::Index<usize>>::Output: Snapshot<T>,
+    <<Tr as std::ops::Index<usize>>::Output as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    (0 .. traj.len()).map(|i| {
+        if align {rmsd(&traj[i], reference)} else {rmsd_no_align(&traj[i], reference)}
+    }).collect()
+}
+
+/// The result of `pca`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PcaResult<T: nalgebra::Scalar> {
+    /// the mean position of each particle, after alignment if it is done.
+    pub mean: std::vec::Vec<nalgebra::Vector3<T>>,
+    /// eigenvalues of the covariance matrix in descending order, i.e. the
+    /// variance along each mode.
+    pub eigenvalues: std::vec::Vec<T>,
+    /// normalized eigenvectors. The k-th column is the k-th mode, ordered as
+    /// `(x0, y0, z0, x1, y1, z1, ...)`. The signs are arbitrary.
+    pub eigenvectors: nalgebra::DMatrix<T>,
+    /// projections of the fluctuation in each frame onto each mode. The
+    /// element `(i, k)` is the projection of the i-th frame onto the k-th
+    /// mode, so the first columns are the essential subspace.
+    pub projections: nalgebra::DMatrix<T>,
+}
+
+/// Performs principal component analysis of atomic fluctuations, also known
+/// as essential dynamics.
+///
+/// The 3N x 3N covariance matrix of the displacements from the mean
+/// structure is diagonalized, and the modes are sorted by their variances.
+/// If `align` is true, the frames are first superimposed onto the first
+/// frame, and then onto the mean of the aligned frames by the Kabsch
+/// algorithm, so that the overall rotation and translation do not appear
+/// in the modes. The trajectory itself is not modified.
+///
+/// The covariance matrix has (3N)² elements, so it is intended for a few
+/// hundreds of particles, e.g. C-alpha atoms of a protein.
+///
+/// Fails if the trajectory is empty, the number of particles changes, or a
+/// snapshot does not have positions.
+pub fn pca<T>(traj: &VecTrajectory<XYZSnapshot<T>>, align: bool) -> Result<PcaResult<T>>
+where
+    T: nalgebra::Real
+{
+    if traj.snapshots.is_empty() {
+        return Err(Error::invalid_condition("trajectory is empty".to_string()));
+    }
+    let mut aligned;
+    let traj = if align {
+        aligned = traj.clone();
+        align_trajectory(&mut aligned, None, None)?;
+        let mut reference = aligned.snapshots[0].clone();
+        let mean = mean_structure(&frame_positions(&aligned)?);
+        for (particle, r) in reference.particles.iter_mut().zip(mean) {
+            particle.xyz = Coordinate::from_vector(CoordKind::Position, r);
+        }
+        align_trajectory(&mut aligned, Some(&reference), None)?;
+        &aligned
+    } else {
+        traj
+    };
+    let frames = frame_positions(traj)?;
+    let mean   = mean_structure(&frames);
+    let n_frames = nalgebra::convert::<f64, T>(frames.len() as f64);
+
+    let dim = 3 * mean.len();
+    let deviations: std::vec::Vec<nalgebra::DVector<T>> = frames.iter().map(|frame| {
+        nalgebra::DVector::from_fn(dim, |i, _| frame[i / 3][i % 3] - mean[i / 3][i % 3])
+    }).collect();
+    let mut covariance = nalgebra::DMatrix::zeros(dim, dim);
+    for d in deviations.iter() {
+        covariance += d * d.transpose();
+    }
+    covariance /= n_frames;
+
+    let eigen = covariance.symmetric_eigen();
+    let mut order: std::vec::Vec<usize> = (0 .. dim).collect();
+    order.sort_by(|&i, &j| eigen.eigenvalues[j].partial_cmp(&eigen.eigenvalues[i])
+        .unwrap_or(std::cmp::Ordering::Equal));
+
+    let eigenvalues  = order.iter().map(|&i| eigen.eigenvalues[i]).collect();
+    let eigenvectors = nalgebra::DMatrix::from_fn(dim, dim, |r, c| eigen.eigenvectors[(r, order[c])]);
+    let projections  = nalgebra::DMatrix::from_fn(deviations.len(), dim, |f, k| {
+        eigenvectors.column(k).dot(&deviations[f])
+    });
+    Ok(PcaResult{mean, eigenvalues, eigenvectors, projections})
+}
+
+// Collects positions of all the frames, which should have the same number of
+// particles as the first one.
+fn frame_positions<T>(traj: &VecTrajectory<XYZSnapshot<T>>)
+    -> Result<std::vec::Vec<std::vec::Vec<nalgebra::Vector3<T>>>>
+where
+    T: nalgebra::Real
+{
+    let first = &traj.snapshots[0];
+    traj.snapshots.iter().map(|snapshot| paired_positions(snapshot, first).map(|(p, _)| p)).collect()
+}
+
+// The mean position of each particle over the frames.
+fn mean_structure<T: nalgebra::Real>(frames: &[std::vec::Vec<nalgebra::Vector3<T>>])
+    -> std::vec::Vec<nalgebra::Vector3<T>>
+{
+    (0 .. frames[0].len()).map(|i| {
+        let positions: std::vec::Vec<_> = frames.iter().map(|frame| frame[i]).collect();
+        centroid(&positions)
+    }).collect()
+}
+
+// The mean of the positions, or zero if there are none.
+fn centroid<T: nalgebra::Real>(positions: &[nalgebra::Vector3<T>]) -> nalgebra::Vector3<T> {
+    let n = nalgebra::convert::<f64, T>(positions.len().max(1) as f64);
+    positions.iter().fold(nalgebra::Vector3::zeros(), |acc, r| acc + r) / n
+}
+
+// Collects positions of two snapshots that have the same number of particles.
+type PositionPair<T> = (std::vec::Vec<nalgebra::Vector3<T>>, std::vec::Vec<nalgebra::Vector3<T>>);
+
+fn paired_positions<T, S, R>(a: &S, b: &R) -> Result<PositionPair<T>>
+where
+    T: nalgebra::Real,
+    S: Snapshot<T> + ?Sized,
+    R: Snapshot<T> + ?Sized,
+    <S as std::ops::Index<usize>>::Output: Particle<T>,
+    <R as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    let p = a.positions().ok_or_else(|| Error::invalid_condition(
+        "snapshot does not contain positions".to_string()))?;
+    let q = b.positions().ok_or_else(|| Error::invalid_condition(
+        "snapshot does not contain positions".to_string()))?;
+    if p.len() != q.len() {
+        return Err(Error::invalid_condition(format!(
+            "number of particles differs: {} and {}", p.len(), q.len())));
+    }
+    Ok((p, q))
+}
+
+// The rotation matrix R that minimizes Σ|R (p_i - p_c) - (q_i - q_c)|².
+// `p` and `q` should have the same length.
+fn kabsch_rotation<T: nalgebra::Real>(p: &[nalgebra::Vector3<T>], q: &[nalgebra::Vector3<T>])
+    -> nalgebra::Matrix3<T>
+{
+    let pc = centroid(p);
+    let qc = centroid(q);
+
+    let mut h = nalgebra::Matrix3::zeros();
+    for (pi, qi) in p.iter().zip(q.iter()) {
+        h += (pi - pc) * (qi - qc).transpose();
+    }
+    let svd = h.svd(true, true);
+    let u   = svd.u.expect("U is computed");
+    let v   = svd.v_t.expect("V^T is computed").transpose();
+
+    // correct the reflection so that the result becomes a proper rotation
+    let d = if (v * u.transpose()).determinant() < T::zero() {-T::one()} else {T::one()};
+    let correction = nalgebra::Matrix3::from_diagonal(&nalgebra::Vector3::new(T::one(), T::one(), d));
+    v * correction * u.transpose()
+}
+
+/// Calculates the mean and its standard error by block averaging.
+///
+/// The series, e.g. radius of gyration of each frame, is divided into
+/// non-overlapping blocks of `block_size` values, and the standard error is
+/// estimated from the variance of the block means. For a correlated series,
+/// the error is underestimated unless the blocks are longer than the
+/// correlation time; see `block_average_sweep` to find the plateau. Values
+/// after the last full block are not used.
+///
+/// Fails if `block_size` is zero or the series has less than two blocks.
+///
+/// ```
+/// use trajan::analysis::block_average;
+/// let (mean, error) = block_average(&[1.0, 3.0, 2.0, 4.0], 2).unwrap();
+/// assert_eq!(mean, 2.5);
+/// assert_eq!(error, 0.5);
+/// ```
+pub fn block_average<T>(series: &[T], block_size: usize) -> Result<(T, T)>
+where
+    T: nalgebra::Real
+{
+    if block_size == 0 {
+        return Err(Error::invalid_condition("block size must be positive".to_string()));
+    }
+    let n_blocks = series.len() / block_size;
+    if n_blocks < 2 {
+        return Err(Error::invalid_condition(format!(
+            "{} values are too few for blocks of {}", series.len(), block_size)));
+    }
+    let size  = nalgebra::convert::<f64, T>(block_size as f64);
+    let means: std::vec::Vec<T> = series.chunks_exact(block_size)
+        .map(|block| block.iter().fold(T::zero(), |acc, x| acc + *x) / size)
+        .collect();
+    let n     = nalgebra::convert::<f64, T>(n_blocks as f64);
+    let mean  = means.iter().fold(T::zero(), |acc, x| acc + *x) / n;
+    let var   = means.iter().fold(T::zero(), |acc, x| acc + (*x - mean).powi(2)) / (n - T::one());
+    Ok((mean, (var / n).sqrt()))
+}
+
+/// Calculates the standard error by `block_average` for each block size from
+/// 1 to a half of the series, and returns pairs of `(block_size, error)`.
+///
+/// The error increases with the block size while the blocks are correlated,
+/// and reaches a plateau that is the estimate of the true error. It is empty
+/// if the series has less than two values.
+pub fn block_average_sweep<T>(series: &[T]) -> std::vec::Vec<(usize, T)>
+where
+    T: nalgebra::Real
+{
+    (1 ..= series.len() / 2).map(|size| {
+        let (_, error) = block_average(series, size).expect("at least two blocks");
+        (size, error)
+    }).collect()
+}
+
+/// A histogram with bins of equal width in [min, max).
+///
+/// Values out of the range are counted separately as underflow or overflow.
+/// Each bin is a half-open interval, so a value equal to `max` is an overflow.
+/// NaN is ignored.
+///
+/// ```
+/// use trajan::analysis::Histogram;
+/// let mut h = Histogram::new(0.0, 1.0, 4).unwrap();
+/// h.add(0.1);
+/// h.add(0.3);
+/// h.add(1.5);
+/// assert_eq!(h.counts(), &[1.0, 1.0, 0.0, 0.0]);
+/// assert_eq!(h.overflow(), 1.0);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram<T> {
+    min:       T,
+    max:       T,
+    counts:    std::vec::Vec<T>,
+    underflow: T,
+    overflow:  T,
+}
+
+impl<T: nalgebra::Real> Histogram<T> {
+    /// Constructs an empty histogram that has `n_bins` bins in [min, max).
+    /// Fails if `n_bins` is zero or `max` is not larger than `min`.
+    pub fn new(min: T, max: T, n_bins: usize) -> Result<Self> {
+        if n_bins == 0 {
+            return Err(Error::invalid_condition("histogram has no bins".to_string()));
+        }
+        if min.partial_cmp(&max) != Some(std::cmp::Ordering::Less) {
+            return Err(Error::invalid_condition(format!(
+                "histogram range is empty: [{}, {})", min, max)));
+        }
+        Ok(Histogram{min, max, counts: vec![T::zero(); n_bins],
+                     underflow: T::zero(), overflow: T::zero()})
+    }
+
+    /// Returns the width of a bin.
+    pub fn width(&self) -> T {
+        (self.max - self.min) / nalgebra::convert::<f64, T>(self.counts.len() as f64)
+    }
+
+    /// Counts a value.
+    pub fn add(&mut self, value: T) {
+        self.add_weighted(value, T::one())
+    }
+
+    /// Counts a value with a weight.
+    pub fn add_weighted(&mut self, value: T, weight: T) {
+        if value < self.min {
+            self.underflow += weight;
+        } else if value >= self.max {
+            self.overflow += weight;
+        } else if value >= self.min { // NaN fails all the comparisons
+            let idx = nalgebra::try_convert::<T, f64>((value - self.min) / self.width())
+                .map(|x| x as usize).unwrap_or(0);
+            // guards the case when value is just below max and rounded up.
+            let last = self.counts.len() - 1;
+            self.counts[idx.min(last)] += weight;
+        }
+    }
+
+    /// Returns the counts in each bin.
+    pub fn counts(&self) -> &[T] {
+        &self.counts
+    }
+
+    /// Returns the sum of weights below `min`.
+    pub fn underflow(&self) -> T {
+        self.underflow
+    }
+
+    /// Returns the sum of weights at or above `max`.
+    pub fn overflow(&self) -> T {
+        self.overflow
+    }
+
+    /// Returns the center of each bin.
+    pub fn bin_centers(&self) -> std::vec::Vec<T> {
+        let width = self.width();
+        let half  = width / nalgebra::convert::<f64, T>(2.0);
+        (0 .. self.counts.len()).map(|i| {
+            self.min + width * nalgebra::convert::<f64, T>(i as f64) + half
+        }).collect()
+    }
+
+    /// Returns the probability density in each bin, that is integrated to 1
+    /// over the range. Underflow and overflow are not taken into account.
+    /// If nothing is counted in the range, all the values are zero.
+    pub fn normalize(&self) -> std::vec::Vec<T> {
+        let total = self.counts.iter().fold(T::zero(), |acc, c| acc + *c);
+        if total == T::zero() {
+            return vec![T::zero(); self.counts.len()];
+        }
+        let width = self.width();
+        self.counts.iter().map(|c| *c / (total * width)).collect()
+    }
+}
+
+/// A trait to fold a quantity over snapshots one by one.
+///
+/// It enables to analyse a trajectory with constant memory while reading a
+/// file, e.g. by `XYZReader::accumulate`.
+pub trait Accumulator<T: nalgebra::Scalar> {
+    /// the result of the analysis.
+    type Output;
+
+    /// Takes a snapshot into account.
+    fn observe<S>(&mut self, snap: &S)
+    where
+        S: Snapshot<T>,
+        <S as std::ops::Index<usize>>::Output: Particle<T>;
+
+    /// Finishes the analysis and returns the result.
+    fn finalize(self) -> Self::Output;
+}
+
+/// Calculates the mean radius of gyration over snapshots.
+///
+/// Snapshots that do not have positions are ignored. The result is None if
+/// no snapshot is observed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeanRadiusOfGyration<T> {
+    sum:   T,
+    count: usize,
+}
+
+impl<T: nalgebra::Real> MeanRadiusOfGyration<T> {
+    /// Constructs an empty accumulator.
+    pub fn new() -> Self {
+        MeanRadiusOfGyration{sum: T::zero(), count: 0}
+    }
+}
+
+impl<T: nalgebra::Real> Default for MeanRadiusOfGyration<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: nalgebra::Real> Accumulator<T> for MeanRadiusOfGyration<T> {
+    type Output = Option<T>;
+
+    fn observe<S>(&mut self, snap: &S)
+    where
+        S: Snapshot<T>,
+        <S as std::ops::Index<usize>>::Output: Particle<T>,
+    {
+        if let Some(rg) = radius_of_gyration(snap) {
+            self.sum   += rg;
+            self.count += 1;
+        }
+    }
+
+    fn finalize(self) -> Self::Output {
+        if self.count == 0 {
+            return None;
+        }
+        Some(self.sum / nalgebra::convert::<f64, T>(self.count as f64))
+    }
+}
+
+/// Calculates the radial distribution function g(r) over snapshots.
+///
+/// Distances are calculated with the minimum image convention in the given
+/// box. The result is a list of pairs of the center of a bin and the value of
+/// g(r) at the bin.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RadialDistribution<T: nalgebra::Scalar> {
+    boundary:  SimulationBox<T>,
+    histogram: Histogram<T>,
+    norm:      T,
+}
+
+impl<T: nalgebra::Real> RadialDistribution<T> {
+    /// Constructs an empty accumulator with `n_bins` bins in [0, r_max).
+    /// Fails if `n_bins` is zero or `r_max` is not positive.
+    pub fn new(boundary: SimulationBox<T>, r_max: T, n_bins: usize) -> Result<Self> {
+        Ok(RadialDistribution{boundary, histogram: Histogram::new(T::zero(), r_max, n_bins)?,
+                              norm: T::zero()})
+    }
+}
+
+impl<T: nalgebra::Real> Accumulator<T> for RadialDistribution<T> {
+    type Output = std::vec::Vec<(T, T)>;
+
+    fn observe<S>(&mut self, snap: &S)
+    where
+        S: Snapshot<T>,
+        <S as std::ops::Index<usize>>::Output: Particle<T>,
+    {
+        let positions = match snap.positions() {
+            Some(ps) => ps,
+            None     => return,
+        };
+        let n = positions.len();
+        for i in 0 .. n {
+            for j in i+1 .. n {
+                let r = self.boundary.minimum_image(positions[j] - positions[i]).norm();
+                self.histogram.add(r);
+            }
+        }
+        // the number of ordered pairs, N(N-1), not N^2, so that g(r) of a
+        // small system also converges to 1.
+        let n = nalgebra::convert::<f64, T>(n as f64);
+        self.norm += n * (n - T::one()) / self.boundary.volume();
+    }
+
+    fn finalize(self) -> Self::Output {
+        let width = self.histogram.width();
+        let two   = nalgebra::convert::<f64, T>(2.0);
+        let four_thirds_pi = nalgebra::convert::<f64, T>(4.0 / 3.0) * T::pi();
+
+        self.histogram.bin_centers().into_iter()
+            .zip(self.histogram.counts().iter())
+            .map(|(center, count)| {
+                let lower = center - width / two;
+                let upper = center + width / two;
+                let shell = four_thirds_pi * (upper * upper * upper - lower * lower * lower);
+                let g = if self.norm == T::zero() {
+                    T::zero()
+                } else {
+                    two * *count / (self.norm * shell)
+                };
+                (center, g)
+            }).collect()
+    }
+}
+
+/// Calculates the static structure factor S(q) averaged over snapshots.
+///
+/// See `structure_factor` for the details. Snapshots that do not have
+/// positions are ignored. All the values are zero if no snapshot is observed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructureFactor<T: nalgebra::Scalar> {
+    boundary: SimulationBox<T>,
+    q_values: std::vec::Vec<T>,
+    sum:      std::vec::Vec<T>,
+    count:    usize,
+}
+
+impl<T: nalgebra::Real> StructureFactor<T> {
+    /// Constructs an empty accumulator.
+    pub fn new(boundary: SimulationBox<T>, q_values: std::vec::Vec<T>) -> Self {
+        let sum = vec![T::zero(); q_values.len()];
+        StructureFactor{boundary, q_values, sum, count: 0}
+    }
+}
+
+impl<T: nalgebra::Real> Accumulator<T> for StructureFactor<T> {
+    type Output = std::vec::Vec<(T, T)>;
+
+    fn observe<S>(&mut self, snap: &S)
+    where
+        S: Snapshot<T>,
+        <S as std::ops::Index<usize>>::Output: Particle<T>,
+    {
+        if let Some(sq) = structure_factor(snap, &self.boundary, &self.q_values) {
+            for (s, x) in self.sum.iter_mut().zip(sq) {
+                *s += x;
+            }
+            self.count += 1;
+        }
+    }
+
+    fn finalize(self) -> Self::Output {
+        let n = nalgebra::convert::<f64, T>(self.count.max(1) as f64);
+        self.q_values.into_iter().zip(self.sum)
+            .map(|(q, s)| (q, s / n)).collect()
+    }
+}
+
+/// Calculates the mean positions of each particle over snapshots.
+///
+/// The number of particles must be the same in all the snapshots; otherwise
+/// the result is an error. Superposition is not performed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeanStructure<T: nalgebra::Scalar> {
+    sum:   std::vec::Vec<nalgebra::Vector3<T>>,
+    count: usize,
+    error: std::option::Option<std::string::String>,
+}
+
+impl<T: nalgebra::Real> MeanStructure<T> {
+    /// Constructs an empty accumulator.
+    pub fn new() -> Self {
+        MeanStructure{sum: std::vec::Vec::new(), count: 0, error: None}
+    }
+}
+
+impl<T: nalgebra::Real> Default for MeanStructure<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: nalgebra::Real> Accumulator<T> for MeanStructure<T> {
+    type Output = Result<std::vec::Vec<nalgebra::Vector3<T>>>;
+
+    fn observe<S>(&mut self, snap: &S)
+    where
+        S: Snapshot<T>,
+        <S as std::ops::Index<usize>>::Output: Particle<T>,
+    {
+        if self.error.is_some() {
+            return;
+        }
+        let positions = match snap.positions() {
+            Some(ps) => ps,
+            None     => {
+                self.error = Some("snapshot does not contain positions".to_string());
+                return;
+            }
+        };
+        if self.count == 0 {
+            self.sum = positions;
+        } else if self.sum.len() != positions.len() {
+            self.error = Some(format!("number of particles changed from {} to {}",
+                                      self.sum.len(), positions.len()));
+            return;
+        } else {
+            for (s, r) in self.sum.iter_mut().zip(positions.iter()) {
+                *s += r;
+            }
+        }
+        self.count += 1;
+    }
+
+    fn finalize(self) -> Self::Output {
+        if let Some(error) = self.error {
+            return Err(Error::invalid_condition(error));
+        }
+        if self.count == 0 {
+            return Err(Error::invalid_condition("no snapshot observed".to_string()));
+        }
+        let n = nalgebra::convert::<f64, T>(self.count as f64);
+        Ok(self.sum.into_iter().map(|s| s / n).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{uniform, TestParticle, TestSnapshot};
+    use crate::trajectory::VecTrajectory;
+    use crate::xyz::XYZReader;
+
+    const CONTENTS: &[u8] = b"\
+        3
+        t = 1
+        H 0.0 0.0 0.0
+        H 1.0 0.0 0.0
+        H 0.0 1.0 0.0
+        3
+        t = 2
+        H 0.0 0.0 0.0
+        H 2.0 0.0 0.0
+        H 0.0 2.0 0.0
+        3
+        t = 3
+        H 0.0 0.0 1.0
+        H 3.0 0.0 1.0
+        H 0.0 3.0 1.0";
+
+    #[test]
+    fn rg_of_snapshot() {
+        let s = XYZSnapshot::<f64>::new("".to_string(), vec![
+            "H -1.0 0.0 0.0".parse().unwrap(),
+            "H  1.0 0.0 0.0".parse().unwrap(),
+        ]);
+        assert_eq!(center_of_mass(&s), Some(nalgebra::Vector3::new(0.0, 0.0, 0.0)));
+        assert_eq!(radius_of_gyration(&s), Some(1.0));
+
+        let empty = XYZSnapshot::<f64>::new("".to_string(), vec![]);
+        assert_eq!(radius_of_gyration(&empty), None);
+    }
+
+    #[test]
+    fn streaming_rg_matches_collected() {
+        let reader = XYZReader::new(CoordKind::Position, CONTENTS).f64();
+        let streamed = reader.accumulate(MeanRadiusOfGyration::new()).unwrap();
+
+        let reader = XYZReader::new(CoordKind::Position, CONTENTS).f64();
+        let rgs: std::vec::Vec<f64> = reader
+            .map(|s| radius_of_gyration(&s).unwrap()).collect();
+        let collected = rgs.iter().sum::<f64>() / rgs.len() as f64;
+
+        assert!((streamed - collected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn streaming_mean_structure() {
+        let reader = XYZReader::new(CoordKind::Position, CONTENTS).f64();
+        let mean = reader.accumulate(MeanStructure::new()).unwrap();
+        assert_eq!(mean.len(), 3);
+        assert!((mean[1] - nalgebra::Vector3::new(2.0, 0.0, 1.0 / 3.0)).norm() < 1e-12);
+    }
+
+    #[test]
+    fn diff_perturbed_trajectory() {
+        let reference = VecTrajectory::new(
+            XYZReader::new(CoordKind::Position, CONTENTS).f64().collect());
+        let diffs = diff_trajectories(&reference, &reference.clone(), 1e-6);
+        assert_eq!(diffs.len(), 3);
+        assert!(diffs.iter().all(|d| !d.is_different() && d.max_deviation == Some(0.0)));
+
+        let mut perturbed = reference.clone();
+        *perturbed.snapshots[1].particles[2].xyz.y_mut() += 0.01;
+        let diffs = diff_trajectories(&reference, &perturbed, 1e-6);
+        let different: std::vec::Vec<usize> = diffs.iter()
+            .filter(|d| d.is_different()).map(|d| d.frame).collect();
+        assert_eq!(different, vec![1]);
+        assert!((diffs[1].max_deviation.unwrap() - 0.01).abs() < 1e-12);
+        assert!(diff_trajectories(&reference, &perturbed, 0.1).iter().all(|d| !d.is_different()));
+
+        let mut renamed = reference.clone();
+        renamed.snapshots[2].particles[0].name = "O".to_string();
+        renamed.snapshots[2].particles.pop();
+        let diffs = diff_trajectories(&reference, &renamed, 1e-6);
+        assert!(diffs[2].count_mismatch && diffs[2].max_deviation.is_none());
+        assert!(diffs[2].name_mismatch);
+
+        // NaN is not hidden by the following finite deviations
+        let mut broken = reference.clone();
+        *broken.snapshots[0].particles[0].xyz.x_mut() = f64::NAN;
+        let diffs = diff_trajectories(&reference, &broken, 1e-6);
+        assert!(diffs[0].exceeds_tol && diffs[0].max_deviation.unwrap().is_nan());
+
+        let mut velocities = reference.clone();
+        let v = &mut velocities.snapshots[1].particles[1];
+        v.xyz = v.xyz.into_kind(CoordKind::Velocity);
+        let diffs = diff_trajectories(&reference, &velocities, 1e-6);
+        assert!(diffs[1].kind_mismatch && diffs[1].max_deviation.is_none());
+        assert!(diffs[1].is_different() && !diffs[0].kind_mismatch);
+
+        let mut truncated = reference.clone();
+        truncated.snapshots.pop();
+        let diffs = diff_trajectories(&truncated, &reference, 1e-6);
+        assert_eq!(diffs.len(), 3);
+        assert!(diffs[2].missing && !diffs[0].missing);
+    }
+
+    #[test]
+    fn resample_at_midpoints() {
+        let traj = VecTrajectory::new(
+            XYZReader::new(CoordKind::Position, CONTENTS).f64().collect());
+        let resampled = resample_by_time(&traj, &[0.0, 1.0, 3.0], 0.5).unwrap();
+        assert_eq!(resampled.snapshots.len(), 7);
+        // between the first and the second frames
+        let mid = &resampled.snapshots[1];
+        assert_eq!(mid.particles[1].xyz, Coordinate::Position{x: 1.5, y: 0.0, z: 0.0});
+        assert_eq!(mid.metadata("time"), Some(&Attribute::Float(0.5)));
+        assert_eq!(mid.parse_time(), Some(0.5));
+        // a quarter of the way from the second to the third
+        let quarter = &resampled.snapshots[3];
+        assert!((quarter.particles[2].xyz[1] - 2.25).abs() < 1e-12);
+        assert!((quarter.particles[2].xyz[2] - 0.25).abs() < 1e-12);
+        assert!(resampled.snapshots[6].approx_eq(&traj[2], 1e-12));
+
+        assert!(resample_by_time(&traj, &[0.0, 1.0], 0.5).is_err());
+        assert!(resample_by_time(&traj, &[0.0, 2.0, 1.0], 0.5).is_err());
+        assert!(resample_by_time(&traj, &[0.0, 1.0, 3.0], 0.0).is_err());
+        let mut ragged = traj.clone();
+        ragged.snapshots[1].particles.pop();
+        assert!(resample_by_time(&ragged, &[0.0, 1.0, 3.0], 0.5).is_err());
+    }
+
+    #[test]
+    fn right_angle_distribution() {
+        // a rigid L-shaped molecule that moves and rotates, and a straight one
+        let frame = |t: f64| {
+            let (c, s) = (t.cos(), t.sin());
+            XYZSnapshot::<f64>::new("".to_string(), vec![
+                XYZParticle::new("H".to_string(), Coordinate::Position{x: t + c, y: s, z: 0.0}),
+                XYZParticle::new("O".to_string(), Coordinate::Position{x: t, y: 0.0, z: 0.0}),
+                XYZParticle::new("H".to_string(), Coordinate::Position{x: t - s, y: c, z: 0.0}),
+                XYZParticle::new("C".to_string(), Coordinate::Position{x: t + 2.0 * c, y: 2.0 * s, z: 0.0}),
+            ])
+        };
+        let traj = VecTrajectory::new((0 .. 5).map(|i| frame(0.3 * i as f64)).collect());
+        let angles = bond_angles(&traj[1], &[(0, 1, 2), (3, 0, 1)], None).unwrap();
+        assert!((angles[0] - std::f64::consts::FRAC_PI_2).abs() < 1e-12);
+        assert!((angles[1] - std::f64::consts::PI).abs() < 1e-6);
+
+        let h = angle_distribution(&traj, &[(0, 1, 2)], None, 9).unwrap();
+        assert_eq!(h.counts()[4], 5.0);
+        assert_eq!(h.counts().iter().sum::<f64>(), 5.0);
+        let h = angle_distribution(&traj, &[(3, 0, 1)], None, 9).unwrap();
+        assert_eq!(h.counts()[8], 5.0);
+
+        assert!(angle_distribution(&traj, &[(0, 1, 4)], None, 9).is_err());
+    }
+
+    #[test]
+    fn max_force_of_each_frame() {
+        let traj = VecTrajectory::new(XYZReader::new(CoordKind::Force, CONTENTS).f64().collect());
+        assert_eq!(max_force_series(&traj).unwrap(), vec![1.0, 2.0, 10.0_f64.sqrt()]);
+        let traj = VecTrajectory::new(XYZReader::new(CoordKind::Position, CONTENTS).f64().collect());
+        assert!(max_force_series(&traj).is_err());
+    }
+
+    #[test]
+    fn volume_and_density_of_boxes() {
+        let mut snapshots: std::vec::Vec<XYZSnapshot<f64>> =
+            XYZReader::new(CoordKind::Position, CONTENTS).f64().take(2).collect();
+        snapshots[0].set_metadata("box",
+            Attribute::Matrix(nalgebra::Matrix3::from_diagonal(&nalgebra::Vector3::new(2.0, 3.0, 4.0))));
+        // triclinic: the volume is the determinant
+        snapshots[1].set_metadata("box",
+            Attribute::Matrix(nalgebra::Matrix3::new(2.0, 1.0, 0.0,
+                                                     0.0, 2.0, 1.0,
+                                                     0.0, 0.0, 2.0)));
+        let traj = VecTrajectory::new(snapshots.clone());
+        assert_eq!(volume_series(&traj).unwrap(), vec![24.0, 8.0]);
+        assert_eq!(density_series(&traj, 48.0).unwrap(), vec![2.0, 6.0]);
+
+        snapshots[1].metadata.clear();
+        let traj = VecTrajectory::new(snapshots);
+        assert!(volume_series(&traj).is_err());
+        assert!(density_series(&traj, 48.0).is_err());
+    }
+
+    #[test]
+    fn block_average_of_uncorrelated_series() {
+        let mut next = uniform(31415);
+        let series: std::vec::Vec<f64> = (0 .. 20000).map(|_| next()).collect();
+        let n    = series.len() as f64;
+        let mean = series.iter().sum::<f64>() / n;
+        let var  = series.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        let sem  = (var / n).sqrt();
+
+        let (m, e) = block_average(&series, 1).unwrap();
+        assert!((m - mean).abs() < 1e-12);
+        assert!((e - sem).abs() < 1e-12);
+        // blocks of uncorrelated values give the same error
+        let (m, e) = block_average(&series, 10).unwrap();
+        assert!((m - mean).abs() < 1e-12);
+        assert!((e / sem - 1.0).abs() < 0.15);
+
+        // each value repeated 10 times has the correlation time 10
+        let correlated: std::vec::Vec<f64> = series[.. 2000].iter()
+            .flat_map(|x| std::iter::repeat_n(*x, 10)).collect();
+        let sweep = block_average_sweep(&correlated[.. 1000]);
+        assert_eq!(sweep.len(), 500);
+        assert_eq!(sweep[0].0, 1);
+        let (_, short) = block_average(&correlated, 1).unwrap();
+        let (_, long)  = block_average(&correlated, 10).unwrap();
+        assert!((long / short - 10.0_f64.sqrt()).abs() < 0.3);
+
+        assert!(block_average(&series, 0).is_err());
+        assert!(block_average(&series[.. 3], 2).is_err());
+        assert!(block_average_sweep(&[1.0]).is_empty());
+    }
+
+    #[test]
+    fn velocities_from_linear_motion() {
+        let frame = |t: f64| XYZSnapshot::new("".to_string(), vec![
+            XYZParticle::new("A".to_string(), Coordinate::Position{x: 1.0 + 2.0 * t, y: 0.5 * t, z: 3.0}),
+            XYZParticle::new("B".to_string(), Coordinate::Position{x: 9.5 + 3.0 * t, y: 0.0, z: 0.0}),
+        ]);
+        let v = finite_difference_velocities(&frame(0.0), &frame(0.2), 0.1, None).unwrap();
+        assert_eq!(v.which(), Some(CoordKind::Velocity));
+        assert_eq!(v.particles[0].name, "A");
+        let vs = v.velocities().unwrap();
+        assert!((vs[0] - nalgebra::Vector3::new(2.0, 0.5, 0.0)).norm() < 1e-12);
+        assert!((vs[1] - nalgebra::Vector3::new(3.0, 0.0, 0.0)).norm() < 1e-12);
+
+        // B crosses the boundary at x = 10 and is wrapped into 0.1.
+        let mut wrapped = frame(0.2);
+        wrapped.particles[1].xyz = Coordinate::Position{x: 0.1, y: 0.0, z: 0.0};
+        let b = SimulationBox::cuboid(nalgebra::Vector3::new(10.0, 10.0, 10.0)).unwrap();
+        let v = finite_difference_velocities(&frame(0.0), &wrapped, 0.1, Some(&b)).unwrap();
+        let vs = v.velocities().unwrap();
+        assert!((vs[1] - nalgebra::Vector3::new(3.0, 0.0, 0.0)).norm() < 1e-12);
+
+        let fewer = XYZSnapshot::new("".to_string(), vec![frame(0.2).particles[0].clone()]);
+        assert!(finite_difference_velocities(&frame(0.0), &fewer, 0.1, None).is_err());
+    }
+
+    #[test]
+    fn structure_factor_of_simple_cubic() {
+        let mut particles = std::vec::Vec::new();
+        for i in 0 .. 4 {
+            for j in 0 .. 4 {
+                for k in 0 .. 4 {
+                    particles.push(format!("A {} {} {}", i, j, k).parse().unwrap());
+                }
+            }
+        }
+        let s = XYZSnapshot::<f64>::new("".to_string(), particles);
+        let b = SimulationBox::cuboid(nalgebra::Vector3::new(4.0, 4.0, 4.0)).unwrap();
+
+        // q = 2π/L sqrt(m). The Bragg peak of the lattice appears at
+        // q = 2π/a = 2π/L sqrt(16).
+        let dq = 2.0 * std::f64::consts::PI / 4.0;
+        let q_values: std::vec::Vec<f64> = (1 ..= 16).map(|m| dq * (m as f64).sqrt()).collect();
+        let sq = structure_factor(&s, &b, &q_values).unwrap();
+
+        assert!((sq[15] - 64.0).abs() < 1e-8);
+        assert!(sq[0 .. 15].iter().all(|x| x.abs() < 1e-8));
+
+        let mut acc = StructureFactor::new(b, q_values);
+        acc.observe(&s);
+        acc.observe(&s);
+        let averaged = acc.finalize();
+        assert!((averaged[15].1 - 64.0).abs() < 1e-8);
+    }
+
+    // four particles with no symmetry, so that a rotation is determined uniquely.
+    fn asymmetric_reference() -> XYZSnapshot<f64> {
+        XYZSnapshot::new("".to_string(), vec![
+            XYZParticle::new("A".to_string(), Coordinate::Position{x: 0.0, y: 0.0, z: 0.0}),
+            XYZParticle::new("B".to_string(), Coordinate::Position{x: 1.0, y: 0.0, z: 0.0}),
+            XYZParticle::new("C".to_string(), Coordinate::Position{x: 0.0, y: 2.0, z: 0.0}),
+            XYZParticle::new("D".to_string(), Coordinate::Position{x: 0.0, y: 0.0, z: 3.0}),
+        ])
+    }
+
+    #[test]
+    fn recover_known_rotation() {
+        let reference = asymmetric_reference();
+        let rot = nalgebra::UnitQuaternion::from_axis_angle(
+            &nalgebra::Vector3::y_axis(), 0.3) *
+            nalgebra::UnitQuaternion::from_axis_angle(&nalgebra::Vector3::x_axis(), 1.2);
+        let shift = nalgebra::Vector3::new(5.0, -1.0, 2.0);
+
+        let mut rotated = XYZSnapshot::new("".to_string(), reference.particles.clone());
+        rotated.apply_transform(|r| *r = rot * *r + shift);
+
+        let found = orientation_quaternion(&reference, &rotated).unwrap();
+        assert!(found.angle_to(&rot) < 1e-10);
+
+        let traj = VecTrajectory::new(vec![reference.clone(), rotated]);
+        let series = orientation_series(&traj).unwrap();
+        assert_eq!(series.len(), 2);
+        assert!(series[0].angle() < 1e-10);
+        assert!(series[1].angle_to(&rot) < 1e-10);
+
+        let fewer = XYZSnapshot::new("".to_string(), reference.particles[..3].to_vec());
+        assert!(orientation_quaternion(&reference, &fewer).is_err());
+    }
+
+    #[test]
+    fn rotating_end_to_end_vector() {
+        let omega = 0.1;
+        let traj: VecTrajectory<XYZSnapshot<f64>> = (0 .. 20).map(|t| {
+            let theta = omega * t as f64;
+            XYZSnapshot::new("".to_string(), vec![
+                XYZParticle::new("A".to_string(), Coordinate::Position{x: 1.0, y: 1.0, z: 0.0}),
+                XYZParticle::new("B".to_string(), Coordinate::Position{x: 5.0, y: 5.0, z: 5.0}),
+                XYZParticle::new("C".to_string(), Coordinate::Position{
+                    x: 1.0 + 3.0 * theta.cos(), y: 1.0 + 3.0 * theta.sin(), z: 0.0}),
+            ])
+        }).collect();
+
+        let acf = end_to_end_autocorrelation(&traj, 0, 2).unwrap();
+        assert_eq!(acf.len(), 20);
+        for (lag, c) in acf.iter().enumerate() {
+            assert!((c - (omega * lag as f64).cos()).abs() < 1e-12);
+        }
+        assert!(end_to_end_autocorrelation(&traj, 0, 3).is_err());
+        assert!(end_to_end_autocorrelation(&traj, 0, 0).is_err());
+    }
+
+    #[test]
+    fn count_first_shell() {
+        // an ion at the center of an octahedron of waters, and a far one
+        let snap = XYZSnapshot::<f64>::new("".to_string(), vec![
+            "Na 5.0 5.0 5.0".parse().unwrap(),
+            "O 7.4 5.0 5.0".parse().unwrap(), "O 2.6 5.0 5.0".parse().unwrap(),
+            "O 5.0 7.4 5.0".parse().unwrap(), "O 5.0 2.6 5.0".parse().unwrap(),
+            "O 5.0 5.0 7.4".parse().unwrap(), "O 5.0 5.0 2.6".parse().unwrap(),
+            "O 9.9 9.9 9.9".parse().unwrap(),
+        ]);
+        let oxygens: std::vec::Vec<usize> = (1 .. 8).collect();
+        assert_eq!(coordination_number(&snap, None, &[0], &oxygens, 3.0).unwrap(), vec![6]);
+
+        // the far one is close to the ion across the boundary
+        let b = SimulationBox::cuboid(nalgebra::Vector3::new(10.0, 10.0, 10.0)).unwrap();
+        assert_eq!(coordination_number(&snap, Some(&b), &[0, 7], &oxygens, 3.0).unwrap(),
+                   vec![6, 0]);
+        let b = SimulationBox::cuboid(nalgebra::Vector3::new(5.0, 5.0, 5.0)).unwrap();
+        let counts = coordination_number(&snap, Some(&b), &[0], &oxygens, 1.0).unwrap();
+        assert_eq!(counts, vec![1]);
+
+        let traj = VecTrajectory::new(vec![snap.clone(), snap.clone()]);
+        let mean: f64 = mean_coordination_number(&traj, None, &[0, 7], &oxygens, 3.0).unwrap();
+        assert_eq!(mean, 3.0);
+        assert!(coordination_number(&snap, None, &[8], &oxygens, 3.0).is_err());
+    }
+
+    #[test]
+    fn two_separated_clusters() {
+        let snap = XYZSnapshot::<f64>::new("".to_string(), vec![
+            "C 1.0 1.0 1.0".parse().unwrap(),
+            "C 6.0 6.0 6.0".parse().unwrap(),
+            "C 2.0 1.0 1.0".parse().unwrap(),
+            "C 6.0 7.0 6.0".parse().unwrap(),
+            "C 3.0 1.0 1.0".parse().unwrap(),
+            "C 9.5 1.0 1.0".parse().unwrap(),
+        ]);
+        assert_eq!(clusters(&snap, None, 1.5).unwrap(),
+                   vec![vec![0, 2, 4], vec![1, 3], vec![5]]);
+        assert_eq!(largest_cluster_size(&snap, None, 1.5).unwrap(), 3);
+
+        // the last one joins the first cluster across the boundary
+        let b = SimulationBox::cuboid(nalgebra::Vector3::new(10.0, 10.0, 10.0)).unwrap();
+        assert_eq!(clusters(&snap, Some(&b), 1.6).unwrap(),
+                   vec![vec![0, 2, 4, 5], vec![1, 3]]);
+        assert_eq!(largest_cluster_size(&snap, Some(&b), 1.6).unwrap(), 4);
+
+        let empty = XYZSnapshot::<f64>::new("".to_string(), vec![]);
+        assert_eq!(largest_cluster_size(&empty, None, 1.5).unwrap(), 0);
+    }
+
+    #[test]
+    fn dense_and_sparse_regions() {
+        // a dense cube on the left and sparse particles on the right. the
+        // first one is close to the last ones across the boundary.
+        let mut particles = std::vec::Vec::new();
+        for i in 0 .. 27 {
+            let (x, y, z) = ((i % 3) as f64, ((i / 3) % 3) as f64, (i / 9) as f64);
+            particles.push(XYZParticle::new("A".to_string(),
+                Coordinate::Position{x: 0.5 * x + 0.1, y: 0.5 * y + 5.0, z: 0.5 * z + 5.0}));
+        }
+        for x in &[4.0, 6.5, 9.0] {
+            particles.push(XYZParticle::new("B".to_string(),
+                Coordinate::Position{x: *x, y: 5.0, z: 5.0}));
+        }
+        let snap = XYZSnapshot::new("".to_string(), particles);
+        let b = SimulationBox::cuboid(nalgebra::Vector3::new(10.0, 10.0, 10.0)).unwrap();
+
+        let rho = local_density(&snap, Some(&b), 1.2).unwrap();
+        assert_eq!(rho.len(), 30);
+        assert!(rho[.. 27].iter().all(|d| rho[27 .. 29].iter().all(|s| s < d)));
+        let sphere = 4.0 / 3.0 * std::f64::consts::PI * 1.2_f64.powi(3);
+        assert_eq!(rho[27], 0.0);
+        assert!((rho[29] * sphere - 1.0).abs() < 1e-12);
+        assert!(local_density(&snap, None, 1.2).unwrap()[29] == 0.0);
+    }
+
+    #[test]
+    fn align_rotated_frames() {
+        let reference = asymmetric_reference();
+        let frames: std::vec::Vec<_> = (0 .. 4).map(|i| {
+            let rot = nalgebra::UnitQuaternion::from_axis_angle(
+                &nalgebra::Vector3::x_axis(), 0.4 * i as f64);
+            let shift = nalgebra::Vector3::new(i as f64, -2.0 * i as f64, 0.5);
+            let mut frame = reference.clone();
+            frame.apply_transform(|r| *r = rot * *r + shift);
+            frame
+        }).collect();
+
+        let mut traj = VecTrajectory::new(frames.clone());
+        align_trajectory(&mut traj, Some(&reference), None).unwrap();
+        for frame in traj.snapshots.iter() {
+            let d: f64 = rmsd_no_align(frame, &reference).unwrap();
+            assert!(d < 1e-10);
+        }
+
+        // fit only on A, B and C, then D follows the rigid motion
+        let mut traj = VecTrajectory::new(frames.clone());
+        align_trajectory(&mut traj, None, Some(&[0, 1, 2])).unwrap();
+        for frame in traj.snapshots.iter() {
+            let d: f64 = rmsd_no_align(frame, &traj.snapshots[0]).unwrap();
+            assert!(d < 1e-10);
+        }
+
+        let mut traj = VecTrajectory::new(frames);
+        assert!(align_trajectory(&mut traj, None, Some(&[0, 4])).is_err());
+        assert!(align_trajectory(&mut traj, None, Some(&[])).is_err());
+        let fewer = XYZSnapshot::new("".to_string(), reference.particles[.. 3].to_vec());
+        assert!(align_trajectory(&mut traj, Some(&fewer), None).is_err());
+    }
+
+    #[test]
+    fn pca_finds_dominant_mode() {
+        let mut next  = uniform(2718);
+        let mut noise = move || 0.01 * (next() - 0.5);
+        let reference = [
+            nalgebra::Vector3::new(0.0, 0.0, 0.0),
+            nalgebra::Vector3::new(1.5, 0.0, 0.0),
+            nalgebra::Vector3::new(0.0, 1.5, 0.0),
+            nalgebra::Vector3::new(0.0, 0.0, 1.5),
+        ];
+        // the first particle oscillates along x, others fluctuate slightly
+        let amplitudes: std::vec::Vec<f64> = (0 .. 50).map(|i| (0.3 * i as f64).sin()).collect();
+        let frames = amplitudes.iter().map(|a| {
+            XYZSnapshot::new("".to_string(), reference.iter().enumerate().map(|(i, r)| {
+                let mut r = r + nalgebra::Vector3::new(noise(), noise(), noise());
+                if i == 0 {
+                    r.x += a;
+                }
+                XYZParticle::new("CA".to_string(), Coordinate::Position{x: r.x, y: r.y, z: r.z})
+            }).collect())
+        }).collect();
+        let traj = VecTrajectory::new(frames);
+
+        let result = pca(&traj, false).unwrap();
+        assert_eq!(result.eigenvalues.len(), 12);
+        assert_eq!(result.eigenvectors.shape(), (12, 12));
+        assert_eq!(result.projections.shape(), (50, 12));
+        assert!(result.eigenvalues.windows(2).all(|w| w[0] >= w[1]));
+        assert!(result.eigenvalues[0] > 1000.0 * result.eigenvalues[1]);
+        assert!(result.eigenvectors[(0, 0)].abs() > 0.999);
+
+        let mean = amplitudes.iter().sum::<f64>() / 50.0;
+        let var  = amplitudes.iter().map(|a| (a - mean).powi(2)).sum::<f64>() / 50.0;
+        assert!((result.eigenvalues[0] - var).abs() < 1e-2 * var);
+        for (f, a) in amplitudes.iter().enumerate() {
+            assert!((result.projections[(f, 0)].abs() - (a - mean).abs()).abs() < 1e-2);
+        }
+
+        // the mode remains dominant after removing the rigid-body motion
+        let result = pca(&traj, true).unwrap();
+        assert!(result.eigenvalues[0] > 50.0 * result.eigenvalues[1]);
+
+        let mut uneven = traj.clone();
+        uneven.snapshots[1].particles.pop();
+        assert!(pca(&uneven, false).is_err());
+        assert!(pca(&VecTrajectory::<XYZSnapshot<f64>>::new(vec![]), false).is_err());
+    }
+
+    #[test]
+    fn rmsd_against_reference() {
+        let reference = asymmetric_reference();
+        let rot = nalgebra::UnitQuaternion::from_axis_angle(&nalgebra::Vector3::z_axis(), 0.7);
+        let mut rotated = reference.clone();
+        rotated.apply_transform(|r| *r = rot * *r + nalgebra::Vector3::new(1.0, 2.0, 3.0));
+        let mut drifted = reference.clone();
+        drifted.particles[3].xyz[2] += 2.0;
+
+        let traj = VecTrajectory::new(vec![reference.clone(), rotated, drifted]);
+        let aligned: Vec<f64> = rmsd_series(&traj, &reference, true).unwrap();
+        assert!(aligned[0].abs() < 1e-10);
+        assert!(aligned[1].abs() < 1e-10);
+        assert!(aligned[2] > 0.1);
+
+        let raw: Vec<f64> = rmsd_series(&traj, &reference, false).unwrap();
+        assert_eq!(raw[0], 0.0);
+        assert!(raw[1] > 1.0);
+        assert!((raw[2] - 1.0).abs() < 1e-12); // sqrt(2² / 4)
+        assert!(aligned[2] <= raw[2]);
+
+        let fewer = XYZSnapshot::new("".to_string(), reference.particles[..3].to_vec());
+        assert!(rmsd_series(&traj, &fewer, true).is_err());
+        assert!(rmsd_no_align(&fewer, &reference).is_err());
+    }
+
+    #[test]
+    fn center_of_each_residue() {
+        // atoms read from PDB, that have residue information
+        let atom = |resid, resname, mass, x, y, z| TestParticle{
+            mass: Some(mass), pos: Some(nalgebra::Vector3::new(x, y, z)),
+            chain: Some("A"), resid: Some(resid), resname: Some(resname),
+            ..Default::default()
+        };
+        let snap = TestSnapshot(vec![
+            atom(1, "HOH", 16.0, 0.0, 0.0, 0.0),
+            atom(1, "HOH",  1.0, 1.0, 0.0, 0.0),
+            atom(1, "HOH",  1.0, 0.0, 1.0, 0.0),
+            atom(2, "NA",  23.0, 5.0, 5.0, 5.0),
+        ]);
+        let residues = group_by_residue(&snap).unwrap();
+        assert_eq!(residues.len(), 2);
+        assert_eq!(residues[0].0, ResidueId{chain: "A".to_string(), resid: 1,
+                                            resname: "HOH".to_string()});
+        assert!((residues[0].1 - nalgebra::Vector3::new(1.0 / 18.0, 1.0 / 18.0, 0.0)).norm() < 1e-12);
+        assert_eq!(residues[1].0.resname, "NA");
+        assert_eq!(residues[1].1, nalgebra::Vector3::new(5.0, 5.0, 5.0));
+
+        // xyz snapshots do not have residues
+        let xyz = XYZSnapshot::<f64>::new("".to_string(), vec!["H 0.0 0.0 0.0".parse().unwrap()]);
+        assert_eq!(group_by_residue(&xyz), None);
+    }
+
+    #[test]
+    fn convex_hull_of_cube() {
+        let mut particles = std::vec::Vec::new();
+        for &x in [0.0, 1.0].iter() {
+            for &y in [0.0, 1.0].iter() {
+                for &z in [0.0, 1.0].iter() {
+                    particles.push(XYZParticle::new("C".to_string(),
+                                                    Coordinate::Position{x, y, z}));
+                }
+            }
+        }
+        // points inside the cube and on its faces do not change the volume
+        particles.push(XYZParticle::new("H".to_string(), Coordinate::Position{x: 0.5, y: 0.5, z: 0.5}));
+        particles.push(XYZParticle::new("H".to_string(), Coordinate::Position{x: 0.5, y: 0.5, z: 1.0}));
+        particles.push(XYZParticle::new("H".to_string(), Coordinate::Position{x: 0.2, y: 0.9, z: 0.1}));
+        let cube = XYZSnapshot::new("".to_string(), particles);
+        let volume: f64 = convex_hull_volume(&cube).unwrap();
+        assert!((volume - 1.0).abs() < 1e-12);
+
+        // a point outside adds a pyramid of volume 1/3 * 1 * 0.5
+        let mut roofed = cube.clone();
+        roofed.particles.push(XYZParticle::new("O".to_string(),
+                                               Coordinate::Position{x: 0.5, y: 0.5, z: 1.5}));
+        let volume: f64 = convex_hull_volume(&roofed).unwrap();
+        assert!((volume - (1.0 + 0.5 / 3.0)).abs() < 1e-12);
+
+        let flat = XYZSnapshot::<f64>::new("".to_string(), vec![
+            "H 0.0 0.0 0.0".parse().unwrap(), "H 1.0 0.0 0.0".parse().unwrap(),
+            "H 0.0 1.0 0.0".parse().unwrap(), "H 1.0 1.0 0.0".parse().unwrap(),
+        ]);
+        assert_eq!(convex_hull_volume(&flat), None);
+        let few = XYZSnapshot::<f64>::new("".to_string(), flat.particles[.. 3].to_vec());
+        assert_eq!(convex_hull_volume(&few), None);
+    }
+
+    #[test]
+    fn dipole_of_ion_pair() {
+        let pair = XYZSnapshot::new("".to_string(), vec![
+            XYZParticle::new("Na".to_string(), Coordinate::Position{x: 1.0, y: 2.0, z: 3.0})
+                .with_charge(1.0),
+            XYZParticle::new("Cl".to_string(), Coordinate::Position{x: 1.0, y: 2.0, z: 0.5})
+                .with_charge(-1.0),
+        ]);
+        let mu: nalgebra::Vector3<f64> = dipole_moment(&pair).unwrap();
+        assert!((mu - nalgebra::Vector3::new(0.0, 0.0, 2.5)).norm() < 1e-12);
+
+        // a neutral system does not depend on the origin
+        let shifted = dipole_moment_about(&pair, &nalgebra::Vector3::new(5.0, 5.0, 5.0)).unwrap();
+        assert!((shifted - mu).norm() < 1e-12);
+
+        let mut ion = pair.clone();
+        ion.particles.truncate(1);
+        let about = dipole_moment_about(&ion, &nalgebra::Vector3::new(1.0, 2.0, 3.0)).unwrap();
+        assert!(about.norm() < 1e-12);
+
+        let mut uncharged = pair.clone();
+        uncharged.particles[1].charge = None;
+        assert_eq!(dipole_moment(&uncharged), None);
+    }
+
+    #[test]
+    fn nematic_order_parameter() {
+        let snap = XYZSnapshot::<f64>::new("".to_string(), vec![
+            "C 0.0 0.0 0.0".parse().unwrap(), "C 0.0 0.0 2.0".parse().unwrap(),
+            "C 1.0 1.0 0.0".parse().unwrap(), "C 1.0 1.0 -1.5".parse().unwrap(),
+            "C 2.0 0.0 3.0".parse().unwrap(), "C 2.0 0.0 4.0".parse().unwrap(),
+        ]);
+        let aligned = bond_directors(&snap, &[(0, 1), (2, 3), (4, 5)], None).unwrap();
+        let (s, director) = nematic_order(&aligned).unwrap();
+        assert!((s - 1.0).abs() < 1e-12);
+        assert!((director.z.abs() - 1.0).abs() < 1e-12);
+        assert_eq!(bond_directors(&snap, &[(0, 6)], None), None);
+
+        // isotropic directions
+        let mut next = uniform(2718);
+        let random: std::vec::Vec<_> = (0 .. 5000).map(|_| {
+            let z   = 2.0 * next() - 1.0;
+            let phi = 2.0 * std::f64::consts::PI * next();
+            let r   = (1.0 - z * z).sqrt();
+            nalgebra::Vector3::new(r * phi.cos(), r * phi.sin(), z)
+        }).collect();
+        let (s, _) = nematic_order(&random).unwrap();
+        assert!(s.abs() < 0.05);
+
+        assert_eq!(nematic_order::<f64>(&[]), None);
+    }
+
+    #[test]
+    fn shape_of_snapshot() {
+        // vertices of an octahedron
+        let sphere = XYZSnapshot::<f64>::new("".to_string(), vec![
+            "H  1.0 0.0 0.0".parse().unwrap(), "H -1.0 0.0 0.0".parse().unwrap(),
+            "H 0.0  1.0 0.0".parse().unwrap(), "H 0.0 -1.0 0.0".parse().unwrap(),
+            "H 0.0 0.0  1.0".parse().unwrap(), "H 0.0 0.0 -1.0".parse().unwrap(),
+        ]);
+        let tensor = gyration_tensor(&sphere).unwrap();
+        assert!((tensor.trace() - radius_of_gyration(&sphere).unwrap().powi(2)).abs() < 1e-12);
+        let shape = shape_descriptors(&sphere).unwrap();
+        assert!(shape.asphericity.abs() < 1e-12);
+        assert!(shape.acylindricity.abs() < 1e-12);
+        assert!(shape.relative_anisotropy.abs() < 1e-12);
+
+        let rod = XYZSnapshot::<f64>::new("".to_string(), vec![
+            "H 0.0 0.0 0.0".parse().unwrap(),
+            "H 1.0 1.0 1.0".parse().unwrap(),
+            "H 2.0 2.0 2.0".parse().unwrap(),
+            "H 3.0 3.0 3.0".parse().unwrap(),
+        ]);
+        let shape = shape_descriptors(&rod).unwrap();
+        assert!(shape.eigenvalues[0].abs() < 1e-12);
+        assert!(shape.eigenvalues[1].abs() < 1e-12);
+        assert!((shape.eigenvalues[2] - 3.75).abs() < 1e-12);
+        assert!((shape.relative_anisotropy - 1.0).abs() < 1e-12);
+
+        let empty = XYZSnapshot::<f64>::new("".to_string(), vec![]);
+        assert_eq!(shape_descriptors(&empty), None);
+    }
+
+    #[test]
+    fn kinetic_energy_of_particle() {
+        let snap = TestSnapshot(vec![TestParticle{
+            mass: Some(2.0), vel: Some(nalgebra::Vector3::new(1.0, 2.0, 2.0)), ..Default::default()
+        }]);
+        assert!((kinetic_energy(&snap).unwrap() - 9.0).abs() < 1e-12);
+        // 2 * 9 / (3 * 0.5)
+        assert!((temperature(&snap, 0.5, 0, false).unwrap() - 12.0).abs() < 1e-12);
+
+        assert_eq!(degrees_of_freedom(10, 0, false), 30);
+        assert_eq!(degrees_of_freedom(10, 0, true), 27);
+        assert_eq!(degrees_of_freedom(10, 5, true), 22);
+        assert_eq!(degrees_of_freedom(1, 0, true), 0);
+        assert!((temperature(&snap, 0.5, 1, false).unwrap() - 18.0).abs() < 1e-12);
+        assert_eq!(temperature(&snap, 0.5, 0, true), None);
+        assert_eq!(temperature(&snap, 0.5, 3, false), None);
+
+        // XYZSnapshot does not have masses
+        let reader = XYZReader::new(CoordKind::Velocity, CONTENTS).f64();
+        let snapshots: VecTrajectory<_> = reader.collect();
+        assert!(kinetic_energy(&snapshots[0]).is_none());
+    }
+
+    #[test]
+    fn remove_center_of_mass() {
+        let reader = XYZReader::new(CoordKind::Position, CONTENTS).f64();
+        let mut traj: VecTrajectory<_> = reader.collect();
+        remove_com_motion(&mut traj, ComReference::EachFrame).unwrap();
+        for snapshot in traj.snapshots.iter() {
+            assert!(center_of_mass(snapshot).unwrap().norm() < 1e-12);
+        }
+
+        let reader = XYZReader::new(CoordKind::Position, CONTENTS).f64();
+        let original: VecTrajectory<_> = reader.collect();
+        let mut traj = original.clone();
+        remove_com_motion(&mut traj, ComReference::FirstFrame).unwrap();
+        let shift = center_of_mass(&original.snapshots[0]).unwrap();
+        for (s, o) in traj.snapshots.iter().zip(original.snapshots.iter()) {
+            let d = center_of_mass(o).unwrap() - center_of_mass(s).unwrap();
+            assert!((d - shift).norm() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn msd_requires_fixed_count() {
+        let reader = XYZReader::new(CoordKind::Position, CONTENTS).f64();
+        let traj: VecTrajectory<_> = reader.collect();
+        let msd = mean_square_displacement(&traj).unwrap();
+        assert_eq!(msd[0], 0.0);
+        assert!((msd[2] - (1.0 + 5.0 + 5.0) / 3.0).abs() < 1e-12);
+
+        let mut variable = traj.clone();
+        variable.snapshots[1].particles.push("H 5.0 5.0 5.0".parse().unwrap());
+        assert!(mean_square_displacement(&variable).is_err());
+
+        // per-snapshot analyses still work.
+        assert!(variable.snapshots.iter().all(|s| radius_of_gyration(s).is_some()));
+    }
+
+    #[test]
+    fn bond_length_of_pair() {
+        let s = XYZSnapshot::<f64>::new("".to_string(), vec![
+            "C 0.5 1.0 1.0".parse().unwrap(),
+            "C 9.0 1.0 1.0".parse().unwrap(),
+            "C 2.0 1.0 1.0".parse().unwrap(),
+        ]);
+        let b = SimulationBox::cuboid(nalgebra::Vector3::new(10.0, 10.0, 10.0)).unwrap();
+
+        assert_eq!(bond_lengths(&s, &[(0, 1), (0, 2)], None).unwrap(), vec![8.5, 1.5]);
+
+        let pbc = bond_lengths(&s, &[(0, 1), (0, 2)], Some(&b)).unwrap();
+        assert!((pbc[0] - 1.5).abs() < 1e-12);
+        assert!((pbc[1] - 1.5).abs() < 1e-12);
+
+        assert!(bond_lengths(&s, &[(0, 3)], None).is_err());
+
+        let h = bond_length_histogram(&s, &[(0, 1), (0, 2)], None, 0.0, 2.0, 2).unwrap();
+        assert_eq!(h.counts(), &[0.0, 1.0]);
+        assert_eq!(h.overflow(), 1.0);
+    }
+
+    #[test]
+    fn find_overlapping_pair() {
+        let snap = XYZSnapshot::new("".to_string(), vec![
+            XYZParticle::new("A".to_string(), Coordinate::Position{x: 0.5, y: 5.0, z: 5.0}),
+            XYZParticle::new("B".to_string(), Coordinate::Position{x: 5.0, y: 5.0, z: 5.0}),
+            XYZParticle::new("C".to_string(), Coordinate::Position{x: 9.8, y: 5.0, z: 5.0}),
+            XYZParticle::new("D".to_string(), Coordinate::Position{x: 5.0, y: 7.0, z: 5.0}),
+        ]);
+        let b = SimulationBox::cuboid(nalgebra::Vector3::new(10.0, 10.0, 10.0)).unwrap();
+        let clashes = find_clashes(&snap, Some(&b), 1.0f64).unwrap();
+        assert_eq!(clashes.len(), 1);
+        assert_eq!((clashes[0].0, clashes[0].1), (0, 2));
+        assert!((clashes[0].2 - 0.7).abs() < 1e-12);
+
+        assert!(find_clashes(&snap, None, 1.0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn histogram_bins() {
+        assert!(Histogram::new(-1.0, 1.0, 0).is_err());
+        assert!(Histogram::new(1.0, 1.0, 4).is_err());
+        assert!(Histogram::new(1.0, -1.0, 4).is_err());
+        assert!(Histogram::new(f64::NAN, 1.0, 4).is_err());
+
+        let mut h = Histogram::new(-1.0, 1.0, 4).unwrap();
+        assert_eq!(h.width(), 0.5);
+        assert_eq!(h.bin_centers(), vec![-0.75, -0.25, 0.25, 0.75]);
+
+        h.add(-1.0);  // lower edge belongs to the first bin
+        h.add(-0.75); // center
+        h.add(-0.5);  // edge between the first and the second bin
+        h.add(0.25);
+        h.add_weighted(0.99, 2.0);
+        h.add(1.0);   // upper edge is an overflow
+        h.add(-1.5);
+        h.add(f64::NAN);
+
+        assert_eq!(h.counts(), &[2.0, 1.0, 1.0, 2.0]);
+        assert_eq!(h.underflow(), 1.0);
+        assert_eq!(h.overflow(),  1.0);
+
+        let density = h.normalize();
+        let integral: f64 = density.iter().map(|d| d * h.width()).sum();
+        assert!((integral - 1.0).abs() < 1e-12);
+        assert_eq!(density[0], 2.0 / (6.0 * 0.5));
+    }
+
+    #[test]
+    fn rdf_of_ideal_lattice() {
+        let mut particles = std::vec::Vec::new();
+        for i in 0 .. 4 {
+            for j in 0 .. 4 {
+                for k in 0 .. 4 {
+                    particles.push(format!("A {} {} {}", i, j, k).parse().unwrap());
+                }
+            }
+        }
+        let s = XYZSnapshot::<f64>::new("".to_string(), particles);
+        let b = SimulationBox::cuboid(nalgebra::Vector3::new(4.0, 4.0, 4.0)).unwrap();
+
+        let mut rdf = RadialDistribution::new(b, 2.0, 8).unwrap();
+        rdf.observe(&s);
+        let g = rdf.finalize();
+
+        // no pair is closer than the lattice constant, 1.0.
+        assert!(g[0..4].iter().all(|x| x.1 == 0.0));
+        assert_eq!(g[4].0, 1.125);
+        assert!(g[4].1 > 0.0);
+    }
+
+    #[test]
+    fn rdf_of_small_ideal_gas() {
+        let mut random = uniform(1618);
+        let mut next = move || 10.0 * random();
+        let b = SimulationBox::cuboid(nalgebra::Vector3::new(10.0, 10.0, 10.0)).unwrap();
+        let mut rdf = RadialDistribution::new(b, 4.5, 3).unwrap();
+        // with only 3 particles, normalizing by N^2 would give g(r) = 2/3
+        for _ in 0 .. 20000 {
+            let s = XYZSnapshot::<f64>::new("".to_string(), (0 .. 3).map(|_| {
+                XYZParticle::new("A".to_string(), Coordinate::Position{x: next(), y: next(), z: next()})
+            }).collect());
+            rdf.observe(&s);
+        }
+        let g = rdf.finalize();
+        assert!((g[1].1 - 1.0).abs() < 0.05);
+        assert!((g[2].1 - 1.0).abs() < 0.05);
+    }
+}
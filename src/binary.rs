@@ -0,0 +1,157 @@
+//! Helpers to read binary trajectory formats.
+//!
+//! Binary formats like DCD, TRR and XTC may be written in either byte order
+//! and in either single or double precision, depending on the machine and
+//! the software that wrote them. The readers of those formats share the
+//! functions in this module to handle the byte order and precision.
+use crate::error::{Error, Result};
+
+/// Byte order of a binary file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+impl Endian {
+    /// Returns the byte order of the machine running this.
+    pub fn native() -> Self {
+        if cfg!(target_endian = "little") {Endian::Little} else {Endian::Big}
+    }
+}
+
+/// Precision of floating point values in a binary file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precision {
+    Single,
+    Double,
+}
+
+impl Precision {
+    /// Returns the number of bytes of a value.
+    pub fn size(self) -> usize {
+        match self {
+            Precision::Single => 4,
+            Precision::Double => 8,
+        }
+    }
+
+    /// Finds the precision from the size of a block that contains `count`
+    /// floating point values. Returns None if it matches neither.
+    pub fn detect(block_size: usize, count: usize) -> Option<Self> {
+        if block_size == count * 4 {
+            Some(Precision::Single)
+        } else if block_size == count * 8 {
+            Some(Precision::Double)
+        } else {
+            None
+        }
+    }
+}
+
+/// Finds the byte order by comparing a record-length marker with the
+/// expected value.
+///
+/// Fortran unformatted files (e.g. DCD) put the length of a record before and
+/// after it. If the length of the first record is known, the byte order can
+/// be found from the marker. Returns None if it matches neither.
+///
+/// ```
+/// use trajan::binary::{detect_endian_from_marker, Endian};
+/// assert_eq!(detect_endian_from_marker([84, 0, 0, 0], 84), Some(Endian::Little));
+/// assert_eq!(detect_endian_from_marker([0, 0, 0, 84], 84), Some(Endian::Big));
+/// assert_eq!(detect_endian_from_marker([1, 2, 3, 4], 84), None);
+/// ```
+pub fn detect_endian_from_marker(marker: [u8; 4], expected: i32) -> Option<Endian> {
+    if i32::from_le_bytes(marker) == expected {
+        Some(Endian::Little)
+    } else if i32::from_be_bytes(marker) == expected {
+        Some(Endian::Big)
+    } else {
+        None
+    }
+}
+
+/// Reads a 4-byte signed integer in the byte order.
+pub fn read_i32_endian<R: std::io::Read>(reader: &mut R, endian: Endian) -> Result<i32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(match endian {
+        Endian::Little => i32::from_le_bytes(buf),
+        Endian::Big    => i32::from_be_bytes(buf),
+    })
+}
+
+/// Reads a 4-byte floating point value in the byte order.
+pub fn read_f32_endian<R: std::io::Read>(reader: &mut R, endian: Endian) -> Result<f32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(match endian {
+        Endian::Little => f32::from_le_bytes(buf),
+        Endian::Big    => f32::from_be_bytes(buf),
+    })
+}
+
+/// Reads an 8-byte floating point value in the byte order.
+pub fn read_f64_endian<R: std::io::Read>(reader: &mut R, endian: Endian) -> Result<f64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(match endian {
+        Endian::Little => f64::from_le_bytes(buf),
+        Endian::Big    => f64::from_be_bytes(buf),
+    })
+}
+
+/// Reads a floating point value in the byte order and precision as f64.
+pub fn read_real_endian<R: std::io::Read>(reader: &mut R, endian: Endian,
+                                          precision: Precision) -> Result<f64> {
+    match precision {
+        Precision::Single => Ok(f64::from(read_f32_endian(reader, endian)?)),
+        Precision::Double => read_f64_endian(reader, endian),
+    }
+}
+
+/// Reads a non-negative 4-byte integer as a size. Fails if it is negative.
+pub fn read_size_endian<R: std::io::Read>(reader: &mut R, endian: Endian) -> Result<usize> {
+    let n = read_i32_endian(reader, endian)?;
+    if n < 0 {
+        return Err(Error::invalid_format(format!("negative size: {}", n)));
+    }
+    Ok(n as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_both_endians() {
+        let le: &[u8] = &[0x78, 0x56, 0x34, 0x12, 0x00, 0x00, 0xc0, 0x3f];
+        let be: &[u8] = &[0x12, 0x34, 0x56, 0x78, 0x3f, 0xc0, 0x00, 0x00];
+        let mut le = le;
+        let mut be = be;
+        assert_eq!(read_i32_endian(&mut le, Endian::Little).unwrap(), 0x12345678);
+        assert_eq!(read_i32_endian(&mut be, Endian::Big).unwrap(),    0x12345678);
+        assert_eq!(read_f32_endian(&mut le, Endian::Little).unwrap(), 1.5);
+        assert_eq!(read_f32_endian(&mut be, Endian::Big).unwrap(),    1.5);
+
+        // no bytes remain
+        assert!(read_i32_endian(&mut le, Endian::Little).is_err());
+    }
+
+    #[test]
+    fn read_double() {
+        let bytes = (-2.25f64).to_le_bytes();
+        assert_eq!(read_f64_endian(&mut &bytes[..], Endian::Little).unwrap(), -2.25);
+        let bytes = (-2.25f64).to_be_bytes();
+        assert_eq!(read_f64_endian(&mut &bytes[..], Endian::Big).unwrap(), -2.25);
+        assert_eq!(read_real_endian(&mut &bytes[..], Endian::Big, Precision::Double).unwrap(), -2.25);
+    }
+
+    #[test]
+    fn detect_precision() {
+        assert_eq!(Precision::detect(12, 3), Some(Precision::Single));
+        assert_eq!(Precision::detect(24, 3), Some(Precision::Double));
+        assert_eq!(Precision::detect(20, 3), None);
+    }
+}
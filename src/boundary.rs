@@ -0,0 +1,339 @@
+//! Handles periodic boundary conditions.
+//!
+//! Most of the MD simulations are performed under the periodic boundary
+//! condition. The particles that go out of the box come back from the other
+//! side, so the coordinates written in a file are often wrapped into the box.
+//! `SimulationBox` represents the periodic cell by its three lattice vectors,
+//! so both orthorhombic and triclinic cells are handled in the same way.
+use crate::error::{Error, Result};
+use crate::coordinate::Coordinate;
+use crate::snapshot::Snapshot;
+use crate::trajectory::VecTrajectory;
+use crate::xyz::XYZSnapshot;
+
+/// A periodic simulation cell spanned by three lattice vectors.
+///
+/// ```
+/// use trajan::boundary::SimulationBox;
+/// let b = SimulationBox::cuboid(nalgebra::Vector3::new(10.0, 10.0, 10.0)).unwrap();
+/// let d = b.minimum_image(nalgebra::Vector3::new(9.0, 0.0, 0.0));
+/// assert!((d - nalgebra::Vector3::new(-1.0, 0.0, 0.0)).norm() < 1e-12);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulationBox<T: nalgebra::Scalar> {
+    lattice: nalgebra::Matrix3<T>,
+    inverse: nalgebra::Matrix3<T>,
+}
+
+impl<T: nalgebra::Real> SimulationBox<T> {
+    /// Constructs a box from lattice vectors. Fails if they are linearly
+    /// dependent, i.e. the box has no volume.
+    pub fn new(a: nalgebra::Vector3<T>,
+               b: nalgebra::Vector3<T>,
+               c: nalgebra::Vector3<T>) -> Result<Self> {
+        let lattice = nalgebra::Matrix3::from_columns(&[a, b, c]);
+        let inverse = lattice.try_inverse().ok_or_else(|| Error::invalid_condition(
+            format!("lattice vectors are linearly dependent: {:?}", lattice)
+        ))?;
+        Ok(SimulationBox{lattice, inverse})
+    }
+
+    /// Constructs an orthorhombic box from the lengths along x, y, and z.
+    pub fn cuboid(lengths: nalgebra::Vector3<T>) -> Result<Self> {
+        let zero = T::zero();
+        Self::new(nalgebra::Vector3::new(lengths.x, zero, zero),
+                  nalgebra::Vector3::new(zero, lengths.y, zero),
+                  nalgebra::Vector3::new(zero, zero, lengths.z))
+    }
+
+    /// Constructs a box from the lengths of lattice vectors (a, b, c) and the
+    /// angles between them (alpha, beta, gamma) in degrees, as written in
+    /// many file formats. `a` is placed along x and `b` in the xy plane.
+    pub fn from_lengths_angles(lengths: nalgebra::Vector3<T>,
+                               angles: nalgebra::Vector3<T>) -> Result<Self> {
+        let to_rad = T::pi() / nalgebra::convert::<f64, T>(180.0);
+        let (cos_a, cos_b) = ((angles.x * to_rad).cos(), (angles.y * to_rad).cos());
+        let (sin_g, cos_g) = (angles.z * to_rad).sin_cos();
+
+        let zero = T::zero();
+        let cx = cos_b;
+        let cy = (cos_a - cos_b * cos_g) / sin_g;
+        let cz = (T::one() - cx * cx - cy * cy).max(zero).sqrt();
+        Self::new(nalgebra::Vector3::new(lengths.x, zero, zero),
+                  nalgebra::Vector3::new(lengths.y * cos_g, lengths.y * sin_g, zero),
+                  nalgebra::Vector3::new(lengths.z * cx, lengths.z * cy, lengths.z * cz))
+    }
+
+    /// Returns the lattice matrix. Each column is a lattice vector.
+    pub fn lattice(&self) -> &nalgebra::Matrix3<T> {
+        &self.lattice
+    }
+
+    /// Returns the lengths of the three lattice vectors.
+    pub fn lengths(&self) -> nalgebra::Vector3<T> {
+        nalgebra::Vector3::new(self.lattice.column(0).norm(),
+                               self.lattice.column(1).norm(),
+                               self.lattice.column(2).norm())
+    }
+
+    /// Returns the reciprocal lattice matrix including the factor 2π. Each
+    /// column is a reciprocal lattice vector, so that `a_i · b_j = 2π δ_ij`.
+    pub fn reciprocal(&self) -> nalgebra::Matrix3<T> {
+        self.inverse.transpose() * T::two_pi()
+    }
+
+    /// Returns the volume of the box.
+    pub fn volume(&self) -> T {
+        self.lattice.determinant().abs()
+    }
+
+    /// Converts a cartesian coordinate into the fractional coordinate, in the
+    /// unit of the lattice vectors.
+    pub fn cartesian_to_fractional(&self, r: &nalgebra::Vector3<T>) -> nalgebra::Vector3<T> {
+        self.inverse * r
+    }
+
+    /// Converts a fractional coordinate into the cartesian coordinate.
+    pub fn fractional_to_cartesian(&self, frac: &nalgebra::Vector3<T>) -> nalgebra::Vector3<T> {
+        self.lattice * frac
+    }
+
+    /// Returns the distances between the opposite faces of the box. They are
+    /// the same as the lengths for an orthorhombic box.
+    pub fn widths(&self) -> nalgebra::Vector3<T> {
+        nalgebra::Vector3::new(T::one() / self.inverse.row(0).norm(),
+                               T::one() / self.inverse.row(1).norm(),
+                               T::one() / self.inverse.row(2).norm())
+    }
+
+    /// Returns the center of the box, assuming the box spans from the origin.
+    pub fn center(&self) -> nalgebra::Vector3<T> {
+        let half = nalgebra::convert::<f64, T>(0.5);
+        self.lattice * nalgebra::Vector3::new(half, half, half)
+    }
+
+    /// Returns the image of `r` inside the box spanning from the origin.
+    pub fn wrap(&self, r: nalgebra::Vector3<T>) -> nalgebra::Vector3<T> {
+        let frac = self.inverse * r;
+        self.lattice * frac.map(|x| x - x.floor())
+    }
+
+    /// Returns the displacement vector that is the nearest image of `dr`.
+    ///
+    /// For a strongly skewed triclinic box, this is the image found by
+    /// rounding the fractional coordinate, that is not always the shortest.
+    pub fn minimum_image(&self, dr: nalgebra::Vector3<T>) -> nalgebra::Vector3<T> {
+        dr - self.lattice * self.image_shift(dr)
+    }
+
+    // The number of lattice vectors to subtract from `dr` to get the minimum
+    // image, in fractional coordinates. It is non-zero if `dr` is longer than
+    // a half of the box along any lattice vector.
+    fn image_shift(&self, dr: nalgebra::Vector3<T>) -> nalgebra::Vector3<T> {
+        (self.inverse * dr).map(|x| x.round())
+    }
+}
+
+/// Unwraps positions in the trajectory so that each particle moves
+/// continuously.
+///
+/// Frame by frame, lattice vectors are added to each particle so that the
+/// displacement between consecutive frames becomes the nearest image. It is
+/// needed to compute quantities like MSD from a wrapped trajectory.
+///
+/// The box is assumed to be constant through the trajectory, so a trajectory
+/// sampled under varying box (e.g. NPT) is not supported. Also, the frames
+/// should be sampled frequently enough so that no particle moves more than
+/// a half of the box between consecutive frames.
+///
+/// Fails if a snapshot does not contain positions or the number of particles
+/// changes between frames.
+pub fn unwrap_trajectory<T>(traj: &mut VecTrajectory<XYZSnapshot<T>>,
+                            boundary: &SimulationBox<T>) -> Result<()>
+where
+    T: nalgebra::Real
+{
+    let mut snapshots = traj.snapshots.iter_mut();
+    let mut prev_wrapped = match snapshots.next() {
+        Some(first) => positions_of(first)?,
+        None        => return Ok(()),
+    };
+    let mut prev_unwrapped = prev_wrapped.clone();
+
+    for snapshot in snapshots {
+        let wrapped = positions_of(snapshot)?;
+        if wrapped.len() != prev_wrapped.len() {
+            return Err(Error::invalid_condition(format!(
+                "number of particles changed from {} to {} while unwrapping",
+                prev_wrapped.len(), wrapped.len())));
+        }
+        for (i, particle) in snapshot.particles.iter_mut().enumerate() {
+            let dr = wrapped[i] - prev_wrapped[i];
+            let unwrapped = prev_unwrapped[i] + dr - boundary.lattice * boundary.image_shift(dr);
+            particle.xyz = Coordinate::from_vector(particle.xyz.which(), unwrapped);
+            prev_unwrapped[i] = unwrapped;
+        }
+        prev_wrapped = wrapped;
+    }
+    Ok(())
+}
+
+/// Finds the particles that seem to be wrapped into the box between frames.
+///
+/// Returns `(frame, particle)` pairs, where the particle moves more than a
+/// half of the box along any lattice vector from the previous frame to the
+/// `frame`. An empty result means that the trajectory is likely unwrapped
+/// already, or sampled too sparsely. `unwrap_trajectory` corrects exactly
+/// these jumps.
+///
+/// Fails if a snapshot does not contain positions or the number of particles
+/// changes between frames.
+pub fn detect_boundary_crossings<T>(traj: &VecTrajectory<XYZSnapshot<T>>,
+                                    boundary: &SimulationBox<T>)
+    -> Result<std::vec::Vec<(usize, usize)>>
+where
+    T: nalgebra::Real
+{
+    let mut crossings = std::vec::Vec::new();
+    let mut prev: std::option::Option<std::vec::Vec<nalgebra::Vector3<T>>> = None;
+    for (frame, snapshot) in traj.snapshots.iter().enumerate() {
+        let positions = positions_of(snapshot)?;
+        if let Some(prev) = prev {
+            if positions.len() != prev.len() {
+                return Err(Error::invalid_condition(format!(
+                    "number of particles changed from {} to {} at frame {}",
+                    prev.len(), positions.len(), frame)));
+            }
+            for (i, (r0, r1)) in prev.iter().zip(positions.iter()).enumerate() {
+                if boundary.image_shift(r1 - r0) != nalgebra::Vector3::zeros() {
+                    crossings.push((frame, i));
+                }
+            }
+        }
+        prev = Some(positions);
+    }
+    Ok(crossings)
+}
+
+fn positions_of<T: nalgebra::Real>(snapshot: &XYZSnapshot<T>)
+    -> Result<std::vec::Vec<nalgebra::Vector3<T>>>
+{
+    snapshot.positions().ok_or_else(|| Error::invalid_condition(
+        "snapshot does not contain positions".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xyz::XYZParticle;
+
+    #[test]
+    fn minimum_image_cuboid() {
+        let b = SimulationBox::cuboid(nalgebra::Vector3::new(10.0, 20.0, 30.0)).unwrap();
+        assert_eq!(b.lengths(), nalgebra::Vector3::new(10.0, 20.0, 30.0));
+
+        let d = b.minimum_image(nalgebra::Vector3::new(6.0, -11.0, 14.0));
+        assert!((d - nalgebra::Vector3::new(-4.0, 9.0, 14.0)).norm() < 1e-12);
+    }
+
+    #[test]
+    fn reciprocal_lattice() {
+        let b = SimulationBox::new(nalgebra::Vector3::new(2.0, 0.0, 0.0),
+                                   nalgebra::Vector3::new(1.0, 3.0, 0.0),
+                                   nalgebra::Vector3::new(0.0, 1.0, 4.0)).unwrap();
+        let product = b.lattice().transpose() * b.reciprocal();
+        let expected = nalgebra::Matrix3::identity() * 2.0 * std::f64::consts::PI;
+        assert!((product - expected).norm() < 1e-12);
+        assert!((b.volume() - 24.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn box_from_lengths_and_angles() {
+        let b = SimulationBox::from_lengths_angles(nalgebra::Vector3::new(2.0, 3.0, 4.0),
+                                                   nalgebra::Vector3::new(90.0, 90.0, 90.0)).unwrap();
+        let expected = nalgebra::Matrix3::from_diagonal(&nalgebra::Vector3::new(2.0, 3.0, 4.0));
+        assert!((b.lattice() - expected).norm() < 1e-12);
+
+        let b = SimulationBox::from_lengths_angles(nalgebra::Vector3::new(2.0f64, 3.0, 4.0),
+                                                   nalgebra::Vector3::new(70.0, 80.0, 60.0)).unwrap();
+        assert!((b.lengths() - nalgebra::Vector3::new(2.0, 3.0, 4.0)).norm() < 1e-12);
+        let l = b.lattice();
+        let angle = |i: usize, j: usize| -> f64 {
+            (l.column(i).dot(&l.column(j)) / (l.column(i).norm() * l.column(j).norm()))
+                .acos().to_degrees()
+        };
+        assert!((angle(1, 2) - 70.0).abs() < 1e-10);
+        assert!((angle(0, 2) - 80.0).abs() < 1e-10);
+        assert!((angle(0, 1) - 60.0).abs() < 1e-10);
+    }
+
+    #[test]
+    fn fractional_coordinate() {
+        let b = SimulationBox::new(nalgebra::Vector3::new(2.0, 0.0, 0.0),
+                                   nalgebra::Vector3::new(1.0, 3.0, 0.0),
+                                   nalgebra::Vector3::new(0.0, 0.0, 4.0)).unwrap();
+        let r = nalgebra::Vector3::new(1.5, 1.5, 1.0);
+        let f = b.cartesian_to_fractional(&r);
+        assert!((f - nalgebra::Vector3::new(0.5, 0.5, 0.25)).norm() < 1e-12);
+        assert!((b.fractional_to_cartesian(&f) - r).norm() < 1e-12);
+
+        let f = nalgebra::Vector3::new(0.1, 0.7, 0.4);
+        let r = b.fractional_to_cartesian(&f);
+        assert!((r - nalgebra::Vector3::new(0.9, 2.1, 1.6)).norm() < 1e-12);
+        assert!((b.cartesian_to_fractional(&r) - f).norm() < 1e-12);
+
+        // the distance between the planes spanned by b and c
+        let w = b.widths();
+        assert!((w - nalgebra::Vector3::new(6.0 / 10.0f64.sqrt(), 3.0, 4.0)).norm() < 1e-12);
+    }
+
+    #[test]
+    fn wrap_into_box() {
+        let b = SimulationBox::cuboid(nalgebra::Vector3::new(10.0, 20.0, 30.0)).unwrap();
+        assert_eq!(b.center(), nalgebra::Vector3::new(5.0, 10.0, 15.0));
+        let r = b.wrap(nalgebra::Vector3::new(-1.0, 45.0, 15.0));
+        assert!((r - nalgebra::Vector3::new(9.0, 5.0, 15.0)).norm() < 1e-12);
+    }
+
+    #[test]
+    fn singular_box() {
+        let zero = nalgebra::Vector3::new(0.0, 0.0, 0.0);
+        assert!(SimulationBox::new(zero, zero, zero).is_err());
+    }
+
+    #[test]
+    fn unwrap_across_boundary() {
+        let frame = |x: f64| XYZSnapshot::new("".to_string(), vec![
+            XYZParticle::new("A".to_string(), Coordinate::Position{x, y: 5.0, z: 5.0}),
+        ]);
+        let mut traj = VecTrajectory::new(vec![frame(9.0), frame(9.8), frame(0.6), frame(1.4)]);
+        let b = SimulationBox::cuboid(nalgebra::Vector3::new(10.0, 10.0, 10.0)).unwrap();
+
+        unwrap_trajectory(&mut traj, &b).unwrap();
+
+        let xs: std::vec::Vec<f64> = traj.snapshots.iter()
+            .map(|s| *s.particles[0].xyz.x()).collect();
+        assert!((xs[0] -  9.0).abs() < 1e-12);
+        assert!((xs[1] -  9.8).abs() < 1e-12);
+        assert!((xs[2] - 10.6).abs() < 1e-12);
+        assert!((xs[3] - 11.4).abs() < 1e-12);
+    }
+
+    #[test]
+    fn detect_wrapped_particle() {
+        let frame = |x: f64, y: f64| XYZSnapshot::new("".to_string(), vec![
+            XYZParticle::new("A".to_string(), Coordinate::Position{x, y: 5.0, z: 5.0}),
+            XYZParticle::new("B".to_string(), Coordinate::Position{x: 5.0, y, z: 5.0}),
+        ]);
+        let mut traj = VecTrajectory::new(vec![frame(9.0, 1.0), frame(9.8, 4.0),
+                                               frame(0.6, 5.5), frame(1.4, 2.0)]);
+        let b = SimulationBox::cuboid(nalgebra::Vector3::new(10.0, 10.0, 10.0)).unwrap();
+
+        assert_eq!(detect_boundary_crossings(&traj, &b).unwrap(), vec![(2, 0)]);
+        unwrap_trajectory(&mut traj, &b).unwrap();
+        assert!(detect_boundary_crossings(&traj, &b).unwrap().is_empty());
+
+        traj.snapshots[3].particles.pop();
+        assert!(detect_boundary_crossings(&traj, &b).is_err());
+    }
+}
@@ -9,7 +9,7 @@
 //! Some files have a signature that represents the kind of coordinate stored.
 //! For them, this information is needed to be changed dynamically, not
 //! statically. So it is implemented by using enums, not a tuple-struct.
-
+use crate::error::{Error, Result};
 
 /// An enum to represent which kind of vector the data means.
 ///
@@ -31,6 +31,14 @@
 /// let p = Coordinate::<f64>::build(CoordKind::Position, 1.0, 2.0, 3.0);
 /// let v: nalgebra::Vector3<f64> = Into::into(p);
 /// ```
+///
+/// And it can be constructed from nalgebra::Vector3 with a `CoordKind`.
+/// ```
+/// use trajan::coordinate::*;
+/// let v = nalgebra::Vector3::new(1.0, 2.0, 3.0);
+/// let p = Coordinate::from_vector(CoordKind::Position, v);
+/// assert_eq!(p, Coordinate::Position{x: 1.0, y: 2.0, z: 3.0});
+/// ```
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Coordinate<T> {
     Position{x:T, y:T, z:T},
@@ -53,9 +61,35 @@ impl<T> Coordinate<T> {
     /// convert (x, y, z) into `Coordinate` with a value of `CoordKind`.
     pub fn build(kind: CoordKind, x: T, y: T, z: T) -> Self {
         match kind {
-            CoordKind::Position => Coordinate::Position{x: x, y: y, z: z},
-            CoordKind::Velocity => Coordinate::Velocity{x: x, y: y, z: z},
-            CoordKind::Force    => Coordinate::Force{x: x, y: y, z: z},
+            CoordKind::Position => Coordinate::Position{x, y, z},
+            CoordKind::Velocity => Coordinate::Velocity{x, y, z},
+            CoordKind::Force    => Coordinate::Force{x, y, z},
+        }
+    }
+
+    /// convert nalgebra::Vector3 into `Coordinate` with a value of `CoordKind`.
+    pub fn from_vector(kind: CoordKind, v: nalgebra::Vector3<T>) -> Self
+    where
+        T: nalgebra::Scalar
+    {
+        Coordinate::build(kind, v.x, v.y, v.z)
+    }
+
+    /// re-tag the coordinate as `kind` without changing the values.
+    pub fn into_kind(self, kind: CoordKind) -> Self {
+        match self {
+            Coordinate::Position{x, y, z} |
+            Coordinate::Velocity{x, y, z} |
+            Coordinate::Force{x, y, z}    => Coordinate::build(kind, x, y, z),
+        }
+    }
+
+    /// converts each component by `f`, keeping the kind.
+    pub fn map<U, F: Fn(T) -> U>(self, f: F) -> Coordinate<U> {
+        match self {
+            Coordinate::Position{x, y, z} => Coordinate::Position{x: f(x), y: f(y), z: f(z)},
+            Coordinate::Velocity{x, y, z} => Coordinate::Velocity{x: f(x), y: f(y), z: f(z)},
+            Coordinate::Force{x, y, z}    => Coordinate::Force{x: f(x), y: f(y), z: f(z)},
         }
     }
 
@@ -95,7 +129,7 @@ impl<T> Coordinate<T> {
     }
 
     /// borrow mutable x value regardless of the kind.
-    pub fn x_mut<'a>(&'a mut self) -> &'a mut T {
+    pub fn x_mut(&mut self) -> &mut T {
         match self {
             Coordinate::Position{x, ..} => x,
             Coordinate::Velocity{x, ..} => x,
@@ -103,7 +137,7 @@ impl<T> Coordinate<T> {
         }
     }
     /// borrow mutable y value regardless of the kind.
-    pub fn y_mut<'a>(&'a mut self) -> &'a mut T {
+    pub fn y_mut(&mut self) -> &mut T {
         match self {
             Coordinate::Position{y, ..} => y,
             Coordinate::Velocity{y, ..} => y,
@@ -111,13 +145,103 @@ impl<T> Coordinate<T> {
         }
     }
     /// borrow mutable z value regardless of the kind.
-    pub fn z_mut<'a>(&'a mut self) -> &'a mut T {
+    pub fn z_mut(&mut self) -> &mut T {
         match self {
             Coordinate::Position{z, ..} => z,
             Coordinate::Velocity{z, ..} => z,
             Coordinate::Force{z, ..}    => z,
         }
     }
+
+    /// iterate over x, y and z in the same order as `Index`.
+    ///
+    /// ```
+    /// use trajan::coordinate::*;
+    /// let v = Coordinate::build(CoordKind::Velocity, 1.0, 2.0, 2.0);
+    /// let sq: f64 = v.iter().map(|x| x * x).sum();
+    /// assert_eq!(sq, 9.0);
+    /// ```
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        IntoIterator::into_iter([self.x(), self.y(), self.z()])
+    }
+    /// iterate over mutable x, y and z in the same order as `Index`.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        match self {
+            Coordinate::Position{x, y, z} |
+            Coordinate::Velocity{x, y, z} |
+            Coordinate::Force{x, y, z}    => IntoIterator::into_iter([x, y, z]),
+        }
+    }
+}
+
+/// Vector operations regardless of the kind.
+///
+/// ```
+/// use trajan::coordinate::*;
+/// let x = Coordinate::build(CoordKind::Position, 1.0, 0.0, 0.0);
+/// let y = Coordinate::build(CoordKind::Position, 0.0, 1.0, 0.0);
+/// assert_eq!(x.dot(&y), 0.0);
+/// assert_eq!(x.cross(&y), Coordinate::Position{x: 0.0, y: 0.0, z: 1.0});
+/// ```
+impl<T: nalgebra::Real> Coordinate<T> {
+    /// dot product of two coordinates.
+    pub fn dot(&self, other: &Coordinate<T>) -> T {
+        *self.x() * *other.x() + *self.y() * *other.y() + *self.z() * *other.z()
+    }
+
+    /// cross product of two coordinates. The kind of the result is the same
+    /// as self.
+    pub fn cross(&self, other: &Coordinate<T>) -> Coordinate<T> {
+        let (ax, ay, az) = (*self.x(),  *self.y(),  *self.z());
+        let (bx, by, bz) = (*other.x(), *other.y(), *other.z());
+        Coordinate::build(self.which(), ay * bz - az * by,
+                                        az * bx - ax * bz,
+                                        ax * by - ay * bx)
+    }
+
+    /// length of the coordinate.
+    pub fn norm(&self) -> T {
+        self.dot(self).sqrt()
+    }
+
+    /// coordinate that has the same direction and the unit length. If self
+    /// is zero, all the elements become NaN.
+    pub fn normalize(&self) -> Coordinate<T> {
+        let n = self.norm();
+        Coordinate::build(self.which(), *self.x() / n, *self.y() / n, *self.z() / n)
+    }
+
+    /// compares two coordinates with tolerances. Unlike `PartialEq` that
+    /// compares the values exactly, each component may differ by
+    /// `max(abs_tol, rel_tol * max(|a|, |b|))`. The kinds should be the same.
+    /// NaN is not equal to anything.
+    ///
+    /// ```
+    /// use trajan::coordinate::*;
+    /// let a = Coordinate::build(CoordKind::Position, 1.0, 100.0, 0.0);
+    /// let b = Coordinate::build(CoordKind::Position, 1.0, 100.001, 1e-9);
+    /// assert!(a != b);
+    /// assert!(a.approx_eq(&b, 1e-8, 1e-5));
+    /// ```
+    pub fn approx_eq(&self, other: &Coordinate<T>, abs_tol: T, rel_tol: T) -> bool {
+        self.which() == other.which() && self.iter().zip(other.iter()).all(|(a, b)| {
+            let scale = a.abs().max(b.abs());
+            (*a - *b).abs() <= abs_tol.max(rel_tol * scale)
+        })
+    }
+
+    /// converts into `nalgebra::Vector3`, failing if any element is NaN or
+    /// infinite. Use `Into` to convert without the check.
+    pub fn try_into_vector(self) -> Result<nalgebra::Vector3<T>> {
+        let v = nalgebra::Vector3::new(*self.x(), *self.y(), *self.z());
+        // NaN fails the comparison.
+        if v.iter().all(|x| x.abs() <= T::max_value()) {
+            Ok(v)
+        } else {
+            Err(Error::invalid_condition(format!(
+                "non-finite {:?} coordinate: {:?}", self.which(), v.as_slice())))
+        }
+    }
 }
 
 impl<T> std::ops::Index<usize> for Coordinate<T> {
@@ -133,7 +257,7 @@ impl<T> std::ops::Index<usize> for Coordinate<T> {
 }
 
 impl<T> std::ops::IndexMut<usize> for Coordinate<T> {
-    fn index_mut<'a>(&'a mut self, idx: usize) -> &'a mut Self::Output {
+    fn index_mut(&mut self, idx: usize) -> &mut Self::Output {
         match idx {
             0 => self.x_mut(),
             1 => self.y_mut(),
@@ -143,12 +267,12 @@ impl<T> std::ops::IndexMut<usize> for Coordinate<T> {
     }
 }
 
-impl<T> Into<nalgebra::Vector3<T>> for Coordinate<T>
+impl<T> From<Coordinate<T>> for nalgebra::Vector3<T>
 where
     T: nalgebra::Scalar
 {
-    fn into(self) -> nalgebra::Vector3<T> {
-        match self {
+    fn from(c: Coordinate<T>) -> nalgebra::Vector3<T> {
+        match c {
             Coordinate::Position{x, y, z} => nalgebra::Vector3::new(x, y, z),
             Coordinate::Velocity{x, y, z} => nalgebra::Vector3::new(x, y, z),
             Coordinate::Force{x, y, z}    => nalgebra::Vector3::new(x, y, z),
@@ -156,6 +280,15 @@ where
     }
 }
 
+impl<T> From<(CoordKind, nalgebra::Vector3<T>)> for Coordinate<T>
+where
+    T: nalgebra::Scalar
+{
+    fn from((kind, v): (CoordKind, nalgebra::Vector3<T>)) -> Coordinate<T> {
+        Coordinate::from_vector(kind, v)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -269,6 +402,38 @@ mod tests {
         println!("{}", p[3]);
     }
 
+    #[test]
+    fn approximate_equality() {
+        let a = Coordinate::build(CoordKind::Position, 1.0, -2.0, 1000.0);
+        let b = Coordinate::build(CoordKind::Position, 1.0 + 1e-10, -2.0, 1000.0 + 1e-4);
+        assert!(!a.approx_eq(&b, 1e-8, 0.0));
+        assert!(!a.approx_eq(&b, 0.0, 1e-8));
+        assert!( a.approx_eq(&b, 1e-8, 1e-6));
+        assert!( a.approx_eq(&b, 1e-3, 0.0));
+        assert!( a.approx_eq(&a, 0.0, 0.0));
+
+        // different kinds, clearly different values, and NaN
+        let v = a.into_kind(CoordKind::Velocity);
+        assert!(!a.approx_eq(&v, 1.0, 1.0));
+        let c = Coordinate::build(CoordKind::Position, 1.0, 2.0, 1000.0);
+        assert!(!a.approx_eq(&c, 1e-3, 1e-3));
+        let nan = Coordinate::build(CoordKind::Position, f64::NAN, -2.0, 1000.0);
+        assert!(!nan.approx_eq(&nan, 1.0, 1.0));
+    }
+
+    #[test]
+    fn iterate_elements() {
+        let mut c = Coordinate::build(CoordKind::Force, 1.0, 2.0, 3.0);
+        assert_eq!(c.iter().count(), 3);
+        assert_eq!(c.iter().cloned().collect::<Vec<f64>>(), vec![1.0, 2.0, 3.0]);
+        assert!(c.iter().enumerate().all(|(i, x)| *x == c[i]));
+
+        for x in c.iter_mut() {
+            *x *= 2.0;
+        }
+        assert_eq!(c, Coordinate::Force{x: 2.0, y: 4.0, z: 6.0});
+    }
+
     #[test]
     fn to_nalgebra() {
         {
@@ -290,4 +455,44 @@ mod tests {
             assert_eq!(na, nalgebra::Vector3::new(1.0, 2.0, 3.0));
         }
     }
+
+    #[test]
+    fn checked_conversion() {
+        let finite = Coordinate::Position{x: 1.0, y: -2.0, z: 3.0};
+        assert_eq!(finite.try_into_vector().unwrap(), nalgebra::Vector3::new(1.0, -2.0, 3.0));
+
+        let nan = Coordinate::Velocity{x: 1.0, y: f64::NAN, z: 3.0};
+        assert!(nan.try_into_vector().is_err());
+        let inf = Coordinate::Force{x: f32::INFINITY, y: 0.0, z: 0.0};
+        assert!(inf.try_into_vector().is_err());
+
+        // the unchecked conversion is kept
+        let v: nalgebra::Vector3<f64> = nan.into();
+        assert!(v.y.is_nan());
+    }
+
+    #[test]
+    fn vector_operations() {
+        let x = Coordinate::build(CoordKind::Velocity, 1.0, 0.0, 0.0);
+        let y = Coordinate::build(CoordKind::Force,    0.0, 1.0, 0.0);
+        assert_eq!(x.cross(&y), Coordinate::Velocity{x: 0.0, y: 0.0, z: 1.0});
+        assert_eq!(y.cross(&x), Coordinate::Force{x: 0.0, y: 0.0, z: -1.0});
+
+        let v = Coordinate::build(CoordKind::Position, 3.0, 0.0, 4.0);
+        assert_eq!(v.dot(&v), 25.0);
+        assert_eq!(v.norm(), 5.0);
+        assert_eq!(v.normalize(), Coordinate::Position{x: 0.6, y: 0.0, z: 0.8});
+    }
+
+    #[test]
+    fn from_nalgebra() {
+        for kind in &[CoordKind::Position, CoordKind::Velocity, CoordKind::Force] {
+            let c  = Coordinate::build(*kind, 1.0, 2.0, 3.0);
+            let na: nalgebra::Vector3<f64> = Into::into(c);
+            assert_eq!(Coordinate::from_vector(*kind, na), c);
+
+            let from_tuple: Coordinate<f64> = From::from((*kind, na));
+            assert_eq!(from_tuple, c);
+        }
+    }
 }
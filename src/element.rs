@@ -0,0 +1,136 @@
+//! Elements in the periodic table.
+//!
+//! Most formats name particles freely, like "C", "CA" or "OW". `Element`
+//! gives a canonical identity to group particles by element. It is guessed
+//! from the name by `Element::from_name`; see it for the heuristic.
+
+const SYMBOLS: [&str; 118] = [
+    "H",  "He", "Li", "Be", "B",  "C",  "N",  "O",  "F",  "Ne",
+    "Na", "Mg", "Al", "Si", "P",  "S",  "Cl", "Ar", "K",  "Ca",
+    "Sc", "Ti", "V",  "Cr", "Mn", "Fe", "Co", "Ni", "Cu", "Zn",
+    "Ga", "Ge", "As", "Se", "Br", "Kr", "Rb", "Sr", "Y",  "Zr",
+    "Nb", "Mo", "Tc", "Ru", "Rh", "Pd", "Ag", "Cd", "In", "Sn",
+    "Sb", "Te", "I",  "Xe", "Cs", "Ba", "La", "Ce", "Pr", "Nd",
+    "Pm", "Sm", "Eu", "Gd", "Tb", "Dy", "Ho", "Er", "Tm", "Yb",
+    "Lu", "Hf", "Ta", "W",  "Re", "Os", "Ir", "Pt", "Au", "Hg",
+    "Tl", "Pb", "Bi", "Po", "At", "Rn", "Fr", "Ra", "Ac", "Th",
+    "Pa", "U",  "Np", "Pu", "Am", "Cm", "Bk", "Cf", "Es", "Fm",
+    "Md", "No", "Lr", "Rf", "Db", "Sg", "Bh", "Hs", "Mt", "Ds",
+    "Rg", "Cn", "Nh", "Fl", "Mc", "Lv", "Ts", "Og",
+];
+
+/// An element identified by its atomic number.
+///
+/// ```
+/// use trajan::element::Element;
+/// let c = Element::from_symbol("C").unwrap();
+/// assert_eq!(c.atomic_number(), 6);
+/// assert_eq!(c.to_string(), "C");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Element {
+    atomic_number: u8,
+}
+
+impl Element {
+    /// Returns the element of the atomic number. Returns None if it is not
+    /// in 1 ..= 118.
+    pub fn from_atomic_number(z: u8) -> Option<Self> {
+        if 1 <= z && z as usize <= SYMBOLS.len() {
+            Some(Element{atomic_number: z})
+        } else {
+            None
+        }
+    }
+
+    /// Returns the element of the symbol, like "Cl". The case is ignored.
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        SYMBOLS.iter().position(|s| s.eq_ignore_ascii_case(symbol))
+            .map(|i| Element{atomic_number: i as u8 + 1})
+    }
+
+    /// Guesses the element from the name of a particle.
+    ///
+    /// Leading digits (as in PDB names like "1HB") are skipped, and the
+    /// letters that follow are used; trailing digits and charges, like "C1"
+    /// or "Na+", are ignored. Then,
+    ///
+    /// - if the second letter is lowercase, like "Cl" or "ca", the first two
+    ///   letters are the symbol.
+    /// - otherwise, the first letter is the symbol. All-uppercase names are
+    ///   regarded as atom names in biomolecules, so "CA" is an alpha carbon
+    ///   and "HG1" is a hydrogen. Write "Ca" or "Hg" for the metals.
+    ///
+    /// Returns None if the symbol is not an element.
+    ///
+    /// ```
+    /// use trajan::element::Element;
+    /// assert_eq!(Element::from_name("CA").unwrap().symbol(), "C");
+    /// assert_eq!(Element::from_name("Ca2+").unwrap().symbol(), "Ca");
+    /// assert_eq!(Element::from_name("OW").unwrap().symbol(), "O");
+    /// ```
+    pub fn from_name(name: &str) -> Option<Self> {
+        let letters = name.trim().trim_start_matches(|c: char| c.is_ascii_digit());
+        let mut chars = letters.chars().take_while(|c| c.is_ascii_alphabetic());
+        let first  = chars.next()?;
+        match chars.next() {
+            Some(second) if second.is_ascii_lowercase() => {
+                Element::from_symbol(&letters[.. first.len_utf8() + second.len_utf8()])
+            }
+            _ => Element::from_symbol(&letters[.. first.len_utf8()]),
+        }
+    }
+
+    /// Returns the atomic number.
+    pub fn atomic_number(&self) -> u8 {
+        self.atomic_number
+    }
+
+    /// Returns the symbol, like "Cl".
+    pub fn symbol(&self) -> &'static str {
+        SYMBOLS[self.atomic_number as usize - 1]
+    }
+}
+
+impl std::fmt::Display for Element {
+    /// Displays the symbol.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.symbol())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn element_from_name() {
+        let carbon = Element::from_atomic_number(6);
+        assert_eq!(Element::from_name("C"),  carbon);
+        assert_eq!(Element::from_name("c"),  carbon);
+        assert_eq!(Element::from_name("C1"), carbon);
+        assert_eq!(Element::from_name("CA"), carbon);
+        assert_eq!(Element::from_name("Zz"), None);
+        assert_eq!(Element::from_name("X"),  None);
+        assert_eq!(Element::from_name(""),   None);
+        assert_eq!(Element::from_name("42"), None);
+
+        assert_eq!(Element::from_name("1HB").unwrap().symbol(), "H");
+        assert_eq!(Element::from_name("Cl-").unwrap().symbol(), "Cl");
+        assert_eq!(Element::from_name("na").unwrap().symbol(),  "Na");
+        assert_eq!(Element::from_name(" OW ").unwrap().symbol(), "O");
+    }
+
+    #[test]
+    fn periodic_table() {
+        assert_eq!(Element::from_atomic_number(0), None);
+        assert_eq!(Element::from_atomic_number(119), None);
+        assert_eq!(Element::from_atomic_number(1).unwrap().symbol(), "H");
+        assert_eq!(Element::from_atomic_number(118).unwrap().symbol(), "Og");
+        assert_eq!(Element::from_symbol("FE").unwrap().atomic_number(), 26);
+        assert!((1 ..= 118).all(|z| {
+            let e = Element::from_atomic_number(z).unwrap();
+            Element::from_symbol(e.symbol()) == Some(e)
+        }));
+    }
+}
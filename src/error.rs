@@ -1,4 +1,6 @@
 //! error handling.
+// `#[derive(Fail)]` expands into a non-local impl that rustc warns about.
+#![allow(non_local_definitions)]
 use std::fmt;
 use std::fmt::Display;
 use failure::{Backtrace, Context, Fail};
@@ -12,12 +14,18 @@ pub enum ErrorKind {
     ParseError,
     #[fail(display = "Invalid Format: {:?}", error)]
     InvalidFormat{
-        error: std::string::String
+        error: std::string::String,
+        /// the line number (1-origin) in a text file, if it is known.
+        line: std::option::Option<usize>,
     },
     #[fail(display = "Invalid Condition: {:?}", error)]
     InvalidCondition{
         error: std::string::String
     },
+    #[fail(display = "Inconsistent Kind: {:?}", error)]
+    InconsistentKind{
+        error: std::string::String
+    },
 }
 impl std::cmp::Eq for ErrorKind {}
 
@@ -68,7 +76,7 @@ impl std::convert::From<std::string::ParseError> for Error {
 
 
 impl Fail for Error {
-    fn cause(&self) -> Option<&Fail> {
+    fn cause(&self) -> Option<&dyn Fail> {
         self.inner.cause()
     }
 
@@ -95,13 +103,74 @@ impl Error {
     /// Constructs `trajan::error::Error` from `std::string::String` that
     /// represents a portion of an input that is formatted in the invalid way.
     pub fn invalid_format(s: std::string::String) -> Error {
-        Error{inner: failure::Context::new(ErrorKind::InvalidFormat{error: s})}
+        Error{inner: failure::Context::new(ErrorKind::InvalidFormat{error: s, line: None})}
+    }
+    /// Tells where the error occurred in a text file. An `InvalidFormat` or
+    /// `ParseError` becomes `ErrorKind::InvalidFormat` with the line number
+    /// (1-origin), and its message is prefixed like "at line 42: <message of
+    /// self>". The original error is kept as the cause. The other kinds,
+    /// e.g. `Io`, are returned unchanged because they do not depend on the
+    /// contents of the line.
+    pub fn with_line(self, line: usize) -> Error {
+        match self.kind() {
+            ErrorKind::InvalidFormat{..} | ErrorKind::ParseError => {
+                let error = format!("at line {}: {}", line, self.detail());
+                let kind  = ErrorKind::InvalidFormat{error, line: Some(line)};
+                Error{inner: failure::Error::from(self).context(kind)}
+            }
+            _ => self,
+        }
+    }
+    // Prefixes the message by e.g. a file name, keeping the kind.
+    pub(crate) fn with_prefix(self, prefix: &str) -> Error {
+        let kind = match self.kind() {
+            ErrorKind::InvalidFormat{error, line} => ErrorKind::InvalidFormat{
+                error: format!("{}: {}", prefix, error), line: *line},
+            ErrorKind::InvalidCondition{error} => ErrorKind::InvalidCondition{
+                error: format!("{}: {}", prefix, error)},
+            ErrorKind::InconsistentKind{error} => ErrorKind::InconsistentKind{
+                error: format!("{}: {}", prefix, error)},
+            ErrorKind::Io         => return self.with_prefixed_cause(prefix, ErrorKind::Io),
+            ErrorKind::ParseError => return self.with_prefixed_cause(prefix, ErrorKind::ParseError),
+        };
+        Error{inner: failure::Context::new(kind)}
+    }
+    fn with_prefixed_cause(self, prefix: &str, kind: ErrorKind) -> Error {
+        let cause = match self.cause() {
+            Some(c) => format!("{}: {}", prefix, c),
+            None    => prefix.to_string(),
+        };
+        Error{inner: failure::err_msg(cause).context(kind)}
+    }
+    /// Returns the line number where the error occurred, if it is known.
+    pub fn line(&self) -> Option<usize> {
+        match self.kind() {
+            ErrorKind::InvalidFormat{line, ..} => *line,
+            _ => None,
+        }
+    }
+    // The message without the kind if it has one, or with the cause if not.
+    pub(crate) fn detail(&self) -> std::string::String {
+        match self.kind() {
+            ErrorKind::InvalidFormat{error, ..}  => error.clone(),
+            ErrorKind::InvalidCondition{error}   => error.clone(),
+            ErrorKind::InconsistentKind{error}   => error.clone(),
+            _ => match self.cause() {
+                Some(c) => format!("{}: {}", self, c),
+                None    => format!("{}", self),
+            },
+        }
     }
     /// Constructs `trajan::error::Error` from `std::string::String` that
     /// represents some required condition is violated.
     pub fn invalid_condition(s: std::string::String) -> Error {
         Error{inner: failure::Context::new(ErrorKind::InvalidCondition{error: s})}
     }
+    /// Constructs `trajan::error::Error` from `std::string::String` that
+    /// describes a snapshot whose particles have different coordinate kinds.
+    pub fn inconsistent_kind(s: std::string::String) -> Error {
+        Error{inner: failure::Context::new(ErrorKind::InconsistentKind{error: s})}
+    }
 }
 
 impl From<ErrorKind> for Error {
@@ -122,7 +191,7 @@ impl From<Context<ErrorKind>> for Error {
 mod tests {
     #[test]
     fn from_std_io_error() {
-        let e = std::io::Error::new(std::io::ErrorKind::Other, "test");
+        let e = std::io::Error::other("test");
         let err: super::Error = std::convert::From::from(e);
         assert_eq!(*err.kind(), super::ErrorKind::Io);
     }
@@ -144,6 +213,46 @@ mod tests {
     #[test]
     fn from_invalid_format() {
         let err = super::Error::invalid_format("test".to_string());
-        assert_eq!(*err.kind(), super::ErrorKind::InvalidFormat{error: "test".to_string()});
+        assert_eq!(*err.kind(), super::ErrorKind::InvalidFormat{error: "test".to_string(), line: None});
+        assert_eq!(err.line(), None);
+    }
+
+    #[test]
+    fn error_with_line() {
+        let e = "foo".parse::<f64>().unwrap_err();
+        let err = super::Error::from(e).with_line(42);
+        assert_eq!(err.line(), Some(42));
+        match err.kind() {
+            super::ErrorKind::InvalidFormat{error, ..} => {
+                assert!(error.starts_with("at line 42: Parse Value Error: "));
+            }
+            _ => panic!("unexpected kind: {:?}", err.kind()),
+        }
+    }
+
+    #[test]
+    fn io_error_with_line() {
+        let err = super::Error::from(std::io::Error::other("disk")).with_line(7);
+        assert_eq!(*err.kind(), super::ErrorKind::Io);
+        assert_eq!(err.line(), None);
+
+        let e = "foo".parse::<f64>().unwrap_err();
+        let err = super::Error::from(e).with_line(42);
+        let cause = failure::Fail::cause(&err).unwrap();
+        assert!(format!("{}", cause).starts_with("Parse Value Error"));
+    }
+
+    #[test]
+    fn error_with_prefix() {
+        let err = super::Error::from(std::io::Error::other("no such file")).with_prefix("a.xyz");
+        assert_eq!(*err.kind(), super::ErrorKind::Io);
+        assert_eq!(err.detail(), "I/O Error: a.xyz: no such file");
+
+        let err = super::Error::invalid_format("broken".to_string()).with_line(3).with_prefix("a.xyz");
+        assert_eq!(err.line(), Some(3));
+        assert_eq!(err.detail(), "a.xyz: at line 3: broken");
+        let err = super::Error::invalid_format("test".to_string()).with_line(3);
+        assert_eq!(*err.kind(), super::ErrorKind::InvalidFormat{
+            error: "at line 3: test".to_string(), line: Some(3)});
     }
 }
@@ -0,0 +1,65 @@
+//! A particle and a snapshot to test the generic functions on, and other
+//! helpers shared by the tests.
+//!
+//! Unlike XYZParticle, every value of `TestParticle` is optional, so that a
+//! test can make a snapshot that has e.g. masses and velocities but no
+//! positions, or residues like the ones read from a PDB file.
+use crate::particle::{Attribute, Particle};
+use crate::snapshot::Snapshot;
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TestParticle {
+    pub(crate) mass:    Option<f64>,
+    pub(crate) charge:  Option<f64>,
+    pub(crate) pos:     Option<nalgebra::Vector3<f64>>,
+    pub(crate) vel:     Option<nalgebra::Vector3<f64>>,
+    pub(crate) chain:   Option<&'static str>,
+    pub(crate) resid:   Option<i64>,
+    pub(crate) resname: Option<&'static str>,
+}
+
+impl Particle<f64> for TestParticle {
+    type Value = f64;
+    fn mass(&self) -> Option<f64> {self.mass}
+    fn pos(&self) -> Option<nalgebra::Vector3<f64>> {self.pos}
+    fn vel(&self) -> Option<nalgebra::Vector3<f64>> {self.vel}
+    fn force(&self) -> Option<nalgebra::Vector3<f64>> {None}
+    fn attribute(&self, name: &str) -> Option<Attribute> {
+        match name {
+            "charge"  => self.charge.map(Attribute::Float),
+            "chain"   => self.chain.map(|c| Attribute::String(c.to_string())),
+            "resid"   => self.resid.map(Attribute::Integer),
+            "resname" => self.resname.map(|r| Attribute::String(r.to_string())),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TestSnapshot(pub(crate) std::vec::Vec<TestParticle>);
+
+impl std::ops::Index<usize> for TestSnapshot {
+    type Output = TestParticle;
+    fn index(&self, i: usize) -> &TestParticle {&self.0[i]}
+}
+
+impl Snapshot<f64> for TestSnapshot {
+    type Value = f64;
+    fn len(&self) -> usize {self.0.len()}
+    fn masses(&self) -> Option<std::vec::Vec<f64>> {
+        self.0.iter().map(|p| p.mass).collect()
+    }
+    fn attributes(&self, name: &str) -> Option<std::vec::Vec<Attribute>> {
+        self.0.iter().map(|p| p.attribute(name)).collect()
+    }
+}
+
+// Deterministic pseudo-random numbers uniformly distributed in [0, 1), so
+// that a test gives the same result every time.
+pub(crate) fn uniform(seed: u64) -> impl FnMut() -> f64 {
+    let mut state = seed;
+    move || {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (state >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
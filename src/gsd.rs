@@ -0,0 +1,543 @@
+//! Input of HOOMD-blue GSD trajectory file.
+//!
+//! GSD is a binary format that stores data in chunks, like
+//! `particles/position`, and has an index of the chunks at the end of the
+//! file. Since the index tells where the chunks of each frame are, any frame
+//! can be read directly by `GsdReader::read_frame`.
+//!
+//! The following chunks of the HOOMD schema are read.
+//!
+//! - `particles/position` and `particles/velocity` (N x 3).
+//! - `particles/typeid` and `particles/types`, to name each particle.
+//! - `configuration/box` (Lx, Ly, Lz, xy, xz, yz) and `configuration/step`.
+//!
+//! Following the schema, a chunk that is not written in a frame takes the
+//! value in the first frame, or the default value if the first frame does
+//! not have it either (e.g. all the particles are named "A").
+//!
+//! HOOMD places the origin at the center of the box, so positions are in
+//! [-L/2, L/2). They are read as they are. `SimulationBox::wrap` and
+//! `SimulationBox::center` assume a box spanning [0, L), so shift positions
+//! by half of the box (the sum of the lattice vectors divided by 2) before
+//! using them.
+//!
+//! # example
+//! ```no_run
+//! use trajan::gsd::GsdReader;
+//! let mut reader = GsdReader::open("traj.gsd").unwrap();
+//! let last = reader.read_frame(reader.n_frames() - 1).unwrap();
+//! println!("{} particles at step {:?}", last.snapshot.particles.len(), last.step);
+//! ```
+use crate::binary::{read_f32_endian, read_f64_endian, Endian};
+use crate::boundary::SimulationBox;
+use crate::coordinate::Coordinate;
+use crate::error::{Error, Result};
+use crate::particle::Attribute;
+use crate::reader::TrajectoryReader;
+use crate::xyz::{XYZParticle, XYZSnapshot};
+
+// The name of particles when the file does not have `particles/types`.
+const DEFAULT_TYPE: &str = "A";
+
+/// A frame in a GSD trajectory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GsdFrame {
+    /// The timestep, if the file has it.
+    pub step: std::option::Option<u64>,
+    /// The box, if the file has it.
+    pub simulation_box: std::option::Option<SimulationBox<f64>>,
+    /// Velocities of the particles, if the file has them.
+    pub velocities: std::option::Option<std::vec::Vec<nalgebra::Vector3<f64>>>,
+    /// Positions of the particles named by their types. The step and box are
+    /// also stored in its metadata.
+    pub snapshot: XYZSnapshot<f64>,
+}
+
+/// Reads frames in a GSD trajectory, sequentially or randomly.
+pub struct GsdReader<R> {
+    reader:   R,
+    index:    std::collections::HashMap<(u64, u16), IndexEntry>, // (frame, id)
+    names:    std::vec::Vec<std::string::String>,
+    file_len: u64,
+    n_frames: usize,
+    next:     usize,
+}
+
+impl<R: std::io::Read + std::io::Seek> GsdReader<R> {
+    /// Reads the header, the index and the list of chunk names, and
+    /// constructs GsdReader. Fails if it is not a GSD file.
+    pub fn new(mut inner: R) -> Result<Self> {
+        let header   = Header::read(&mut inner)?;
+        let file_len = inner.seek(std::io::SeekFrom::End(0))?;
+
+        // the sizes in the header are checked before allocation
+        let fits = |location: u64, count: u64, size: u64| count.checked_mul(size)
+            .and_then(|len| location.checked_add(len))
+            .is_some_and(|end| end <= file_len);
+        if !fits(header.index_location, header.index_allocated_entries, 32) {
+            return Err(Error::invalid_format(format!(
+                "GSD index of {} entries exceeds the end of file",
+                header.index_allocated_entries)));
+        }
+        let name_size = if header.major_version() < 2 {64} else {1};
+        if !fits(header.namelist_location, header.namelist_allocated_entries, name_size) {
+            return Err(Error::invalid_format(format!(
+                "GSD name list of {} entries exceeds the end of file",
+                header.namelist_allocated_entries)));
+        }
+
+        inner.seek(std::io::SeekFrom::Start(header.index_location))?;
+        let mut index = std::collections::HashMap::new();
+        let mut n_frames = 0;
+        for _ in 0 .. header.index_allocated_entries {
+            let entry = IndexEntry::read(&mut inner)?;
+            // the rest of the allocated entries are not used yet
+            if entry.location == 0 {
+                break;
+            }
+            n_frames = std::cmp::max(n_frames, entry.frame as usize + 1);
+            index.insert((entry.frame, entry.id), entry);
+        }
+
+        inner.seek(std::io::SeekFrom::Start(header.namelist_location))?;
+        let names = if header.major_version() < 2 {
+            // fixed length entries, each of which is 64 bytes
+            let mut names = std::vec::Vec::new();
+            for _ in 0 .. header.namelist_allocated_entries {
+                let mut entry = [0u8; 64];
+                inner.read_exact(&mut entry)?;
+                if entry[0] == 0 {
+                    break;
+                }
+                names.push(c_string(&entry)?);
+            }
+            names
+        } else {
+            // a buffer of null-terminated names
+            let mut buffer = vec![0u8; header.namelist_allocated_entries as usize];
+            inner.read_exact(&mut buffer)?;
+            buffer.split(|c| *c == 0).take_while(|name| !name.is_empty())
+                .map(c_string).collect::<Result<_>>()?
+        };
+        if let Some(entry) = index.values().find(|e| names.len() <= e.id as usize) {
+            return Err(Error::invalid_format(format!(
+                "invalid chunk id {}: the file has {} names", entry.id, names.len())));
+        }
+        Ok(GsdReader{reader: inner, index, names, file_len, n_frames, next: 0})
+    }
+
+    /// Returns the number of frames in the file.
+    pub fn n_frames(&self) -> usize {
+        self.n_frames
+    }
+
+    /// Reads the i-th frame. Fails if i is out of range.
+    pub fn read_frame(&mut self, i: usize) -> Result<GsdFrame> {
+        if self.n_frames <= i {
+            return Err(Error::invalid_condition(format!(
+                "frame {} is out of range: the file has {} frames", i, self.n_frames)));
+        }
+        let n = match self.read_chunk(i, "particles/N")? {
+            Some((_, values)) => values.first().map(|n| *n as usize).unwrap_or(0),
+            None              => 0,
+        };
+        let vectors = |name: &str, chunk: std::option::Option<(usize, std::vec::Vec<f64>)>| {
+            match chunk {
+                Some((3, ref values)) if values.len() == n * 3 => Ok(Some(
+                    values.chunks(3).map(|r| nalgebra::Vector3::new(r[0], r[1], r[2])).collect()
+                )),
+                Some(_) => Err(Error::invalid_format(format!(
+                    "{} should have {} x 3 values", name, n))),
+                None => Ok(None),
+            }
+        };
+        let positions: std::option::Option<std::vec::Vec<nalgebra::Vector3<f64>>> =
+            vectors("particles/position", self.read_chunk(i, "particles/position")?)?;
+        let velocities = vectors("particles/velocity", self.read_chunk(i, "particles/velocity")?)?;
+
+        let types = match self.find_entry(i, "particles/types") {
+            Some(entry) => Some(self.read_bytes(&entry)?),
+            None        => None,
+        };
+        let types = match types {
+            Some((m, bytes)) if 0 < m => bytes.chunks(m).map(c_string).collect::<Result<_>>()?,
+            _ => vec![DEFAULT_TYPE.to_string()],
+        };
+        let typeids = match self.read_chunk(i, "particles/typeid")? {
+            Some((_, values)) if values.len() == n => values,
+            Some(_) => return Err(Error::invalid_format(format!(
+                "particles/typeid should have {} values", n))),
+            None => vec![0.0; n],
+        };
+        let mut particles = std::vec::Vec::with_capacity(n);
+        for (j, id) in typeids.iter().enumerate() {
+            let name = types.get(*id as usize).ok_or_else(|| Error::invalid_format(format!(
+                "particle {} has typeid {}, but there are {} types", j, id, types.len())))?;
+            let r = positions.as_ref().map(|p| p[j]).unwrap_or_else(nalgebra::Vector3::zeros);
+            particles.push(XYZParticle::new(name.clone(),
+                Coordinate::Position{x: r.x, y: r.y, z: r.z}));
+        }
+
+        let step = self.read_chunk(i, "configuration/step")?
+            .and_then(|(_, values)| values.first().map(|s| *s as u64));
+        let simulation_box = match self.read_chunk(i, "configuration/box")? {
+            Some((_, ref b)) if b.len() == 6 => {
+                let (lx, ly, lz, xy, xz, yz) = (b[0], b[1], b[2], b[3], b[4], b[5]);
+                Some(SimulationBox::new(nalgebra::Vector3::new(lx, 0.0, 0.0),
+                                        nalgebra::Vector3::new(xy * ly, ly, 0.0),
+                                        nalgebra::Vector3::new(xz * lz, yz * lz, lz))?)
+            }
+            Some(_) => return Err(Error::invalid_format(
+                "configuration/box should have 6 values".to_string())),
+            None => None,
+        };
+
+        let mut snapshot = XYZSnapshot::new(std::string::String::new(), particles);
+        if let Some(s) = step {
+            snapshot.set_metadata("timestep", Attribute::Integer(s as i64));
+        }
+        if let Some(ref b) = simulation_box {
+            snapshot.set_metadata("box", Attribute::Matrix(*b.lattice()));
+        }
+        Ok(GsdFrame{step, simulation_box, velocities, snapshot})
+    }
+
+    /// Reads the next frame. Returns None if all the frames are read.
+    pub fn read_next(&mut self) -> Result<std::option::Option<GsdFrame>> {
+        if self.n_frames <= self.next {
+            return Ok(None);
+        }
+        let frame = self.read_frame(self.next)?;
+        self.next += 1;
+        Ok(Some(frame))
+    }
+
+    // Finds the chunk in the frame, or in the first frame if the frame does
+    // not have it.
+    fn find_entry(&self, frame: usize, name: &str) -> std::option::Option<IndexEntry> {
+        let id: u16 = std::convert::TryFrom::try_from(
+            self.names.iter().position(|n| n == name)?).ok()?;
+        self.index.get(&(frame as u64, id)).or_else(|| self.index.get(&(0, id))).cloned()
+    }
+
+    // Reads the raw bytes of a chunk with the number of columns.
+    fn read_bytes(&mut self, entry: &IndexEntry) -> Result<(usize, std::vec::Vec<u8>)> {
+        let type_size = type_size(entry.ty)? as u64;
+        let size = entry.n.checked_mul(u64::from(entry.m))
+            .and_then(|len| len.checked_mul(type_size))
+            .filter(|len| entry.location.checked_add(*len).is_some_and(|end| end <= self.file_len))
+            .ok_or_else(|| Error::invalid_format(format!(
+                "GSD chunk {} x {} at {} exceeds the end of file",
+                entry.n, entry.m, entry.location)))?;
+        self.reader.seek(std::io::SeekFrom::Start(entry.location))?;
+        let mut bytes = vec![0u8; size as usize];
+        self.reader.read_exact(&mut bytes)?;
+        Ok((entry.m as usize, bytes))
+    }
+
+    // Reads the values of a chunk as f64 with the number of columns.
+    fn read_chunk(&mut self, frame: usize, name: &str)
+        -> Result<std::option::Option<(usize, std::vec::Vec<f64>)>>
+    {
+        let entry = match self.find_entry(frame, name) {
+            Some(entry) => entry,
+            None        => return Ok(None),
+        };
+        let (m, bytes) = self.read_bytes(&entry)?;
+        let values = bytes.chunks(type_size(entry.ty)?).map(|b| read_value(b, entry.ty))
+            .collect::<Result<_>>()?;
+        Ok(Some((m, values)))
+    }
+}
+
+impl GsdReader<std::io::BufReader<std::fs::File>> {
+    /// Opens file and constructs GsdReader by using the file.
+    pub fn open<P>(path: P) -> Result<Self>
+    where
+        P: std::convert::AsRef<std::path::Path>
+    {
+        let f = std::fs::File::open(path)?;
+        GsdReader::new(std::io::BufReader::new(f))
+    }
+}
+
+/// Enables GsdReader to be used as a generic `TrajectoryReader`.
+/// The step and box are available through `Snapshot::metadata`.
+impl<R: std::io::Read + std::io::Seek> TrajectoryReader<f64> for GsdReader<R> {
+    type Snapshot = XYZSnapshot<f64>;
+    fn read_snapshot(&mut self) -> Result<std::option::Option<Self::Snapshot>> {
+        Ok(self.read_next()?.map(|frame| frame.snapshot))
+    }
+}
+
+/// Enables GsdReader to be used as a Iterator of GsdFrame.
+/// It stops at the end of the file or at the first error.
+impl<R: std::io::Read + std::io::Seek> std::iter::Iterator for GsdReader<R> {
+    type Item = GsdFrame;
+    fn next(&mut self) -> std::option::Option<Self::Item> {
+        self.read_next().ok().and_then(|frame| frame)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// GSD file layout
+//
+// All the values are little-endian. The file starts with a header of 256
+// bytes, and the index entries are 32 bytes each.
+
+const GSD_MAGIC: u64 = 0x65DF_65DF_65DF_65DF;
+
+const GSD_UINT8:  u8 = 1;
+const GSD_UINT16: u8 = 2;
+const GSD_UINT32: u8 = 3;
+const GSD_UINT64: u8 = 4;
+const GSD_INT8:   u8 = 5;
+const GSD_INT16:  u8 = 6;
+const GSD_INT32:  u8 = 7;
+const GSD_INT64:  u8 = 8;
+const GSD_FLOAT:  u8 = 9;
+const GSD_DOUBLE: u8 = 10;
+const GSD_CHAR:   u8 = 11;
+
+#[derive(Debug)]
+struct Header {
+    index_location:             u64,
+    index_allocated_entries:    u64,
+    namelist_location:          u64,
+    namelist_allocated_entries: u64,
+    gsd_version:                u32, // (major << 16) | minor
+}
+
+impl Header {
+    fn read<R: std::io::Read>(reader: &mut R) -> Result<Self> {
+        let mut bytes = [0u8; 256];
+        reader.read_exact(&mut bytes).map_err(|_| Error::invalid_format(
+            "not a GSD file: too short".to_string()))?;
+        let u64_at = |i: usize| read_u64(&bytes[i .. i + 8]);
+        if u64_at(0) != GSD_MAGIC {
+            return Err(Error::invalid_format("not a GSD file".to_string()));
+        }
+        let mut version = [0u8; 4];
+        version.copy_from_slice(&bytes[44 .. 48]);
+        Ok(Header{
+            index_location:             u64_at(8),
+            index_allocated_entries:    u64_at(16),
+            namelist_location:          u64_at(24),
+            namelist_allocated_entries: u64_at(32),
+            gsd_version:                u32::from_le_bytes(version),
+        })
+    }
+
+    fn major_version(&self) -> u32 {
+        self.gsd_version >> 16
+    }
+}
+
+#[derive(Debug, Clone)]
+struct IndexEntry {
+    frame:    u64,
+    n:        u64, // the number of rows
+    location: u64,
+    m:        u32, // the number of columns
+    id:       u16, // index in the name list
+    ty:       u8,
+}
+
+impl IndexEntry {
+    fn read<R: std::io::Read>(reader: &mut R) -> Result<Self> {
+        let mut bytes = [0u8; 32];
+        reader.read_exact(&mut bytes)?;
+        let mut m  = [0u8; 4];
+        let mut id = [0u8; 2];
+        m.copy_from_slice(&bytes[24 .. 28]);
+        id.copy_from_slice(&bytes[28 .. 30]);
+        Ok(IndexEntry{
+            frame:    read_u64(&bytes[0 .. 8]),
+            n:        read_u64(&bytes[8 .. 16]),
+            location: read_u64(&bytes[16 .. 24]),
+            m:        u32::from_le_bytes(m),
+            id:       u16::from_le_bytes(id),
+            ty:       bytes[30],
+        })
+    }
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(bytes);
+    u64::from_le_bytes(buf)
+}
+
+fn type_size(ty: u8) -> Result<usize> {
+    match ty {
+        GSD_UINT8  | GSD_INT8  | GSD_CHAR   => Ok(1),
+        GSD_UINT16 | GSD_INT16              => Ok(2),
+        GSD_UINT32 | GSD_INT32 | GSD_FLOAT  => Ok(4),
+        GSD_UINT64 | GSD_INT64 | GSD_DOUBLE => Ok(8),
+        _ => Err(Error::invalid_format(format!("unknown GSD type: {}", ty))),
+    }
+}
+
+fn read_value(mut bytes: &[u8], ty: u8) -> Result<f64> {
+    let mut b2 = [0u8; 2];
+    let mut b4 = [0u8; 4];
+    match ty {
+        GSD_FLOAT  => Ok(f64::from(read_f32_endian(&mut bytes, Endian::Little)?)),
+        GSD_DOUBLE => read_f64_endian(&mut bytes, Endian::Little),
+        GSD_UINT8  => Ok(f64::from(bytes[0])),
+        GSD_INT8   => Ok(f64::from(bytes[0] as i8)),
+        GSD_UINT16 => {b2.copy_from_slice(bytes); Ok(f64::from(u16::from_le_bytes(b2)))}
+        GSD_INT16  => {b2.copy_from_slice(bytes); Ok(f64::from(i16::from_le_bytes(b2)))}
+        GSD_UINT32 => {b4.copy_from_slice(bytes); Ok(f64::from(u32::from_le_bytes(b4)))}
+        GSD_INT32  => {b4.copy_from_slice(bytes); Ok(f64::from(i32::from_le_bytes(b4)))}
+        GSD_UINT64 => Ok(read_u64(bytes) as f64),
+        GSD_INT64  => Ok(read_u64(bytes) as i64 as f64),
+        _ => Err(Error::invalid_format(format!(
+            "GSD type {} cannot be read as a number", ty))),
+    }
+}
+
+// Reads a string padded by null characters.
+fn c_string(bytes: &[u8]) -> Result<std::string::String> {
+    let len = bytes.iter().position(|c| *c == 0).unwrap_or(bytes.len());
+    std::string::String::from_utf8(bytes[.. len].to_vec()).map_err(|e| Error::invalid_format(
+        format!("invalid name in GSD file: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::Snapshot;
+
+    // (name, frame, type, columns, data)
+    type Chunk<'a> = (&'a str, u64, u8, u32, std::vec::Vec<u8>);
+
+    // Writes a minimal GSD 2.0 file with the chunks.
+    fn write_gsd(chunks: &[Chunk]) -> std::vec::Vec<u8> {
+        let mut names: std::vec::Vec<&str> = std::vec::Vec::new();
+        for (name, ..) in chunks.iter() {
+            if !names.contains(name) {
+                names.push(name);
+            }
+        }
+        let mut data = std::vec::Vec::new();
+        let mut index = std::vec::Vec::new();
+        for (name, frame, ty, m, bytes) in chunks.iter() {
+            let n = bytes.len() / (*m as usize * type_size(*ty).unwrap());
+            index.extend_from_slice(&frame.to_le_bytes());
+            index.extend_from_slice(&(n as u64).to_le_bytes());
+            index.extend_from_slice(&(256 + data.len() as u64).to_le_bytes());
+            index.extend_from_slice(&m.to_le_bytes());
+            let id = names.iter().position(|x| x == name).unwrap() as u16;
+            index.extend_from_slice(&id.to_le_bytes());
+            index.push(*ty);
+            index.push(0);
+            data.extend_from_slice(bytes);
+        }
+        // an allocated but unused entry
+        index.extend_from_slice(&[0u8; 32]);
+        let mut namelist = std::vec::Vec::new();
+        for name in names.iter() {
+            namelist.extend_from_slice(name.as_bytes());
+            namelist.push(0);
+        }
+        namelist.resize(namelist.len() + 8, 0);
+
+        let index_location = 256 + data.len() as u64;
+        let mut b = std::vec::Vec::new();
+        b.extend_from_slice(&GSD_MAGIC.to_le_bytes());
+        b.extend_from_slice(&index_location.to_le_bytes());
+        b.extend_from_slice(&(index.len() as u64 / 32).to_le_bytes());
+        b.extend_from_slice(&(index_location + index.len() as u64).to_le_bytes());
+        b.extend_from_slice(&(namelist.len() as u64).to_le_bytes());
+        b.extend_from_slice(&1u32.to_le_bytes()); // schema version
+        b.extend_from_slice(&(2u32 << 16).to_le_bytes());
+        b.resize(256, 0);
+        b.extend(data);
+        b.extend(index);
+        b.extend(namelist);
+        b
+    }
+
+    fn floats(values: &[f32]) -> std::vec::Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect()
+    }
+    fn uints(values: &[u32]) -> std::vec::Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn read_gsd_frames() {
+        let bytes = write_gsd(&[
+            ("configuration/step", 0, GSD_UINT64, 1, 100u64.to_le_bytes().to_vec()),
+            ("configuration/box",  0, GSD_FLOAT, 1, floats(&[10.0, 10.0, 10.0, 0.0, 0.0, 0.0])),
+            ("particles/N",        0, GSD_UINT32, 1, uints(&[3])),
+            ("particles/types",    0, GSD_INT8, 2, b"A\0Na".to_vec()),
+            ("particles/typeid",   0, GSD_UINT32, 1, uints(&[0, 1, 0])),
+            ("particles/position", 0, GSD_FLOAT, 3, floats(&[1.0, 2.0, 3.0,
+                                                             4.0, 5.0, 6.0,
+                                                             7.0, 8.0, 9.0])),
+            ("particles/velocity", 0, GSD_FLOAT, 3, floats(&[0.5, 0.0, 0.0,
+                                                             0.0, 0.5, 0.0,
+                                                             0.0, 0.0, 0.5])),
+            // the second frame does not have types, typeid, box and velocity
+            ("configuration/step", 1, GSD_UINT64, 1, 200u64.to_le_bytes().to_vec()),
+            ("particles/N",        1, GSD_UINT32, 1, uints(&[3])),
+            ("particles/position", 1, GSD_FLOAT, 3, floats(&[1.5, 2.0, 3.0,
+                                                             4.5, 5.0, 6.0,
+                                                             7.5, 8.0, 9.0])),
+        ]);
+        let mut reader = GsdReader::new(std::io::Cursor::new(bytes)).unwrap();
+        assert_eq!(reader.n_frames(), 2);
+
+        // random access
+        let second = reader.read_frame(1).unwrap();
+        assert_eq!(second.step, Some(200));
+        assert_eq!(second.snapshot.metadata("timestep"), Some(&Attribute::Integer(200)));
+        let names: std::vec::Vec<_> = second.snapshot.particles.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["A", "Na", "A"]);
+        assert_eq!(second.snapshot.particles[1].xyz, Coordinate::Position{x: 4.5, y: 5.0, z: 6.0});
+        let lengths = second.simulation_box.as_ref().unwrap().lengths();
+        assert!((lengths - nalgebra::Vector3::new(10.0, 10.0, 10.0)).norm() < 1e-12);
+        assert!(reader.read_frame(2).is_err());
+
+        // sequential access
+        let frames = reader.collect::<std::vec::Vec<_>>();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].step, Some(100));
+        assert_eq!(frames[0].snapshot.particles[2].xyz, Coordinate::Position{x: 7.0, y: 8.0, z: 9.0});
+        assert_eq!(frames[0].velocities.as_ref().unwrap()[1], nalgebra::Vector3::new(0.0, 0.5, 0.0));
+        assert_eq!(frames[1], second);
+    }
+
+    #[test]
+    fn reject_sizes_beyond_file() {
+        let bytes = write_gsd(&[
+            ("particles/N",        0, GSD_UINT32, 1, uints(&[1])),
+            ("particles/position", 0, GSD_FLOAT, 3, floats(&[1.0, 2.0, 3.0])),
+        ]);
+        assert!(GsdReader::new(std::io::Cursor::new(bytes.clone())).unwrap().read_frame(0).is_ok());
+        let index_location = read_u64(&bytes[8 .. 16]) as usize;
+
+        let mut huge_index = bytes.clone();
+        huge_index[16 .. 24].copy_from_slice(&(u64::MAX / 2).to_le_bytes());
+        assert!(GsdReader::new(std::io::Cursor::new(huge_index)).is_err());
+
+        let mut huge_namelist = bytes.clone();
+        huge_namelist[32 .. 40].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(GsdReader::new(std::io::Cursor::new(huge_namelist)).is_err());
+
+        // the number of rows of the positions
+        let mut huge_chunk = bytes.clone();
+        let n = index_location + 32 + 8;
+        huge_chunk[n .. n + 8].copy_from_slice(&(1u64 << 60).to_le_bytes());
+        let mut reader = GsdReader::new(std::io::Cursor::new(huge_chunk)).unwrap();
+        assert!(reader.read_frame(0).is_err());
+    }
+
+    #[test]
+    fn reject_non_gsd() {
+        assert!(GsdReader::new(std::io::Cursor::new(vec![0u8; 256])).is_err());
+        assert!(GsdReader::new(std::io::Cursor::new(b"CDF\x02".to_vec())).is_err());
+    }
+}
@@ -3,8 +3,22 @@
 //! A library for molecular dynamics trajectory analysis.
 
 pub mod error;
+pub mod binary;
+pub mod parse;
 pub mod coordinate;
+pub mod boundary;
+pub mod neighbor;
+pub mod element;
 pub mod particle;
 pub mod snapshot;
 pub mod trajectory;
+pub mod reader;
 pub mod xyz;
+pub mod xtc;
+#[cfg(feature = "netcdf")]
+pub mod netcdf;
+#[cfg(feature = "gsd")]
+pub mod gsd;
+pub mod analysis;
+#[cfg(test)]
+mod fixture;
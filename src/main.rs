@@ -1,5 +1,16 @@
+use std::io::BufRead;
+
 fn main() {
-    let reader = trajan::xyz::XYZReader::open_pos("example.xyz").unwrap().f32();
+    // reads from the file if a path is given, otherwise from stdin so that it
+    // can be used in a pipeline like `cat traj.xyz | trajan`.
+    let stdin = std::io::stdin();
+    let input: Box<dyn BufRead> = match std::env::args().nth(1) {
+        Some(path) => Box::new(std::io::BufReader::new(
+            std::fs::File::open(path).expect("open an input file"))),
+        None       => Box::new(stdin.lock()),
+    };
+    let reader = trajan::xyz::XYZReader::from_bufread(
+        trajan::coordinate::CoordKind::Position, input).f32();
     let mut writer = trajan::xyz::XYZWriter::new(std::io::stdout());
 
     for snapshot in reader {
@@ -0,0 +1,195 @@
+//! Finds pairs of particles within a cutoff distance.
+//!
+//! Checking all the pairs takes O(N²) time. Here, the space is divided into
+//! cells whose width is not less than the cutoff, and only the particles in
+//! the same or adjacent cells are checked, that takes O(N) time for a system
+//! with a uniform density. If the system is too small to be divided into
+//! enough cells, all the pairs are checked instead.
+use crate::boundary::SimulationBox;
+
+/// Finds all the pairs `(i, j, distance)` with `i < j` whose distance is less
+/// than `cutoff`. If a box is given, the distance is calculated with the
+/// minimum image convention, so `cutoff` should not exceed a half of the box
+/// width. The pairs are sorted by `(i, j)`.
+///
+/// ```
+/// use trajan::neighbor::neighbor_pairs;
+/// let positions = vec![nalgebra::Vector3::new(0.0, 0.0, 0.0),
+///                      nalgebra::Vector3::new(0.5, 0.0, 0.0),
+///                      nalgebra::Vector3::new(5.0, 0.0, 0.0)];
+/// let pairs = neighbor_pairs(&positions, None, 1.0);
+/// assert_eq!(pairs, vec![(0, 1, 0.5)]);
+/// ```
+pub fn neighbor_pairs<T>(positions: &[nalgebra::Vector3<T>],
+                         boundary: Option<&SimulationBox<T>>,
+                         cutoff: T) -> std::vec::Vec<(usize, usize, T)>
+where
+    T: nalgebra::Real
+{
+    let distance = |i: usize, j: usize| {
+        let dr = positions[j] - positions[i];
+        match boundary {
+            Some(b) => b.minimum_image(dr).norm(),
+            None    => dr.norm(),
+        }
+    };
+
+    let mut pairs = std::vec::Vec::new();
+    match cell_indices(positions, boundary, cutoff) {
+        Some((cells, dims)) => {
+            let mut members: std::collections::HashMap<[i64; 3], std::vec::Vec<usize>> =
+                std::collections::HashMap::new();
+            for (i, cell) in cells.iter().enumerate() {
+                members.entry(*cell).or_default().push(i);
+            }
+            for (i, cell) in cells.iter().enumerate() {
+                for neighbor in adjacent_cells(*cell, dims) {
+                    if let Some(js) = members.get(&neighbor) {
+                        for &j in js.iter().filter(|&&j| i < j) {
+                            let d = distance(i, j);
+                            if d < cutoff {
+                                pairs.push((i, j, d));
+                            }
+                        }
+                    }
+                }
+            }
+            pairs.sort_by_key(|&(i, j, _)| (i, j));
+        }
+        None => {
+            for i in 0 .. positions.len() {
+                for j in i + 1 .. positions.len() {
+                    let d = distance(i, j);
+                    if d < cutoff {
+                        pairs.push((i, j, d));
+                    }
+                }
+            }
+        }
+    }
+    pairs
+}
+
+// The cell of each particle, and the number of cells along each axis in a
+// periodic box.
+type Cells = (std::vec::Vec<[i64; 3]>, std::option::Option<[i64; 3]>);
+
+// Assigns each particle to a cell. Returns None if the cell list does not
+// work well, i.e. the box is too small compared to the cutoff.
+fn cell_indices<T>(positions: &[nalgebra::Vector3<T>],
+                   boundary: Option<&SimulationBox<T>>,
+                   cutoff: T) -> std::option::Option<Cells>
+where
+    T: nalgebra::Real
+{
+    if positions.is_empty() || cutoff <= T::zero() {
+        return None;
+    }
+    let to_i64 = |x: T| -> std::option::Option<i64> {
+        let x: f64 = nalgebra::try_convert(x.floor())?;
+        if x.is_finite() && x.abs() < 1e15 {Some(x as i64)} else {None}
+    };
+    match boundary {
+        Some(b) => {
+            let widths = b.widths();
+            let mut dims = [0i64; 3];
+            for k in 0 .. 3 {
+                dims[k] = to_i64(widths[k] / cutoff)?;
+                if dims[k] < 3 {
+                    return None;
+                }
+            }
+            let cells = positions.iter().map(|r| {
+                let f = b.cartesian_to_fractional(r);
+                let mut cell = [0i64; 3];
+                for k in 0 .. 3 {
+                    let n = nalgebra::convert::<f64, T>(dims[k] as f64);
+                    cell[k] = to_i64((f[k] - f[k].floor()) * n)?.rem_euclid(dims[k]);
+                }
+                Some(cell)
+            }).collect::<std::option::Option<std::vec::Vec<_>>>()?;
+            Some((cells, Some(dims)))
+        }
+        None => {
+            let lower = positions.iter().skip(1)
+                .fold(positions[0], |acc, r| acc.zip_map(r, |a, b| a.min(b)));
+            let cells = positions.iter().map(|r| {
+                let d = (r - lower) / cutoff;
+                Some([to_i64(d.x)?, to_i64(d.y)?, to_i64(d.z)?])
+            }).collect::<std::option::Option<std::vec::Vec<_>>>()?;
+            Some((cells, None))
+        }
+    }
+}
+
+// Returns the cell and its 26 neighbors. In a periodic box, the indices are
+// wrapped. Since there are at least 3 cells along each axis, no cell appears
+// twice.
+fn adjacent_cells(cell: [i64; 3], dims: std::option::Option<[i64; 3]>)
+    -> impl Iterator<Item = [i64; 3]>
+{
+    (-1 ..= 1).flat_map(move |dx| (-1 ..= 1).flat_map(move |dy| (-1 ..= 1).map(move |dz| {
+        let mut next = [cell[0] + dx, cell[1] + dy, cell[2] + dz];
+        if let Some(dims) = dims {
+            for k in 0 .. 3 {
+                next[k] = next[k].rem_euclid(dims[k]);
+            }
+        }
+        next
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::uniform;
+
+    // deterministic pseudo-random positions in [0, 10)^3
+    fn positions(n: usize) -> std::vec::Vec<nalgebra::Vector3<f64>> {
+        let mut random = uniform(12345);
+        let mut next = move || random() * 10.0;
+        (0 .. n).map(|_| nalgebra::Vector3::new(next(), next(), next())).collect()
+    }
+
+    fn brute_force(positions: &[nalgebra::Vector3<f64>],
+                   boundary: Option<&SimulationBox<f64>>, cutoff: f64)
+        -> std::vec::Vec<(usize, usize, f64)>
+    {
+        let mut pairs = std::vec::Vec::new();
+        for i in 0 .. positions.len() {
+            for j in i + 1 .. positions.len() {
+                let dr = positions[j] - positions[i];
+                let d = boundary.map_or(dr, |b| b.minimum_image(dr)).norm();
+                if d < cutoff {
+                    pairs.push((i, j, d));
+                }
+            }
+        }
+        pairs
+    }
+
+    #[test]
+    fn cell_list_matches_brute_force() {
+        let ps = positions(300);
+        assert_eq!(neighbor_pairs(&ps, None, 1.5), brute_force(&ps, None, 1.5));
+
+        let b = SimulationBox::cuboid(nalgebra::Vector3::new(10.0, 10.0, 10.0)).unwrap();
+        let pairs = neighbor_pairs(&ps, Some(&b), 1.5);
+        assert_eq!(pairs, brute_force(&ps, Some(&b), 1.5));
+        // some of them are found across the boundary
+        assert!(pairs.iter().any(|&(i, j, _)| (ps[i] - ps[j]).norm() > 1.5));
+
+        let b = SimulationBox::new(nalgebra::Vector3::new(10.0, 0.0, 0.0),
+                                   nalgebra::Vector3::new(3.0, 10.0, 0.0),
+                                   nalgebra::Vector3::new(-2.0, 1.0, 10.0)).unwrap();
+        assert_eq!(neighbor_pairs(&ps, Some(&b), 1.5), brute_force(&ps, Some(&b), 1.5));
+    }
+
+    #[test]
+    fn small_box_falls_back() {
+        let ps = positions(50);
+        let b = SimulationBox::cuboid(nalgebra::Vector3::new(10.0, 10.0, 10.0)).unwrap();
+        assert_eq!(neighbor_pairs(&ps, Some(&b), 4.0), brute_force(&ps, Some(&b), 4.0));
+        assert!(neighbor_pairs(&[], Some(&b), 1.0).is_empty());
+    }
+}
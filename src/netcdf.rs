@@ -0,0 +1,517 @@
+//! Input of AMBER NetCDF trajectory file.
+//!
+//! AMBER writes trajectories in the NetCDF classic format (usually with
+//! 64-bit offsets), following the AMBER trajectory convention. This module
+//! contains a small parser of the classic format, so no native library is
+//! needed. NetCDF-4 (HDF5 based) files are not supported.
+//!
+//! The following variables are read.
+//!
+//! - `coordinates(frame, atom, spatial)` in Angstrom.
+//! - `time(frame)` in ps, if it exists.
+//! - `cell_lengths(frame, cell_spatial)` and `cell_angles(frame, cell_angular)`,
+//!   if they exist.
+//!
+//! Since each frame is placed at a known offset, any frame can be read
+//! directly by `AmberNcReader::read_frame`.
+//!
+//! # example
+//! ```no_run
+//! use trajan::netcdf::AmberNcReader;
+//! let mut reader = AmberNcReader::open("traj.nc").unwrap();
+//! let last = reader.read_frame(reader.n_frames() - 1).unwrap();
+//! println!("{} atoms at {:?} ps", last.snapshot.particles.len(), last.time);
+//! ```
+use crate::binary::{read_f32_endian, read_f64_endian, read_i32_endian, read_size_endian, Endian};
+use crate::boundary::SimulationBox;
+use crate::coordinate::Coordinate;
+use crate::error::{Error, Result};
+use crate::particle::Attribute;
+use crate::reader::TrajectoryReader;
+use crate::xyz::{XYZParticle, XYZSnapshot};
+
+// The name given to each particle, because the file does not contain names.
+const PARTICLE_NAME: &str = "X";
+
+/// A frame in an AMBER NetCDF trajectory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AmberNcFrame {
+    /// time in ps, if the file has it.
+    pub time: std::option::Option<f64>,
+    /// The box in Angstrom, if the file has it.
+    pub simulation_box: std::option::Option<SimulationBox<f64>>,
+    /// Positions in Angstrom. Particles are named "X". The time and box are
+    /// also stored in its metadata.
+    pub snapshot: XYZSnapshot<f64>,
+}
+
+/// Reads frames in an AMBER NetCDF trajectory, sequentially or randomly.
+pub struct AmberNcReader<R> {
+    reader:   R,
+    header:   Header,
+    n_atoms:  usize,
+    n_frames: usize,
+    next:     usize,
+    file_len: u64,
+}
+
+impl<R: std::io::Read + std::io::Seek> AmberNcReader<R> {
+    /// Reads the header and constructs AmberNcReader.
+    /// Fails if it is not a NetCDF classic file or does not have coordinates.
+    pub fn new(mut inner: R) -> Result<Self> {
+        let header = Header::read(&mut inner)?;
+
+        let coords = header.variable("coordinates").ok_or_else(|| Error::invalid_format(
+            "NetCDF file does not have `coordinates`".to_string()))?;
+        if coords.dims.len() != 3 || !header.is_record(coords) ||
+           header.dims[coords.dims[2]].len != 3 {
+            return Err(Error::invalid_format(
+                "`coordinates` should be (frame, atom, spatial)".to_string()));
+        }
+        let n_atoms  = header.dims[coords.dims[1]].len;
+        let file_len = inner.seek(std::io::SeekFrom::End(0))?;
+        let n_frames = match header.numrecs {
+            Some(n) => n,
+            None    => {
+                // the number of records is not written while streaming
+                let begin = header.vars.iter().filter(|v| header.is_record(v))
+                    .map(|v| v.begin).min().unwrap_or(file_len);
+                file_len.saturating_sub(begin).checked_div(header.recsize).unwrap_or(0) as usize
+            }
+        };
+        Ok(AmberNcReader{reader: inner, header, n_atoms, n_frames, next: 0, file_len})
+    }
+
+    /// Returns the number of frames in the file.
+    pub fn n_frames(&self) -> usize {
+        self.n_frames
+    }
+
+    /// Returns the number of atoms in each frame.
+    pub fn n_atoms(&self) -> usize {
+        self.n_atoms
+    }
+
+    /// Reads the i-th frame. Fails if i is out of range.
+    pub fn read_frame(&mut self, i: usize) -> Result<AmberNcFrame> {
+        if self.n_frames <= i {
+            return Err(Error::invalid_condition(format!(
+                "frame {} is out of range: the file has {} frames", i, self.n_frames)));
+        }
+        let coords = self.read_record("coordinates", i)?
+            .expect("coordinates exist");
+        let particles = coords.chunks(3).map(|r| XYZParticle::new(
+                PARTICLE_NAME.to_string(), Coordinate::Position{x: r[0], y: r[1], z: r[2]}
+            )).collect();
+
+        let time = self.read_record("time", i)?.and_then(|t| t.first().cloned());
+
+        let lengths = self.read_record("cell_lengths", i)?;
+        let angles  = self.read_record("cell_angles", i)?;
+        let simulation_box = match (lengths, angles) {
+            (Some(ref l), Some(ref a)) if l.len() == 3 && a.len() == 3 => {
+                Some(SimulationBox::from_lengths_angles(
+                    nalgebra::Vector3::new(l[0], l[1], l[2]),
+                    nalgebra::Vector3::new(a[0], a[1], a[2]))?)
+            }
+            _ => None,
+        };
+        let mut snapshot = XYZSnapshot::new(std::string::String::new(), particles);
+        if let Some(t) = time {
+            snapshot.set_metadata("time", Attribute::Float(t));
+        }
+        if let Some(ref b) = simulation_box {
+            snapshot.set_metadata("box", Attribute::Matrix(*b.lattice()));
+        }
+        Ok(AmberNcFrame{time, simulation_box, snapshot})
+    }
+
+    /// Reads the next frame. Returns None if all the frames are read.
+    pub fn read_next(&mut self) -> Result<std::option::Option<AmberNcFrame>> {
+        if self.n_frames <= self.next {
+            return Ok(None);
+        }
+        let frame = self.read_frame(self.next)?;
+        self.next += 1;
+        Ok(Some(frame))
+    }
+
+    // Reads values of a record variable at the record. Returns None if the
+    // variable does not exist.
+    fn read_record(&mut self, name: &str, record: usize)
+        -> Result<std::option::Option<std::vec::Vec<f64>>>
+    {
+        let var = match self.header.variable(name) {
+            Some(var) if self.header.is_record(var) => var,
+            _ => return Ok(None),
+        };
+        let count = self.header.slice_len(var)?;
+        let offset = (record as u64).checked_mul(self.header.recsize)
+            .and_then(|o| o.checked_add(var.begin))
+            .ok_or_else(|| Error::invalid_format(format!(
+                "offset of record {} of `{}` overflows", record, name)))?;
+        let nc_type = var.nc_type;
+
+        // a broken header may claim more values than the file has
+        let remaining = self.file_len.saturating_sub(offset) / type_size(nc_type)? as u64;
+        self.reader.seek(std::io::SeekFrom::Start(offset))?;
+        let mut values = std::vec::Vec::with_capacity(count.min(remaining as usize));
+        for _ in 0 .. count {
+            values.push(read_value(&mut self.reader, nc_type)?);
+        }
+        Ok(Some(values))
+    }
+}
+
+impl AmberNcReader<std::io::BufReader<std::fs::File>> {
+    /// Opens file and constructs AmberNcReader by using the file.
+    pub fn open<P>(path: P) -> Result<Self>
+    where
+        P: std::convert::AsRef<std::path::Path>
+    {
+        let f = std::fs::File::open(path)?;
+        AmberNcReader::new(std::io::BufReader::new(f))
+    }
+}
+
+/// Enables AmberNcReader to be used as a generic `TrajectoryReader`.
+/// The time and box are available through `Snapshot::metadata`.
+impl<R: std::io::Read + std::io::Seek> TrajectoryReader<f64> for AmberNcReader<R> {
+    type Snapshot = XYZSnapshot<f64>;
+    fn read_snapshot(&mut self) -> Result<std::option::Option<Self::Snapshot>> {
+        Ok(self.read_next()?.map(|frame| frame.snapshot))
+    }
+}
+
+/// Enables AmberNcReader to be used as a Iterator of AmberNcFrame.
+/// It stops at the end of the file or at the first error.
+impl<R: std::io::Read + std::io::Seek> std::iter::Iterator for AmberNcReader<R> {
+    type Item = AmberNcFrame;
+    fn next(&mut self) -> std::option::Option<Self::Item> {
+        self.read_next().ok().and_then(|frame| frame)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// NetCDF classic format
+//
+// All the values are big-endian. See the NetCDF classic format specification
+// for the details of the header.
+
+const NC_DIMENSION: i32 = 0x0A;
+const NC_VARIABLE:  i32 = 0x0B;
+const NC_ATTRIBUTE: i32 = 0x0C;
+
+const NC_BYTE:   i32 = 1;
+const NC_CHAR:   i32 = 2;
+const NC_SHORT:  i32 = 3;
+const NC_INT:    i32 = 4;
+const NC_FLOAT:  i32 = 5;
+const NC_DOUBLE: i32 = 6;
+
+#[derive(Debug)]
+struct Dimension {
+    #[allow(dead_code)]
+    name: std::string::String,
+    len:  usize, // zero for the record dimension
+}
+
+#[derive(Debug)]
+struct Variable {
+    name:    std::string::String,
+    dims:    std::vec::Vec<usize>,
+    nc_type: i32,
+    begin:   u64,
+}
+
+#[derive(Debug)]
+struct Header {
+    numrecs: std::option::Option<usize>, // None while streaming
+    dims:    std::vec::Vec<Dimension>,
+    vars:    std::vec::Vec<Variable>,
+    recsize: u64,
+}
+
+impl Header {
+    fn read<R: std::io::Read>(reader: &mut R) -> Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        let offset64 = match magic {
+            [b'C', b'D', b'F', 1] => false,
+            [b'C', b'D', b'F', 2] => true,
+            [0x89, b'H', b'D', b'F'] => return Err(Error::invalid_format(
+                "NetCDF-4 (HDF5) format is not supported".to_string())),
+            _ => return Err(Error::invalid_format(
+                "not a NetCDF classic format file".to_string())),
+        };
+        let numrecs = match read_i32_endian(reader, Endian::Big)? {
+            -1 => None,
+            n if n < 0 => return Err(Error::invalid_format(format!(
+                "invalid number of records: {}", n))),
+            n  => Some(n as usize),
+        };
+
+        let mut dims = std::vec::Vec::new();
+        for _ in 0 .. read_list_header(reader, NC_DIMENSION)? {
+            let name = read_name(reader)?;
+            let len  = read_size_endian(reader, Endian::Big)?;
+            dims.push(Dimension{name, len});
+        }
+        skip_attributes(reader)?;
+
+        let mut vars = std::vec::Vec::new();
+        for _ in 0 .. read_list_header(reader, NC_VARIABLE)? {
+            let name = read_name(reader)?;
+            let ndims = read_size_endian(reader, Endian::Big)?;
+            let mut var_dims = std::vec::Vec::with_capacity(ndims);
+            for _ in 0 .. ndims {
+                let id = read_size_endian(reader, Endian::Big)?;
+                if dims.len() <= id {
+                    return Err(Error::invalid_format(format!(
+                        "invalid dimension id {} in variable {}", id, name)));
+                }
+                var_dims.push(id);
+            }
+            skip_attributes(reader)?;
+            let nc_type = read_i32_endian(reader, Endian::Big)?;
+            type_size(nc_type)?;
+            let _vsize  = read_i32_endian(reader, Endian::Big)?;
+            let begin = if offset64 {
+                let hi = read_i32_endian(reader, Endian::Big)? as u32;
+                let lo = read_i32_endian(reader, Endian::Big)? as u32;
+                (u64::from(hi) << 32) | u64::from(lo)
+            } else {
+                u64::from(read_i32_endian(reader, Endian::Big)? as u32)
+            };
+            vars.push(Variable{name, dims: var_dims, nc_type, begin});
+        }
+
+        let mut header = Header{numrecs, dims, vars, recsize: 0};
+        header.recsize = header.record_size()?;
+        Ok(header)
+    }
+
+    fn variable(&self, name: &str) -> std::option::Option<&Variable> {
+        self.vars.iter().find(|v| v.name == name)
+    }
+
+    fn is_record(&self, var: &Variable) -> bool {
+        var.dims.first().is_some_and(|d| self.dims[*d].len == 0)
+    }
+
+    // The number of values in a slice of a record variable.
+    fn slice_len(&self, var: &Variable) -> Result<usize> {
+        var.dims[1 ..].iter().try_fold(1usize, |n, d| n.checked_mul(self.dims[*d].len))
+            .ok_or_else(|| Error::invalid_format(format!(
+                "the size of `{}` overflows", var.name)))
+    }
+
+    // The size of a record, that contains one slice of every record variable.
+    fn record_size(&self) -> Result<u64> {
+        let records = self.vars.iter().filter(|v| self.is_record(v)).collect::<std::vec::Vec<_>>();
+        let mut size: u64 = 0;
+        for var in records.iter() {
+            size = self.slice_len(var)?.checked_mul(type_size(var.nc_type)?)
+                .map(|b| b as u64)
+                // each slice is padded to 4 bytes, unless it is the only one
+                .map(|b| if records.len() == 1 {b} else {b.div_ceil(4) * 4})
+                .and_then(|b| size.checked_add(b))
+                .ok_or_else(|| Error::invalid_format(format!(
+                    "the size of `{}` overflows", var.name)))?;
+        }
+        Ok(size)
+    }
+}
+
+fn type_size(nc_type: i32) -> Result<usize> {
+    match nc_type {
+        NC_BYTE | NC_CHAR => Ok(1),
+        NC_SHORT          => Ok(2),
+        NC_INT | NC_FLOAT => Ok(4),
+        NC_DOUBLE         => Ok(8),
+        _ => Err(Error::invalid_format(format!("unknown NetCDF type: {}", nc_type))),
+    }
+}
+
+fn read_value<R: std::io::Read>(reader: &mut R, nc_type: i32) -> Result<f64> {
+    match nc_type {
+        NC_FLOAT  => Ok(f64::from(read_f32_endian(reader, Endian::Big)?)),
+        NC_DOUBLE => read_f64_endian(reader, Endian::Big),
+        NC_INT    => Ok(f64::from(read_i32_endian(reader, Endian::Big)?)),
+        NC_SHORT  => {
+            let mut buf = [0u8; 2];
+            reader.read_exact(&mut buf)?;
+            Ok(f64::from(i16::from_be_bytes(buf)))
+        }
+        _ => Err(Error::invalid_format(format!(
+            "NetCDF type {} cannot be read as a number", nc_type))),
+    }
+}
+
+// Reads the tag and the number of elements of a list. An absent list is
+// written as two zeros.
+fn read_list_header<R: std::io::Read>(reader: &mut R, tag: i32) -> Result<usize> {
+    let found = read_i32_endian(reader, Endian::Big)?;
+    let n     = read_size_endian(reader, Endian::Big)?;
+    if found == tag || (found == 0 && n == 0) {
+        Ok(n)
+    } else {
+        Err(Error::invalid_format(format!(
+            "invalid NetCDF header: expected tag {}, found {}", tag, found)))
+    }
+}
+
+fn read_padded<R: std::io::Read>(reader: &mut R, len: usize) -> Result<std::vec::Vec<u8>> {
+    let mut bytes = vec![0u8; len.div_ceil(4) * 4];
+    reader.read_exact(&mut bytes)?;
+    bytes.truncate(len);
+    Ok(bytes)
+}
+
+fn read_name<R: std::io::Read>(reader: &mut R) -> Result<std::string::String> {
+    let len = read_size_endian(reader, Endian::Big)?;
+    std::string::String::from_utf8(read_padded(reader, len)?).map_err(|e| Error::invalid_format(
+        format!("invalid name in NetCDF header: {}", e)))
+}
+
+fn skip_attributes<R: std::io::Read>(reader: &mut R) -> Result<()> {
+    for _ in 0 .. read_list_header(reader, NC_ATTRIBUTE)? {
+        read_name(reader)?;
+        let nc_type = read_i32_endian(reader, Endian::Big)?;
+        let n = read_size_endian(reader, Endian::Big)?;
+        read_padded(reader, n * type_size(nc_type)?)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::Snapshot;
+
+    // (time, coordinates, cell_lengths, cell_angles)
+    type Frame = (f32, std::vec::Vec<[f32; 3]>, [f64; 3], [f64; 3]);
+
+    // Writes a minimal AMBER NetCDF file (64-bit offset) with the frames.
+    fn write_amber_nc(frames: &[Frame])
+        -> std::vec::Vec<u8>
+    {
+        fn int(b: &mut std::vec::Vec<u8>, v: i32) {b.extend_from_slice(&v.to_be_bytes());}
+        fn name(b: &mut std::vec::Vec<u8>, s: &str) {
+            int(b, s.len() as i32);
+            b.extend_from_slice(s.as_bytes());
+            while !b.len().is_multiple_of(4) {b.push(0);}
+        }
+        let n_atoms = frames[0].1.len();
+        let mut b = std::vec::Vec::new();
+        b.extend_from_slice(b"CDF\x02");
+        int(&mut b, frames.len() as i32);
+
+        // frame, spatial, atom, cell_spatial, cell_angular
+        let dims = [("frame", 0), ("spatial", 3), ("atom", n_atoms),
+                    ("cell_spatial", 3), ("cell_angular", 3)];
+        int(&mut b, NC_DIMENSION);
+        int(&mut b, dims.len() as i32);
+        for (n, len) in dims.iter() {
+            name(&mut b, n);
+            int(&mut b, *len as i32);
+        }
+        // global attribute: Conventions = "AMBER"
+        int(&mut b, NC_ATTRIBUTE);
+        int(&mut b, 1);
+        name(&mut b, "Conventions");
+        int(&mut b, NC_CHAR);
+        name(&mut b, "AMBER");
+
+        let vars: [(&str, &[i32], i32, usize); 4] = [
+            ("time",         &[0],       NC_FLOAT,  4),
+            ("coordinates",  &[0, 2, 1], NC_FLOAT,  n_atoms * 12),
+            ("cell_lengths", &[0, 3],    NC_DOUBLE, 24),
+            ("cell_angles",  &[0, 4],    NC_DOUBLE, 24),
+        ];
+        // the size of header: fixed part + variables (name, dims, atts, type, vsize, begin)
+        let mut header_size = b.len() + 8;
+        for (n, d, _, _) in vars.iter() {
+            header_size += 4 + n.len().div_ceil(4) * 4 + 4 + 4 * d.len() + 8 + 4 + 4 + 8;
+        }
+        int(&mut b, NC_VARIABLE);
+        int(&mut b, vars.len() as i32);
+        let mut begin = header_size as u64;
+        for (n, d, t, size) in vars.iter() {
+            name(&mut b, n);
+            int(&mut b, d.len() as i32);
+            for id in d.iter() {
+                int(&mut b, *id);
+            }
+            int(&mut b, 0); // no attribute
+            int(&mut b, 0);
+            int(&mut b, *t);
+            int(&mut b, *size as i32);
+            b.extend_from_slice(&begin.to_be_bytes());
+            begin += *size as u64;
+        }
+        assert_eq!(b.len(), header_size);
+
+        for (time, coords, lengths, angles) in frames.iter() {
+            b.extend_from_slice(&time.to_be_bytes());
+            for c in coords.iter().flat_map(|c| c.iter()) {
+                b.extend_from_slice(&c.to_be_bytes());
+            }
+            for v in lengths.iter().chain(angles.iter()) {
+                b.extend_from_slice(&v.to_be_bytes());
+            }
+        }
+        b
+    }
+
+    #[test]
+    fn read_amber_netcdf() {
+        let bytes = write_amber_nc(&[
+            (0.0, vec![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]], [10.0, 10.0, 10.0], [90.0, 90.0, 90.0]),
+            (2.0, vec![[1.5, 2.5, 3.5], [4.5, 5.5, 6.5]], [11.0, 12.0, 13.0], [90.0, 90.0, 90.0]),
+        ]);
+        let mut reader = AmberNcReader::new(std::io::Cursor::new(bytes)).unwrap();
+        assert_eq!(reader.n_frames(), 2);
+        assert_eq!(reader.n_atoms(),  2);
+
+        // random access
+        let second = reader.read_frame(1).unwrap();
+        assert_eq!(second.time, Some(2.0));
+        assert_eq!(second.snapshot.metadata("time"), Some(&Attribute::Float(2.0)));
+        assert_eq!(second.snapshot.particles[1].xyz, Coordinate::Position{x: 4.5, y: 5.5, z: 6.5});
+        let lengths = second.simulation_box.as_ref().unwrap().lengths();
+        assert!((lengths - nalgebra::Vector3::new(11.0, 12.0, 13.0)).norm() < 1e-12);
+        assert!(reader.read_frame(2).is_err());
+
+        // sequential access
+        let frames = reader.collect::<std::vec::Vec<_>>();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].time, Some(0.0));
+        assert_eq!(frames[0].snapshot.particles[0].xyz, Coordinate::Position{x: 1.0, y: 2.0, z: 3.0});
+        assert_eq!(frames[1], second);
+    }
+
+    #[test]
+    fn reject_non_netcdf() {
+        assert!(AmberNcReader::new(std::io::Cursor::new(b"CDF\x05".to_vec())).is_err());
+        assert!(AmberNcReader::new(std::io::Cursor::new(b"\x89HDF\r\n".to_vec())).is_err());
+    }
+
+    #[test]
+    fn reject_truncated_netcdf() {
+        let bytes = write_amber_nc(&[
+            (0.0, vec![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]], [10.0, 10.0, 10.0], [90.0, 90.0, 90.0]),
+        ]);
+        let header_len = bytes.len() - 4 - 24 - 48;
+
+        let mut truncated = bytes[.. header_len + 8].to_vec();
+        let mut reader = AmberNcReader::new(std::io::Cursor::new(truncated.clone())).unwrap();
+        assert!(reader.read_frame(0).is_err());
+
+        // while streaming, the number of frames is estimated from the file size
+        truncated.truncate(header_len);
+        truncated[4 .. 8].copy_from_slice(&(-1i32).to_be_bytes());
+        let reader = AmberNcReader::new(std::io::Cursor::new(truncated)).unwrap();
+        assert_eq!(reader.n_frames(), 0);
+    }
+}
@@ -0,0 +1,100 @@
+//! Helpers to split a line of text trajectory formats into fields.
+//!
+//! Most formats separate fields by whitespace, but some use a delimiter like
+//! a comma or a tab, and others like PDB and GRO place each field at fixed
+//! columns. The parsers of those formats share `tokenize` to handle them in
+//! the same way.
+
+/// How fields are separated in a line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fields<'c> {
+    /// Fields are separated by one or more whitespaces. Empty fields never
+    /// appear.
+    Whitespace,
+    /// Fields are separated by the delimiter. Adjacent delimiters make an
+    /// empty field.
+    Delimiter(char),
+    /// Each field occupies the given range of bytes. A range that exceeds the
+    /// line is clipped, so short lines result in empty fields.
+    Columns(&'c [std::ops::Range<usize>]),
+}
+
+/// Splits `line` into fields. Each field is trimmed.
+///
+/// ```
+/// use trajan::parse::{tokenize, Fields};
+/// let fields: Vec<_> = tokenize("H, 1.0, 2.0, 3.0", &Fields::Delimiter(',')).collect();
+/// assert_eq!(fields, vec!["H", "1.0", "2.0", "3.0"]);
+/// ```
+pub fn tokenize<'l, 'c>(line: &'l str, fields: &Fields<'c>) -> Tokens<'l, 'c> {
+    let state = match *fields {
+        Fields::Whitespace       => State::Whitespace(line.split_whitespace()),
+        Fields::Delimiter(delim) => State::Delimiter(line.split(delim)),
+        Fields::Columns(columns) => State::Columns(line, columns.iter()),
+    };
+    Tokens{state}
+}
+
+/// An iterator over fields in a line, constructed by `tokenize`.
+pub struct Tokens<'l, 'c> {
+    state: State<'l, 'c>,
+}
+
+enum State<'l, 'c> {
+    Whitespace(std::str::SplitWhitespace<'l>),
+    Delimiter(std::str::Split<'l, char>),
+    Columns(&'l str, std::slice::Iter<'c, std::ops::Range<usize>>),
+}
+
+impl<'l, 'c> std::iter::Iterator for Tokens<'l, 'c> {
+    type Item = &'l str;
+    fn next(&mut self) -> std::option::Option<Self::Item> {
+        match self.state {
+            State::Whitespace(ref mut iter) => iter.next(),
+            State::Delimiter(ref mut iter)  => iter.next().map(|field| field.trim()),
+            State::Columns(line, ref mut iter) => iter.next().map(|range| {
+                let end   = range.end.min(line.len());
+                let start = range.start.min(end);
+                line.get(start .. end).unwrap_or("").trim()
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_by_whitespace() {
+        let fields: std::vec::Vec<_> =
+            tokenize("  H\t1.0   2.0 3.0\n", &Fields::Whitespace).collect();
+        assert_eq!(fields, vec!["H", "1.0", "2.0", "3.0"]);
+        assert_eq!(tokenize("   \n", &Fields::Whitespace).count(), 0);
+    }
+
+    #[test]
+    fn split_by_delimiter() {
+        let fields: std::vec::Vec<_> =
+            tokenize("H,1.0, 2.0 ,,3.0\n", &Fields::Delimiter(',')).collect();
+        assert_eq!(fields, vec!["H", "1.0", "2.0", "", "3.0"]);
+
+        let fields: std::vec::Vec<_> =
+            tokenize("C\t-1.0\t0.5\t2.0", &Fields::Delimiter('\t')).collect();
+        assert_eq!(fields, vec!["C", "-1.0", "0.5", "2.0"]);
+    }
+
+    #[test]
+    fn split_by_columns() {
+        // GRO: residue number, residue name, atom name, atom number, x, y, z
+        let columns = [0 .. 5, 5 .. 10, 10 .. 15, 15 .. 20, 20 .. 28, 28 .. 36, 36 .. 44];
+        let line = "    1SOL     OW    1   0.126   1.624   1.679\n";
+        let fields: std::vec::Vec<_> = tokenize(line, &Fields::Columns(&columns)).collect();
+        assert_eq!(fields, vec!["1", "SOL", "OW", "1", "0.126", "1.624", "1.679"]);
+
+        // short lines result in empty fields
+        let fields: std::vec::Vec<_> =
+            tokenize("    1SOL", &Fields::Columns(&columns[.. 3])).collect();
+        assert_eq!(fields, vec!["1", "SOL", ""]);
+    }
+}
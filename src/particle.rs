@@ -8,6 +8,7 @@
 //!
 //! Here, a trait that all the `SomeParticle` struct must implement. Through
 //! this, all the data format can be accessible in the uniform way.
+use crate::element::Element;
 
 /// An enum to have a value that might be contained in a file.
 ///
@@ -15,13 +16,78 @@
 /// other kind of parameters ... ). To get some additional parameters, we need
 /// this kind of struct. `std::any::Any` might be enough for this purpose, but
 /// to improve the efficiency, it is implemented in enum.
+///
+/// Two `Other`s are equal only if they are the same value in memory, because
+/// the contents cannot be compared in general.
 #[derive(Debug)]
 pub enum Attribute {
     Float(f64),
     Integer(i64),
     String(std::string::String),
     Vector(nalgebra::Vector3<f64>),
-    Other(std::boxed::Box<std::any::Any + std::marker::Send + std::marker::Sync>),
+    Matrix(nalgebra::Matrix3<f64>),
+    Other(std::boxed::Box<dyn std::any::Any + std::marker::Send + std::marker::Sync>),
+}
+
+impl std::cmp::PartialEq for Attribute {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Attribute::Float(x),   Attribute::Float(y))   => x == y,
+            (Attribute::Integer(x), Attribute::Integer(y)) => x == y,
+            (Attribute::String(x),  Attribute::String(y))  => x == y,
+            (Attribute::Vector(x),  Attribute::Vector(y))  => x == y,
+            (Attribute::Matrix(x),  Attribute::Matrix(y))  => x == y,
+            (Attribute::Other(x),   Attribute::Other(y))   => std::ptr::addr_eq(x.as_ref(), y.as_ref()),
+            _ => false,
+        }
+    }
+}
+
+impl Attribute {
+    /// Returns the value as a float. An `Integer` is converted into a float.
+    /// Returns None for the other variants.
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Attribute::Float(x)   => Some(*x),
+            Attribute::Integer(x) => Some(*x as f64),
+            _ => None,
+        }
+    }
+    /// Returns the value if it is an `Integer`.
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Attribute::Integer(x) => Some(*x),
+            _ => None,
+        }
+    }
+    /// Returns the value if it is a `String`.
+    pub fn as_string(&self) -> Option<&str> {
+        match self {
+            Attribute::String(x) => Some(x.as_str()),
+            _ => None,
+        }
+    }
+    /// Returns the value if it is a `Vector`.
+    pub fn as_vector(&self) -> Option<&nalgebra::Vector3<f64>> {
+        match self {
+            Attribute::Vector(x) => Some(x),
+            _ => None,
+        }
+    }
+    /// Returns the value if it is a `Matrix`.
+    pub fn as_matrix(&self) -> Option<&nalgebra::Matrix3<f64>> {
+        match self {
+            Attribute::Matrix(x) => Some(x),
+            _ => None,
+        }
+    }
+    /// Returns the value if it is an `Other` that contains `U`.
+    pub fn downcast_ref<U: std::any::Any>(&self) -> Option<&U> {
+        match self {
+            Attribute::Other(x) => x.downcast_ref::<U>(),
+            _ => None,
+        }
+    }
 }
 
 /// A trait that should be implemented for all the `Particle` classes to provide
@@ -33,4 +99,81 @@ pub trait Particle<T: nalgebra::Scalar> {
     fn vel(&self) -> Option<nalgebra::Vector3<T>>;
     fn force(&self) -> Option<nalgebra::Vector3<T>>;
     fn attribute(&self, name: &str) -> Option<Attribute>;
+
+    /// Guesses the element from the "name" attribute by
+    /// `Element::from_name`. Returns None if the particle does not have a
+    /// name or the name is not recognized.
+    fn element(&self) -> Option<Element> {
+        Element::from_name(self.attribute("name")?.as_string()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attribute_accessors() {
+        let f = Attribute::Float(1.5);
+        assert_eq!(f.as_float(),   Some(1.5));
+        assert_eq!(f.as_integer(), None);
+        assert_eq!(f.as_string(),  None);
+        assert_eq!(f.as_vector(),  None);
+
+        let i = Attribute::Integer(42);
+        assert_eq!(i.as_float(),   Some(42.0));
+        assert_eq!(i.as_integer(), Some(42));
+        assert_eq!(i.as_string(),  None);
+        assert_eq!(i.as_vector(),  None);
+
+        let s = Attribute::String("CA".to_string());
+        assert_eq!(s.as_float(),   None);
+        assert_eq!(s.as_integer(), None);
+        assert_eq!(s.as_string(),  Some("CA"));
+        assert_eq!(s.as_vector(),  None);
+
+        let v = Attribute::Vector(nalgebra::Vector3::new(1.0, 2.0, 3.0));
+        assert_eq!(v.as_float(),   None);
+        assert_eq!(v.as_integer(), None);
+        assert_eq!(v.as_string(),  None);
+        assert_eq!(v.as_vector(),  Some(&nalgebra::Vector3::new(1.0, 2.0, 3.0)));
+        assert_eq!(v.as_matrix(),  None);
+
+        let m = Attribute::Matrix(nalgebra::Matrix3::identity());
+        assert_eq!(m.as_vector(),  None);
+        assert_eq!(m.as_matrix(),  Some(&nalgebra::Matrix3::identity()));
+
+        let o = Attribute::Other(std::boxed::Box::new(0u8));
+        assert_eq!(o.as_float(),   None);
+        assert_eq!(o.as_integer(), None);
+        assert_eq!(o.as_string(),  None);
+        assert_eq!(o.as_vector(),  None);
+        assert_eq!(o.downcast_ref::<u8>(),  Some(&0u8));
+        assert_eq!(o.downcast_ref::<u16>(), None);
+    }
+
+    #[test]
+    fn attribute_equality() {
+        assert_eq!(Attribute::Integer(1), Attribute::Integer(1));
+        assert_ne!(Attribute::Integer(1), Attribute::Float(1.0));
+
+        // metadata shares Other values between clones of a snapshot
+        let o = std::sync::Arc::new(Attribute::Other(std::boxed::Box::new(0u8)));
+        assert_eq!(o, o.clone());
+        assert_ne!(*o, Attribute::Other(std::boxed::Box::new(0u8)));
+    }
+
+    #[test]
+    fn element_from_particle_name() {
+        use crate::coordinate::Coordinate;
+        use crate::xyz::XYZParticle;
+        let particle = |name: &str| XYZParticle::new(name.to_string(),
+            Coordinate::Position{x: 0.0_f64, y: 0.0, z: 0.0});
+
+        let carbon = Element::from_atomic_number(6);
+        assert_eq!(particle("C").element(),  carbon);
+        assert_eq!(particle("c").element(),  carbon);
+        assert_eq!(particle("C1").element(), carbon);
+        assert_eq!(particle("Zz").element(), None);
+    }
 }
@@ -0,0 +1,66 @@
+//! A module to read trajectories in any kind of format in the same way.
+//!
+//! Each format has its own reader (e.g. `trajan::xyz::XYZReader`). By
+//! implementing `TrajectoryReader`, they can be consumed by generic code that
+//! does not know the format, like an analysis driver or a format converter.
+//!
+//! The type of snapshot is an associated type, so no boxing is required and
+//! the concrete snapshot is available when the format is known at compile
+//! time.
+use crate::error::Result;
+use crate::particle::Particle;
+use crate::snapshot::Snapshot;
+
+/// A trait to read snapshots one by one from any kind of trajectory file.
+pub trait TrajectoryReader<T>
+where
+    T: nalgebra::Scalar,
+    <Self::Snapshot as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    /// The type of snapshot this reader yields.
+    type Snapshot: Snapshot<T>;
+
+    /// Reads the next snapshot. Returns None if it reaches to the end.
+    /// Fails if the file is formatted in an invalid way.
+    fn read_snapshot(&mut self) -> Result<Option<Self::Snapshot>>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coordinate::CoordKind;
+    use crate::xyz::XYZReader;
+
+    fn count_snapshots<T, R>(reader: &mut R) -> Result<usize>
+    where
+        T: nalgebra::Scalar,
+        R: TrajectoryReader<T>,
+        <R::Snapshot as std::ops::Index<usize>>::Output: Particle<T>,
+    {
+        let mut n = 0;
+        while let Some(snapshot) = reader.read_snapshot()? {
+            assert!(!snapshot.is_empty());
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    #[test]
+    fn count_through_trait() {
+        let contents: &[u8] = b"\
+            1
+            t = 1
+            H 1.0 2.0 3.0
+            2
+            t = 2
+            H 1.1 2.1 3.1
+            C 3.1 2.1 1.1
+            ";
+        let mut reader = XYZReader::new(CoordKind::Position, contents).f64();
+        assert_eq!(count_snapshots(&mut reader).unwrap(), 2);
+
+        let broken: &[u8] = b"2\ncomment\nH 1.0 2.0 3.0\n";
+        let mut reader = XYZReader::new(CoordKind::Position, broken).f64();
+        assert!(count_snapshots(&mut reader).is_err());
+    }
+}
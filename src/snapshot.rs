@@ -4,9 +4,14 @@
 //! implementes trajan::particle::Particle trait.
 //!
 //! Through this, all the `SomeSnapshot` can be used in the same way.
+use crate::boundary::SimulationBox;
 use crate::particle::{Attribute, Particle};
 use std::option::Option;
 
+/// A matrix with 3 rows and a dynamic number of columns, returned by
+/// `Snapshot::position_matrix`. The nalgebra in use does not define it.
+pub type Matrix3xX<T> = nalgebra::MatrixMN<T, nalgebra::U3, nalgebra::Dynamic>;
+
 /// A trait to provide the same accessibility to any kind of snapshots.
 pub trait Snapshot<T>: std::ops::Index<usize>
 where
@@ -19,18 +24,250 @@ where
     /// returns how many particles are contained in the snapshot.
     fn len(&self)        -> usize;
 
+    /// returns true if the snapshot contains no particles.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     /// Collects mass of each particle if it exists.
     fn masses(&self)     -> Option<std::vec::Vec<T>>;
 
     /// Collects positions of each particle if it exists.
-    fn positions(&self)  -> Option<std::vec::Vec<nalgebra::Vector3<T>>>;
+    ///
+    /// By default, it collects `Particle::pos` of each particle, so a
+    /// particle that has both position and velocity appears in both
+    /// `positions` and `velocities`. Returns None if any particle does not
+    /// have it.
+    fn positions(&self)  -> Option<std::vec::Vec<nalgebra::Vector3<T>>> {
+        (0 .. self.len()).map(|i| self[i].pos()).collect()
+    }
 
     /// Collects velocities of each particle if it exists.
-    fn velocities(&self) -> Option<std::vec::Vec<nalgebra::Vector3<T>>>;
+    /// By default, it collects `Particle::vel` of each particle.
+    fn velocities(&self) -> Option<std::vec::Vec<nalgebra::Vector3<T>>> {
+        (0 .. self.len()).map(|i| self[i].vel()).collect()
+    }
 
     /// Collects forces of each particle if it exists.
-    fn forces(&self)     -> Option<std::vec::Vec<nalgebra::Vector3<T>>>;
+    /// By default, it collects `Particle::force` of each particle.
+    fn forces(&self)     -> Option<std::vec::Vec<nalgebra::Vector3<T>>> {
+        (0 .. self.len()).map(|i| self[i].force()).collect()
+    }
+
+    /// Collects positions into a matrix whose i-th column is the position of
+    /// the i-th particle, for analyses in linear algebra like PCA. Returns
+    /// None if `positions` does.
+    fn position_matrix(&self) -> Option<Matrix3xX<T>> {
+        let positions = self.positions()?;
+        Some(Matrix3xX::from_fn(positions.len(), |r, c| positions[c][r]))
+    }
+
+    /// Collects velocities into a matrix whose i-th column is the velocity of
+    /// the i-th particle. Returns None if `velocities` does.
+    fn velocity_matrix(&self) -> Option<Matrix3xX<T>> {
+        let velocities = self.velocities()?;
+        Some(Matrix3xX::from_fn(velocities.len(), |r, c| velocities[c][r]))
+    }
 
     /// Collects attributes of each particle if it exists.
     fn attributes(&self, name: &str) -> Option<std::vec::Vec<Attribute>>;
+
+    /// Returns a value about the snapshot itself, like "timestep", "time" or
+    /// "box", if it exists. Available keys depend on the format.
+    fn metadata(&self, _key: &str) -> Option<&Attribute> {
+        None
+    }
+
+    /// Returns the sum of masses. Returns None if masses are not available.
+    fn total_mass(&self) -> Option<T>
+    where
+        T: nalgebra::Real
+    {
+        Some(self.masses()?.iter().fold(T::zero(), |acc, m| acc + *m))
+    }
+
+    /// Returns the sum of the "charge" attribute. Returns None if any of the
+    /// particles does not have a charge.
+    fn net_charge(&self) -> Option<f64> {
+        self.attributes("charge")?.iter()
+            .try_fold(0.0, |acc, q| q.as_float().map(|q| acc + q))
+    }
+
+    /// Returns the largest magnitude of forces on particles. It is a standard
+    /// criterion of convergence in energy minimization. Returns None if the
+    /// snapshot is empty or does not have forces, and NaN if any force is NaN
+    /// so that a diverged step is not reported as converged.
+    fn max_force(&self) -> Option<T>
+    where
+        T: nalgebra::Real
+    {
+        self.forces()?.iter().map(|f| f.norm()).fold(None, |acc, f| match acc {
+            // NaN is not comparable even to itself. keep it once it appears
+            Some(m) if m.partial_cmp(&m).is_none() || f <= m => Some(m),
+            _ => Some(f),
+        })
+    }
+
+    /// Returns the root mean square of the magnitudes of forces. Returns None
+    /// if the snapshot is empty or does not have forces.
+    fn rms_force(&self) -> Option<T>
+    where
+        T: nalgebra::Real
+    {
+        let forces = self.forces()?;
+        if forces.is_empty() {
+            return None;
+        }
+        let sum = forces.iter().fold(T::zero(), |acc, f| acc + f.norm_squared());
+        Some((sum / nalgebra::convert::<f64, T>(forces.len() as f64)).sqrt())
+    }
+
+    /// Returns the vector from the i-th particle to the j-th one. If a box is
+    /// given, the minimum image is returned. It returns None if an index is
+    /// out of range or the particle does not have its position.
+    fn displacement(&self, i: usize, j: usize, boundary: Option<&SimulationBox<T>>)
+        -> Option<nalgebra::Vector3<T>>
+    where
+        T: nalgebra::Real
+    {
+        if self.len() <= i || self.len() <= j {
+            return None;
+        }
+        let dr = self[j].pos()? - self[i].pos()?;
+        Some(match boundary {
+            Some(b) => b.minimum_image(dr),
+            None    => dr,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coordinate::Coordinate;
+    use crate::fixture::{TestParticle, TestSnapshot};
+    use crate::xyz::{XYZParticle, XYZSnapshot};
+
+    // A particle that has both position and velocity.
+    fn bead(pos: [f64; 3], vel: [f64; 3]) -> TestParticle {
+        TestParticle{pos: Some(pos.into()), vel: Some(vel.into()), ..Default::default()}
+    }
+
+    // An atom that has mass and charge but no coordinates.
+    fn atom(mass: f64, charge: f64) -> TestParticle {
+        TestParticle{mass: Some(mass), charge: Some(charge), ..Default::default()}
+    }
+
+    #[test]
+    fn collect_coordinates_from_particles() {
+        let chain = TestSnapshot(vec![
+            bead([0.0, 0.0, 0.0], [1.0, 0.0, 0.0]),
+            bead([1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ]);
+        assert_eq!(chain.positions(), Some(vec![nalgebra::Vector3::new(0.0, 0.0, 0.0),
+                                                nalgebra::Vector3::new(1.0, 0.0, 0.0)]));
+        assert_eq!(chain.velocities(), Some(vec![nalgebra::Vector3::new(1.0, 0.0, 0.0),
+                                                 nalgebra::Vector3::new(0.0, 1.0, 0.0)]));
+        assert_eq!(chain.forces(), None);
+    }
+
+    #[test]
+    fn coordinates_as_matrix() {
+        let chain = TestSnapshot(vec![
+            bead([0.0, 1.0, 2.0], [1.0, 0.0, 0.0]),
+            bead([3.0, 4.0, 5.0], [0.0, 1.0, 0.0]),
+            bead([6.0, 7.0, 8.0], [0.0, 0.0, 1.0]),
+        ]);
+        let positions = chain.position_matrix().unwrap();
+        assert_eq!(positions.shape(), (3, 3));
+        assert_eq!(positions.column(0).into_owned(), nalgebra::Vector3::new(0.0, 1.0, 2.0));
+        assert_eq!(positions.column(1).into_owned(), nalgebra::Vector3::new(3.0, 4.0, 5.0));
+        assert_eq!(positions.column(2).into_owned(), nalgebra::Vector3::new(6.0, 7.0, 8.0));
+        let velocities = chain.velocity_matrix().unwrap();
+        assert_eq!(velocities.shape(), (3, 3));
+        assert_eq!(velocities.column(0).into_owned(), nalgebra::Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(velocities.column(1).into_owned(), nalgebra::Vector3::new(0.0, 1.0, 0.0));
+        assert_eq!(velocities.column(2).into_owned(), nalgebra::Vector3::new(0.0, 0.0, 1.0));
+
+        let system = TestSnapshot(vec![atom(1.0, 0.0)]);
+        assert_eq!(system.position_matrix(), None);
+        assert_eq!(system.velocity_matrix(), None);
+        assert_eq!(TestSnapshot(vec![]).position_matrix().unwrap().ncols(), 0);
+    }
+
+    #[test]
+    fn total_mass_and_charge() {
+        // sodium chloride and water
+        let system = TestSnapshot(vec![
+            atom(22.990,    1.0),
+            atom(35.453,   -1.0),
+            atom(15.999, -0.834),
+            atom( 1.008,  0.417),
+            atom( 1.008,  0.417),
+        ]);
+        assert!((system.total_mass().unwrap() - 76.458).abs() < 1e-10);
+        assert!(system.net_charge().unwrap().abs() < 1e-10);
+
+        // xyz snapshots have neither masses nor charges by default
+        let xyz = XYZSnapshot::new("".to_string(), vec![
+            XYZParticle::new("A".to_string(), Coordinate::Position{x: 0.0, y: 0.0, z: 0.0}),
+            XYZParticle::new("B".to_string(), Coordinate::Position{x: 1.0, y: 0.0, z: 0.0}),
+        ]);
+        assert_eq!(xyz.total_mass(), None);
+        assert_eq!(xyz.net_charge(), None);
+        let mut charged = xyz.clone();
+        charged.particles[0].charge = Some(0.5);
+        assert_eq!(charged.net_charge(), None);
+        charged.particles[1].charge = Some(-0.25);
+        assert_eq!(charged.net_charge(), Some(0.25));
+    }
+
+    #[test]
+    fn force_statistics() {
+        let forces = XYZSnapshot::new("".to_string(), vec![
+            XYZParticle::new("A".to_string(), Coordinate::Force{x: 3.0, y: 4.0, z: 0.0}),
+            XYZParticle::new("B".to_string(), Coordinate::Force{x: 0.0, y: 0.0, z: -1.0}),
+            XYZParticle::new("C".to_string(), Coordinate::Force{x: 1.0, y: 2.0, z: 2.0}),
+        ]);
+        assert_eq!(forces.max_force(), Some(5.0));
+        // sqrt((25 + 1 + 9) / 3)
+        let rms: f64 = forces.rms_force().unwrap();
+        assert!((rms - (35.0_f64 / 3.0).sqrt()).abs() < 1e-12);
+
+        let positions = XYZSnapshot::new("".to_string(), vec![
+            XYZParticle::new("A".to_string(), Coordinate::Position{x: 3.0, y: 4.0, z: 0.0}),
+        ]);
+        assert_eq!(positions.max_force(), None);
+        assert_eq!(positions.rms_force(), None);
+        let empty = XYZSnapshot::<f64>::new("".to_string(), vec![]);
+        assert_eq!(empty.max_force(), None);
+        assert_eq!(empty.rms_force(), None);
+
+        let diverged = XYZSnapshot::new("".to_string(), vec![
+            XYZParticle::new("A".to_string(), Coordinate::Force{x: f64::NAN, y: 0.0, z: 0.0}),
+            XYZParticle::new("B".to_string(), Coordinate::Force{x: 1.0, y: 0.0, z: 0.0}),
+        ]);
+        assert!(diverged.max_force().unwrap().is_nan());
+    }
+
+    #[test]
+    fn displacement_across_boundary() {
+        let snapshot = XYZSnapshot::new("".to_string(), vec![
+            XYZParticle::new("A".to_string(), Coordinate::Position{x: 0.5, y: 5.0, z: 5.0}),
+            XYZParticle::new("B".to_string(), Coordinate::Position{x: 9.5, y: 5.0, z: 5.0}),
+        ]);
+        let b = SimulationBox::cuboid(nalgebra::Vector3::new(10.0, 10.0, 10.0)).unwrap();
+
+        let dr = snapshot.displacement(0, 1, Some(&b)).unwrap();
+        assert!((dr - nalgebra::Vector3::new(-1.0, 0.0, 0.0)).norm() < 1e-12);
+        let dr = snapshot.displacement(0, 1, None).unwrap();
+        assert!((dr - nalgebra::Vector3::new(9.0, 0.0, 0.0)).norm() < 1e-12);
+
+        assert!(snapshot.displacement(0, 2, Some(&b)).is_none());
+        let velocities = XYZSnapshot::new("".to_string(), vec![
+            XYZParticle::new("A".to_string(), Coordinate::Velocity{x: 0.0, y: 0.0, z: 0.0}),
+            XYZParticle::new("B".to_string(), Coordinate::Velocity{x: 1.0, y: 0.0, z: 0.0}),
+        ]);
+        assert!(velocities.displacement(0, 1, None).is_none());
+    }
 }
@@ -4,8 +4,11 @@
 //! implementes trajan::snapshot::Snapshot trait.
 //!
 //! Through this, all the `SomeSnapshot` can be used in the same way.
-use crate::particle::Particle;
+use crate::boundary::SimulationBox;
+use crate::error::{Error, Result};
+use crate::particle::{Attribute, Particle};
 use crate::snapshot::Snapshot;
+use crate::xyz::XYZSnapshot;
 
 /// A trait to provide the same accessibility to any kind of snapshots.
 pub trait Trajectory<T>: std::ops::Index<usize>
@@ -19,4 +22,276 @@ where
 
     /// returns how many snapshots are contained in the trajectory.
     fn len(&self) -> usize;
+
+    /// returns true if the trajectory contains no snapshots.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// returns true if all the snapshots have the same number of particles.
+    ///
+    /// Some trajectories (e.g. reactive or grand-canonical MD) change the
+    /// number of particles between snapshots. Analyses that compare particles
+    /// across snapshots, like MSD or RMSD, require a fixed number.
+    fn is_fixed_count(&self) -> bool {
+        (1 .. self.len()).all(|i| self[i].len() == self[0].len())
+    }
+}
+
+/// A trajectory that holds all the snapshots in a `Vec`.
+///
+/// The number of particles may differ between snapshots. Use
+/// `Trajectory::is_fixed_count` to check it.
+///
+/// It can be collected from an iterator of snapshots, like a reader.
+/// ```no_run
+/// use trajan::xyz::XYZReader;
+/// use trajan::trajectory::VecTrajectory;
+/// let traj: VecTrajectory<_> = XYZReader::open_pos("example.xyz").unwrap().f64().collect();
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct VecTrajectory<S> {
+    /// snapshots contained in the trajectory.
+    pub snapshots: std::vec::Vec<S>,
+    /// Values about the whole trajectory, like simulation parameters. A time
+    /// step stored as "dt" (`Attribute::Float`) is used by `time`. Values are
+    /// shared between clones of the trajectory.
+    pub metadata: std::collections::HashMap<std::string::String, std::sync::Arc<Attribute>>,
+}
+
+impl<S> VecTrajectory<S> {
+    /// Constructs trajectory.
+    pub fn new(snapshots: std::vec::Vec<S>) -> Self {
+        VecTrajectory{snapshots, metadata: std::collections::HashMap::new()}
+    }
+
+    /// Sets a value about the trajectory, overwriting the previous one.
+    pub fn set_metadata(&mut self, key: &str, value: Attribute) {
+        self.metadata.insert(key.to_string(), std::sync::Arc::new(value));
+    }
+
+    /// Returns a value about the trajectory if it exists.
+    pub fn get_metadata(&self, key: &str) -> std::option::Option<&Attribute> {
+        self.metadata.get(key).map(|v| v.as_ref())
+    }
+}
+
+impl<T: nalgebra::Real> VecTrajectory<XYZSnapshot<T>> {
+    /// Applies `f` to the position of each particle in all the snapshots.
+    pub fn apply_transform<F>(&mut self, f: F)
+    where
+        F: Fn(&mut nalgebra::Vector3<T>)
+    {
+        for snapshot in self.snapshots.iter_mut() {
+            snapshot.apply_transform(&f);
+        }
+    }
+
+    /// Returns the time of the i-th snapshot. It is taken from the "time"
+    /// metadata of the snapshot, the comment line (see
+    /// `XYZSnapshot::parse_time`), or `i * dt` if the trajectory has "dt"
+    /// metadata, in this order. Returns None if none of them is available or
+    /// `i` is out of range.
+    pub fn time(&self, i: usize) -> std::option::Option<f64> {
+        let snapshot = self.snapshots.get(i)?;
+        snapshot.metadata.get("time").and_then(|t| t.as_float())
+            .or_else(|| snapshot.parse_time())
+            .or_else(|| Some(self.get_metadata("dt")?.as_float()? * i as f64))
+    }
+
+    /// Multiplies all the positions by `factor`, e.g. to convert units.
+    pub fn scale(&mut self, factor: T) {
+        self.apply_transform(|v| *v *= factor);
+    }
+
+    /// Removes consecutive snapshots that are approximately equal to the
+    /// previous one (see `XYZSnapshot::approx_eq`), e.g. repeated frames
+    /// written at restarts. The first one of a run is kept.
+    pub fn dedup_consecutive(&mut self, tol: T) {
+        self.snapshots.dedup_by(|next, prev| next.approx_eq(prev, tol));
+    }
+
+    /// Transforms all the positions `r` into `matrix * r + translation`.
+    pub fn affine(&mut self, matrix: &nalgebra::Matrix3<T>,
+                  translation: &nalgebra::Vector3<T>) {
+        self.apply_transform(|v| *v = matrix * *v + translation);
+    }
+
+    /// Translates each snapshot so that the center of mass of the selected
+    /// particles comes to the center of the box, then wraps all the
+    /// particles into the box.
+    ///
+    /// The center of mass of the selection is calculated with the minimum
+    /// image convention from the first selected particle, so the selection
+    /// may be split across the boundary. It is weighted by masses if the
+    /// snapshot has them.
+    ///
+    /// Fails if the selection is empty, an index is out of range, or a
+    /// snapshot does not have positions.
+    pub fn recenter_on(&mut self, indices: &[usize],
+                       boundary: &SimulationBox<T>) -> Result<()> {
+        if indices.is_empty() {
+            return Err(Error::invalid_condition(
+                "no particle is selected to recenter".to_string()));
+        }
+        for snapshot in self.snapshots.iter_mut() {
+            let positions = snapshot.positions().ok_or_else(|| Error::invalid_condition(
+                "snapshot does not contain positions".to_string()))?;
+            if let Some(i) = indices.iter().find(|&&i| positions.len() <= i) {
+                return Err(Error::invalid_condition(format!(
+                    "index {} is out of range: snapshot has {} particles",
+                    i, positions.len())));
+            }
+            let masses = snapshot.masses()
+                .unwrap_or_else(|| vec![T::one(); positions.len()]);
+
+            let origin = positions[indices[0]];
+            let mut total  = T::zero();
+            let mut offset = nalgebra::Vector3::zeros();
+            for &i in indices {
+                total  += masses[i];
+                offset += boundary.minimum_image(positions[i] - origin) * masses[i];
+            }
+            let shift = boundary.center() - (origin + offset / total);
+            snapshot.apply_transform(|r| *r = boundary.wrap(*r + shift));
+        }
+        Ok(())
+    }
+}
+
+impl<S> std::ops::Index<usize> for VecTrajectory<S> {
+    type Output = S;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.snapshots[index]
+    }
+}
+
+impl<S> std::ops::IndexMut<usize> for VecTrajectory<S> {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        &mut self.snapshots[index]
+    }
+}
+
+impl<S> std::iter::FromIterator<S> for VecTrajectory<S> {
+    fn from_iter<I: std::iter::IntoIterator<Item = S>>(iter: I) -> Self {
+        VecTrajectory::new(iter.into_iter().collect())
+    }
+}
+
+impl<T, S> Trajectory<T> for VecTrajectory<S>
+where
+    T: nalgebra::Scalar,
+    S: Snapshot<T>,
+    <S as std::ops::Index<usize>>::Output: Particle<T>,
+{
+    type Value = T;
+    fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coordinate::Coordinate;
+    use crate::xyz::{XYZParticle, XYZSnapshot};
+
+    #[test]
+    fn trajectory_metadata() {
+        let s = XYZSnapshot::<f64>::new("".to_string(), vec!["H 1.0 2.0 3.0".parse().unwrap()]);
+        let mut traj = VecTrajectory::new(vec![s.clone(), s.clone(), s.clone()]);
+        assert_eq!(traj.get_metadata("dt"), None);
+        assert_eq!(traj.time(1), None);
+
+        traj.set_metadata("dt", Attribute::Float(0.002));
+        traj.set_metadata("ensemble", Attribute::String("NVT".to_string()));
+        assert_eq!(traj.get_metadata("dt").and_then(|dt| dt.as_float()), Some(0.002));
+        assert_eq!(traj.get_metadata("ensemble").and_then(|e| e.as_string()), Some("NVT"));
+        assert_eq!(traj.time(2), Some(0.004));
+        assert_eq!(traj.time(3), None);
+
+        // the time of a snapshot has priority
+        traj.snapshots[1].comment = "time = 10.0".to_string();
+        assert_eq!(traj.time(1), Some(10.0));
+        traj.snapshots[1].set_metadata("time", Attribute::Float(20.0));
+        assert_eq!(traj.time(1), Some(20.0));
+    }
+
+    #[test]
+    fn fixed_count() {
+        let s1 = XYZSnapshot::<f64>::new("".to_string(), vec![
+            "H 1.0 2.0 3.0".parse().unwrap(),
+        ]);
+        let s2 = XYZSnapshot::<f64>::new("".to_string(), vec![
+            "H 1.0 2.0 3.0".parse().unwrap(),
+            "C 3.0 2.0 1.0".parse().unwrap(),
+        ]);
+        let fixed    = VecTrajectory::new(vec![s1.clone(), s1.clone()]);
+        let variable = VecTrajectory::new(vec![s1.clone(), s2.clone()]);
+        assert!(fixed.is_fixed_count());
+        assert!(!variable.is_fixed_count());
+        assert!(VecTrajectory::<XYZSnapshot<f64>>::new(vec![]).is_fixed_count());
+    }
+
+    #[test]
+    fn dedup_consecutive_snapshots() {
+        let frame = |x: f64| XYZSnapshot::new("".to_string(), vec![
+            XYZParticle::new("H".to_string(), Coordinate::Position{x, y: 0.0, z: 0.0}),
+        ]);
+        let mut traj = VecTrajectory::new(vec![
+            frame(0.0), frame(1.0), frame(1.0 + 1e-10), frame(2.0), frame(1.0)
+        ]);
+        traj.dedup_consecutive(1e-6);
+
+        let xs: std::vec::Vec<f64> = traj.snapshots.iter()
+            .map(|s| *s.particles[0].xyz.x()).collect();
+        assert_eq!(xs, vec![0.0, 1.0, 2.0, 1.0]);
+    }
+
+    #[test]
+    fn recenter_on_selection() {
+        // particles 0 and 1 are split across the boundary
+        let frame = |shift: f64| XYZSnapshot::new("".to_string(), vec![
+            XYZParticle::new("A".to_string(), Coordinate::Position{x: 9.5 + shift, y: 1.0, z: 1.0}),
+            XYZParticle::new("A".to_string(), Coordinate::Position{x: 0.5 + shift, y: 1.0, z: 1.0}),
+            XYZParticle::new("W".to_string(), Coordinate::Position{x: 3.0, y: 8.0, z: 2.0}),
+        ]);
+        let b = SimulationBox::cuboid(nalgebra::Vector3::new(10.0, 10.0, 10.0)).unwrap();
+        let mut traj = VecTrajectory::new(vec![frame(0.0), frame(0.2)]);
+        traj.recenter_on(&[0, 1], &b).unwrap();
+
+        for snapshot in traj.snapshots.iter() {
+            let ps = snapshot.positions().unwrap();
+            let com = (ps[0] + ps[1]) * 0.5;
+            assert!((com - b.center()).norm() < 1e-12);
+            for p in ps.iter() {
+                assert!(p.iter().all(|x| 0.0 <= *x && *x < 10.0));
+            }
+        }
+        assert!(traj.recenter_on(&[0, 3], &b).is_err());
+        assert!(traj.recenter_on(&[], &b).is_err());
+    }
+
+    #[test]
+    fn transform_all_snapshots() {
+        let s1 = XYZSnapshot::<f64>::new("".to_string(), vec![
+            "H 1.0 2.0 3.0".parse().unwrap(),
+        ]);
+        let s2 = XYZSnapshot::<f64>::new("".to_string(), vec![
+            "H -1.0 0.5 0.0".parse().unwrap(),
+        ]);
+        let mut traj = VecTrajectory::new(vec![s1, s2]);
+
+        traj.scale(2.0);
+        assert_eq!(traj[0].positions().unwrap(), vec![nalgebra::Vector3::new(2.0, 4.0, 6.0)]);
+        assert_eq!(traj[1].positions().unwrap(), vec![nalgebra::Vector3::new(-2.0, 1.0, 0.0)]);
+
+        let swap_xy = nalgebra::Matrix3::new(0.0, 1.0, 0.0,
+                                             1.0, 0.0, 0.0,
+                                             0.0, 0.0, 1.0);
+        traj.affine(&swap_xy, &nalgebra::Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(traj[0].positions().unwrap(), vec![nalgebra::Vector3::new(4.0, 2.0, 7.0)]);
+        assert_eq!(traj[1].positions().unwrap(), vec![nalgebra::Vector3::new(1.0, -2.0, 1.0)]);
+    }
 }
@@ -0,0 +1,574 @@
+//! Input of GROMACS xtc format file.
+//!
+//! An xtc file is a sequence of frames written in XDR (big-endian). Each frame
+//! has a header (magic number 1995, the number of atoms, step, time and box)
+//! followed by the coordinates compressed by the `xdr3dfcoord` algorithm.
+//! The coordinates are stored in nm as fixed-point integers with the
+//! precision written in the file, so the values read are exact only up to
+//! the precision (typically 0.001 nm).
+//!
+//! Only reading is supported.
+//!
+//! # example
+//! ```no_run
+//! use trajan::xtc::XtcReader;
+//! let mut reader = XtcReader::open("traj.xtc").unwrap();
+//! while let Some(frame) = reader.read_frame().unwrap() {
+//!     println!("step {}: {} atoms", frame.step, frame.snapshot.particles.len());
+//! }
+//! ```
+use crate::binary::{read_f32_endian, read_i32_endian, read_size_endian, Endian};
+use crate::boundary::SimulationBox;
+use crate::coordinate::Coordinate;
+use crate::error::{Error, Result};
+use crate::particle::Attribute;
+use crate::reader::TrajectoryReader;
+use crate::xyz::{XYZParticle, XYZSnapshot};
+
+const MAGIC: i32 = 1995;
+
+// The name given to each particle, because xtc does not contain names.
+const PARTICLE_NAME: &str = "X";
+
+/// A frame in an xtc file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct XtcFrame {
+    pub step: i32,
+    /// time in ps.
+    pub time: f32,
+    /// The box in nm. It is None if the box is not written (all zero).
+    pub simulation_box: std::option::Option<SimulationBox<f32>>,
+    /// Positions in nm. Particles are named "X". The step, time and box are
+    /// also stored in its metadata.
+    pub snapshot: XYZSnapshot<f32>,
+}
+
+/// Reads xtc frames one by one.
+pub struct XtcReader<R> {
+    reader: R,
+}
+
+impl<R: std::io::Read> XtcReader<R> {
+    /// constructing XtcReader. `inner` is read as it is, so it should be
+    /// buffered if it is slow, e.g. a file.
+    pub fn new(inner: R) -> Self {
+        XtcReader{reader: inner}
+    }
+
+    /// Reads the next frame. Returns None if it reaches to the end.
+    /// Fails if the file is truncated or formatted in an invalid way.
+    pub fn read_frame(&mut self) -> Result<std::option::Option<XtcFrame>> {
+        let magic = match self.read_magic()? {
+            Some(magic) => magic,
+            None        => return Ok(None),
+        };
+        if magic != MAGIC {
+            return Err(Error::invalid_format(format!(
+                "invalid magic number of xtc: {}", magic)));
+        }
+        let natoms = read_size_endian(&mut self.reader, Endian::Big)?;
+        let step   = read_i32_endian(&mut self.reader, Endian::Big)?;
+        let time   = read_f32_endian(&mut self.reader, Endian::Big)?;
+
+        let mut vectors = [nalgebra::Vector3::zeros(); 3];
+        for v in vectors.iter_mut() {
+            for i in 0 .. 3 {
+                v[i] = read_f32_endian(&mut self.reader, Endian::Big)?;
+            }
+        }
+        let simulation_box = if vectors.iter().all(|v| v.iter().all(|x| *x == 0.0)) {
+            None
+        } else {
+            Some(SimulationBox::new(vectors[0], vectors[1], vectors[2])?)
+        };
+
+        let coords = read_coords(&mut self.reader, natoms)?;
+        let particles = coords.into_iter().map(|r| XYZParticle::new(
+                PARTICLE_NAME.to_string(), Coordinate::Position{x: r[0], y: r[1], z: r[2]}
+            )).collect();
+
+        let mut snapshot = XYZSnapshot::new(std::string::String::new(), particles);
+        snapshot.set_metadata("timestep", Attribute::Integer(i64::from(step)));
+        snapshot.set_metadata("time", Attribute::Float(f64::from(time)));
+        if let Some(ref b) = simulation_box {
+            snapshot.set_metadata("box", Attribute::Matrix(b.lattice().map(f64::from)));
+        }
+        Ok(Some(XtcFrame{step, time, simulation_box, snapshot}))
+    }
+
+    // Reads the magic number. Returns None if no byte remains.
+    fn read_magic(&mut self) -> Result<std::option::Option<i32>> {
+        let mut buf = [0u8; 4];
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.reader.read(&mut buf[filled ..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(Error::from(e)),
+            }
+        }
+        match filled {
+            0 => Ok(None),
+            4 => Ok(Some(i32::from_be_bytes(buf))),
+            _ => Err(Error::invalid_format("xtc file is truncated".to_string())),
+        }
+    }
+}
+
+impl XtcReader<std::io::BufReader<std::fs::File>> {
+    /// Opens file and constructs XtcReader by using the file.
+    pub fn open<P>(path: P) -> Result<Self>
+    where
+        P: std::convert::AsRef<std::path::Path>
+    {
+        let f = std::fs::File::open(path)?;
+        Ok(XtcReader::new(std::io::BufReader::new(f)))
+    }
+}
+
+/// Enables XtcReader to be used as a generic `TrajectoryReader`.
+/// The step, time and box are available through `Snapshot::metadata`.
+impl<R: std::io::Read> TrajectoryReader<f32> for XtcReader<R> {
+    type Snapshot = XYZSnapshot<f32>;
+    fn read_snapshot(&mut self) -> Result<std::option::Option<Self::Snapshot>> {
+        Ok(self.read_frame()?.map(|frame| frame.snapshot))
+    }
+}
+
+/// Enables XtcReader to be used as a Iterator of XtcFrame.
+/// It stops at the end of the file or at the first error.
+impl<R: std::io::Read> std::iter::Iterator for XtcReader<R> {
+    type Item = XtcFrame;
+    fn next(&mut self) -> std::option::Option<Self::Item> {
+        self.read_frame().ok().and_then(|frame| frame)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// xdr3dfcoord decompression
+//
+// The algorithm follows the reference implementation (xdrfile.c). Each atom
+// is quantized as an integer triplet. The first atom of a group is written
+// with the number of bits enough to cover [minint, maxint], and following
+// atoms close to the previous one are written as small differences, whose
+// size adapts as the compression proceeds.
+
+const MAGICINTS: [i32; 73] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0,
+    8, 10, 12, 16, 20, 25, 32, 40, 50, 64,
+    80, 101, 128, 161, 203, 256, 322, 406, 512, 645,
+    812, 1024, 1290, 1625, 2048, 2580, 3250, 4096, 5060, 6501,
+    8192, 10321, 13003, 16384, 20642, 26007, 32768, 41285, 52015, 65536,
+    82570, 104031, 131072, 165140, 208063, 262144, 330280, 416127, 524287, 660561,
+    832255, 1048576, 1321122, 1664510, 2097152, 2642245, 3329021, 4194304, 5284491, 6658042,
+    8388607, 10568983, 13316085, 16777216,
+];
+const FIRSTIDX: usize = 9;
+
+fn read_coords<R: std::io::Read>(reader: &mut R, natoms: usize)
+    -> Result<std::vec::Vec<[f32; 3]>>
+{
+    let size = read_size_endian(reader, Endian::Big)?;
+    if size != natoms {
+        return Err(Error::invalid_format(format!(
+            "number of atoms differs in xtc header ({}) and coordinates ({})",
+            natoms, size)));
+    }
+
+    // small systems are not compressed
+    if size <= 9 {
+        let mut coords = std::vec::Vec::with_capacity(size);
+        for _ in 0 .. size {
+            coords.push([read_f32_endian(reader, Endian::Big)?,
+                         read_f32_endian(reader, Endian::Big)?,
+                         read_f32_endian(reader, Endian::Big)?]);
+        }
+        return Ok(coords);
+    }
+
+    let precision = read_f32_endian(reader, Endian::Big)?;
+    let mut minint = [0i32; 3];
+    let mut maxint = [0i32; 3];
+    for m in minint.iter_mut() {
+        *m = read_i32_endian(reader, Endian::Big)?;
+    }
+    for m in maxint.iter_mut() {
+        *m = read_i32_endian(reader, Endian::Big)?;
+    }
+    let mut smallidx = read_size_endian(reader, Endian::Big)?;
+    if smallidx < FIRSTIDX || MAGICINTS.len() <= smallidx {
+        return Err(Error::invalid_format(format!(
+            "invalid xtc compression parameter: {}", smallidx)));
+    }
+
+    // the buffer grows while reading, so that a corrupt size does not
+    // allocate more than the bytes that are actually there
+    let nbytes = read_size_endian(reader, Endian::Big)?;
+    let padded = nbytes.div_ceil(4) * 4; // padded to 4 bytes
+    let mut bytes = std::vec::Vec::new();
+    let mut chunk = std::io::Read::take(&mut *reader, padded as u64);
+    std::io::Read::read_to_end(&mut chunk, &mut bytes)?;
+    if bytes.len() < padded {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+    bytes.truncate(nbytes);
+
+    let mut sizeint = [0u32; 3];
+    for i in 0 .. 3 {
+        if maxint[i] < minint[i] {
+            return Err(Error::invalid_format(format!(
+                "invalid xtc coordinate range: {} > {}", minint[i], maxint[i])));
+        }
+        sizeint[i] = (i64::from(maxint[i]) - i64::from(minint[i]) + 1) as u32;
+    }
+    // if the range is too large, each element is written separately
+    let large = (sizeint[0] | sizeint[1] | sizeint[2]) > 0xff_ffff;
+    let bitsizeint = [sizeofint(sizeint[0]), sizeofint(sizeint[1]), sizeofint(sizeint[2])];
+    let bitsize = if large {0} else {sizeofints(&sizeint)};
+
+    let mut smaller  = MAGICINTS[FIRSTIDX.max(smallidx - 1)] / 2;
+    let mut smallnum = MAGICINTS[smallidx] / 2;
+    let mut sizesmall = [MAGICINTS[smallidx] as u32; 3];
+
+    let inv_precision = 1.0 / precision;
+    let to_real = |c: [i32; 3]| [c[0] as f32 * inv_precision,
+                                 c[1] as f32 * inv_precision,
+                                 c[2] as f32 * inv_precision];
+
+    let mut bits = BitReader::new(&bytes);
+    let mut coords = std::vec::Vec::with_capacity(size.min(nbytes));
+    // the length of a run is kept until the flag tells it changes
+    let mut run = 0;
+    while coords.len() < size {
+        let mut this = if large {
+            [bits.receive(bitsizeint[0])? as i32,
+             bits.receive(bitsizeint[1])? as i32,
+             bits.receive(bitsizeint[2])? as i32]
+        } else {
+            bits.receive_ints(bitsize, &sizeint)?
+        };
+        for i in 0 .. 3 {
+            this[i] = this[i].wrapping_add(minint[i]);
+        }
+        let mut prev = this;
+
+        let mut is_smaller = 0;
+        if bits.receive(1)? == 1 {
+            run = bits.receive(5)? as i32;
+            is_smaller = run % 3;
+            run -= is_smaller;
+            is_smaller -= 1;
+        }
+        if run > 0 {
+            if coords.len() + 1 + run as usize / 3 > size {
+                return Err(Error::invalid_format(
+                    "too many atoms in xtc compressed coordinates".to_string()));
+            }
+            for k in (0 .. run).step_by(3) {
+                let mut cur = bits.receive_ints(smallidx as u32, &sizesmall)?;
+                for i in 0 .. 3 {
+                    cur[i] = cur[i].wrapping_add(prev[i].wrapping_sub(smallnum));
+                }
+                if k == 0 {
+                    // the first and the second atoms are interchanged for the
+                    // better compression of water molecules
+                    std::mem::swap(&mut cur, &mut prev);
+                    coords.push(to_real(prev));
+                } else {
+                    prev = cur;
+                }
+                coords.push(to_real(cur));
+            }
+        } else {
+            coords.push(to_real(this));
+        }
+
+        if is_smaller != 0 {
+            // checked before indexing, because a corrupt flag can move it out
+            let next = if is_smaller < 0 {smallidx - 1} else {smallidx + 1};
+            if next < FIRSTIDX || MAGICINTS.len() <= next {
+                return Err(Error::invalid_format(format!(
+                    "invalid xtc compression parameter: {}", next)));
+            }
+            smallidx = next;
+            if is_smaller < 0 {
+                smallnum = smaller;
+                smaller  = if smallidx > FIRSTIDX {MAGICINTS[smallidx - 1] / 2} else {0};
+            } else {
+                smaller  = smallnum;
+                smallnum = MAGICINTS[smallidx] / 2;
+            }
+        }
+        sizesmall = [MAGICINTS[smallidx] as u32; 3];
+    }
+    Ok(coords)
+}
+
+// the number of bits needed to represent [0, size).
+fn sizeofint(size: u32) -> u32 {
+    let mut num: u64 = 1;
+    let mut bits = 0;
+    while u64::from(size) >= num && bits < 32 {
+        bits += 1;
+        num <<= 1;
+    }
+    bits
+}
+
+// the number of bits needed to represent the product of sizes.
+fn sizeofints(sizes: &[u32]) -> u32 {
+    let mut bytes = vec![1u32];
+    for &size in sizes {
+        let mut tmp: u64 = 0;
+        for byte in bytes.iter_mut() {
+            tmp += u64::from(*byte) * u64::from(size);
+            *byte = (tmp & 0xff) as u32;
+            tmp >>= 8;
+        }
+        while tmp != 0 {
+            bytes.push((tmp & 0xff) as u32);
+            tmp >>= 8;
+        }
+    }
+    let last = *bytes.last().expect("at least one byte");
+    let mut bits = 0;
+    let mut num = 1;
+    while last >= num {
+        bits += 1;
+        num *= 2;
+    }
+    bits + (bytes.len() as u32 - 1) * 8
+}
+
+// reads bits from the most significant one.
+struct BitReader<'a> {
+    bytes:    &'a [u8],
+    count:    usize,
+    lastbits: u32,
+    lastbyte: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        BitReader{bytes, count: 0, lastbits: 0, lastbyte: 0}
+    }
+
+    fn next_byte(&mut self) -> Result<u32> {
+        let byte = self.bytes.get(self.count).ok_or_else(|| Error::invalid_format(
+            "xtc compressed coordinates are truncated".to_string()))?;
+        self.count += 1;
+        Ok(u32::from(*byte))
+    }
+
+    fn receive(&mut self, nbits: u32) -> Result<u32> {
+        let mask = ((1u64 << nbits) - 1) as u32;
+        let mut nbits = nbits;
+        let mut num: u32 = 0;
+        while nbits >= 8 {
+            self.lastbyte = (self.lastbyte << 8) | self.next_byte()?;
+            num |= (self.lastbyte >> self.lastbits) << (nbits - 8);
+            nbits -= 8;
+        }
+        if nbits > 0 {
+            if self.lastbits < nbits {
+                self.lastbits += 8;
+                self.lastbyte = (self.lastbyte << 8) | self.next_byte()?;
+            }
+            self.lastbits -= nbits;
+            num |= (self.lastbyte >> self.lastbits) & ((1 << nbits) - 1);
+        }
+        Ok(num & mask)
+    }
+
+    // reads three integers packed as a mixed-radix number with `sizes`.
+    fn receive_ints(&mut self, nbits: u32, sizes: &[u32; 3]) -> Result<[i32; 3]> {
+        let mut bytes = std::vec::Vec::with_capacity(nbits as usize / 8 + 1);
+        let mut nbits = nbits;
+        while nbits > 8 {
+            bytes.push(self.receive(8)?);
+            nbits -= 8;
+        }
+        if nbits > 0 {
+            bytes.push(self.receive(nbits)?);
+        }
+        let mut nums = [0i32; 3];
+        for i in (1 .. 3).rev() {
+            let mut num: u64 = 0;
+            for byte in bytes.iter_mut().rev() {
+                num = (num << 8) | u64::from(*byte);
+                let p = num / u64::from(sizes[i]);
+                *byte = p as u32;
+                num -= p * u64::from(sizes[i]);
+            }
+            nums[i] = num as i32;
+        }
+        bytes.resize(4.max(bytes.len()), 0);
+        nums[0] = (bytes[0] | (bytes[1] << 8) | (bytes[2] << 16) | (bytes[3] << 24)) as i32;
+        Ok(nums)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::Snapshot;
+
+    // A frame of 10 atoms compressed with precision 1000 by a C port of the
+    // compression in xdr3dfcoord. It contains runs of small differences,
+    // including a change of their size.
+    const COMPRESSED: [u8; 128] = [
+        0x00, 0x00, 0x07, 0xcb, 0x00, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x00, 0x2a,
+        0x3f, 0xc0, 0x00, 0x00, 0x40, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x80, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x40, 0xa0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0a, 0x44, 0x7a, 0x00, 0x00,
+        0x00, 0x00, 0x03, 0x7a, 0x00, 0x00, 0x07, 0x6c, 0x00, 0x00, 0x0b, 0x54,
+        0x00, 0x00, 0x05, 0xdc, 0x00, 0x00, 0x09, 0xc4, 0x00, 0x00, 0x0d, 0xac,
+        0x00, 0x00, 0x00, 0x0c, 0x00, 0x00, 0x00, 0x24, 0xb6, 0x2e, 0x5f, 0x29,
+        0x13, 0xee, 0xa6, 0x59, 0x0c, 0xa2, 0x32, 0x85, 0x27, 0xd1, 0x08, 0xa8,
+        0x00, 0x1a, 0x87, 0x4d, 0xc2, 0x24, 0xe5, 0x1a, 0xf6, 0x25, 0x46, 0x6a,
+        0xc2, 0xc3, 0xff, 0x11, 0x06, 0x4f, 0x72, 0xc4,
+    ];
+
+    // A frame of 4 water-like molecules (12 atoms) compressed with
+    // precision 1000 by a C port of the compression in xdr3dfcoord. From the
+    // second molecule, each run has the same length as the previous one, so
+    // the run-length flag is 0 and the previous run must be reused.
+    const REPEATED_RUNS: [u8; 140] = [
+        0x00, 0x00, 0x07, 0xcb, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x00, 0x00, 0x07,
+        0x3e, 0x80, 0x00, 0x00, 0x40, 0x60, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x60, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x40, 0x60, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x44, 0x7a, 0x00, 0x00,
+        0x00, 0x00, 0x03, 0xd0, 0x00, 0x00, 0x05, 0xdc, 0x00, 0x00, 0x04, 0x44,
+        0x00, 0x00, 0x0c, 0x7c, 0x00, 0x00, 0x0a, 0xe9, 0x00, 0x00, 0x07, 0xdc,
+        0x00, 0x00, 0x00, 0x15, 0x00, 0x00, 0x00, 0x2d, 0x5c, 0x74, 0xb4, 0x01,
+        0x86, 0xa0, 0xde, 0x18, 0x22, 0x21, 0x95, 0x20, 0x10, 0x08, 0xa5, 0x16,
+        0x91, 0x4e, 0xa7, 0x4d, 0x75, 0x06, 0x30, 0x0a, 0xc6, 0x01, 0xf4, 0x11,
+        0xb0, 0x9a, 0xea, 0x0c, 0x60, 0x15, 0x8b, 0x65, 0x06, 0x1c, 0xe9, 0x35,
+        0xd4, 0x18, 0xc0, 0x2b, 0x10, 0x00, 0x00, 0x00,
+    ];
+
+    fn uncompressed_frame(step: i32, coords: &[[f32; 3]]) -> std::vec::Vec<u8> {
+        let mut bytes = std::vec::Vec::new();
+        bytes.extend_from_slice(&MAGIC.to_be_bytes());
+        bytes.extend_from_slice(&(coords.len() as i32).to_be_bytes());
+        bytes.extend_from_slice(&step.to_be_bytes());
+        bytes.extend_from_slice(&0.5f32.to_be_bytes());
+        for _ in 0 .. 9 {
+            bytes.extend_from_slice(&0.0f32.to_be_bytes());
+        }
+        bytes.extend_from_slice(&(coords.len() as i32).to_be_bytes());
+        for c in coords.iter().flat_map(|c| c.iter()) {
+            bytes.extend_from_slice(&c.to_be_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn read_compressed_xtc() {
+        let mut reader = XtcReader::new(&COMPRESSED[..]);
+        let frame = reader.read_frame().unwrap().unwrap();
+        assert_eq!(frame.step, 42);
+        assert_eq!(frame.snapshot.metadata("timestep"), Some(&Attribute::Integer(42)));
+        assert_eq!(frame.snapshot.metadata("time"), Some(&Attribute::Float(1.5)));
+        assert_eq!(frame.snapshot.metadata("box"), Some(&Attribute::Matrix(
+            nalgebra::Matrix3::from_diagonal(&nalgebra::Vector3::new(3.0, 4.0, 5.0)))));
+        assert_eq!(frame.time, 1.5);
+        let b = frame.simulation_box.unwrap();
+        assert_eq!(b.lengths(), nalgebra::Vector3::new(3.0, 4.0, 5.0));
+
+        let expected = [
+            [1.003, 1.996, 3.007], [1.000, 2.000, 3.000], [0.995, 1.996, 3.012],
+            [1.500, 2.500, 3.500], [0.890, 1.909, 2.900], [0.900, 1.900, 2.900],
+            [1.200, 2.200, 3.200], [1.300, 2.100, 3.100], [1.111, 2.222, 3.333],
+            [1.010, 2.020, 3.030],
+        ];
+        let positions = frame.snapshot.particles.iter()
+            .map(|p| p.xyz).collect::<std::vec::Vec<_>>();
+        assert_eq!(positions.len(), expected.len());
+        for (p, e) in positions.iter().zip(expected.iter()) {
+            assert!((p.x() - e[0]).abs() < 1e-6);
+            assert!((p.y() - e[1]).abs() < 1e-6);
+            assert!((p.z() - e[2]).abs() < 1e-6);
+        }
+        assert!(reader.read_frame().unwrap().is_none());
+
+        // truncated frame
+        let mut reader = XtcReader::new(&COMPRESSED[.. 100]);
+        assert!(reader.read_frame().is_err());
+    }
+
+    #[test]
+    fn read_repeated_runs() {
+        let mut reader = XtcReader::new(&REPEATED_RUNS[..]);
+        let frame = reader.read_frame().unwrap().unwrap();
+        assert_eq!(frame.step, 7);
+        let positions = frame.snapshot.particles.iter()
+            .map(|p| p.xyz).collect::<std::vec::Vec<_>>();
+        assert_eq!(positions.len(), 12);
+        for (m, molecule) in positions.chunks(3).enumerate() {
+            let base = [1.0 + 0.7 * m as f32, 1.5 + 0.4 * m as f32, 2.0 - 0.3 * m as f32];
+            let expected = [
+                [base[0],         base[1],         base[2]],
+                [base[0] + 0.096, base[1],         base[2] + 0.012],
+                [base[0] - 0.024, base[1] + 0.093, base[2] - 0.008],
+            ];
+            for (p, e) in molecule.iter().zip(expected.iter()) {
+                assert!((p.x() - e[0]).abs() < 1e-3);
+                assert!((p.y() - e[1]).abs() < 1e-3);
+                assert!((p.z() - e[2]).abs() < 1e-3);
+            }
+        }
+        assert!(reader.read_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn read_uncompressed_xtc() {
+        let mut bytes = uncompressed_frame(0, &[[0.1, 0.2, 0.3], [1.0, 2.0, 3.0]]);
+        bytes.extend(uncompressed_frame(10, &[[0.2, 0.3, 0.4], [1.5, 2.5, 3.5]]));
+
+        let frames = XtcReader::new(&bytes[..]).collect::<std::vec::Vec<_>>();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].step, 10);
+        assert!(frames[1].simulation_box.is_none());
+        assert_eq!(frames[1].snapshot.particles[1].xyz,
+                   Coordinate::Position{x: 1.5, y: 2.5, z: 3.5});
+        assert_eq!(frames[1].snapshot.particles[1].name, "X");
+
+        let mut reader = XtcReader::new(&bytes[..]);
+        assert!(TrajectoryReader::read_snapshot(&mut reader).unwrap().is_some());
+        assert!(TrajectoryReader::read_snapshot(&mut reader).unwrap().is_some());
+        assert!(TrajectoryReader::read_snapshot(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn reject_out_of_range_smallidx() {
+        // 10 atoms at the origin with smallidx = 72, the largest one. The
+        // first atom (1 bit) is followed by a run flag whose run length 2
+        // asks to increase smallidx beyond MAGICINTS.
+        let mut bytes = uncompressed_frame(0, &[]);
+        bytes.truncate(bytes.len() - 4);
+        bytes[4 .. 8].copy_from_slice(&10i32.to_be_bytes());
+        bytes.extend_from_slice(&10i32.to_be_bytes());
+        bytes.extend_from_slice(&1000.0f32.to_be_bytes());
+        for _ in 0 .. 6 {
+            bytes.extend_from_slice(&0i32.to_be_bytes());
+        }
+        bytes.extend_from_slice(&72i32.to_be_bytes());
+        bytes.extend_from_slice(&1i32.to_be_bytes());
+        bytes.extend_from_slice(&[0b0100_0100, 0, 0, 0]);
+
+        let err = XtcReader::new(&bytes[..]).read_frame().unwrap_err();
+        assert_eq!(*err.kind(), crate::error::ErrorKind::InvalidFormat{
+            error: "invalid xtc compression parameter: 73".to_string(), line: None});
+    }
+
+    #[test]
+    fn invalid_magic() {
+        let mut bytes = uncompressed_frame(0, &[[0.1, 0.2, 0.3]]);
+        bytes[3] = 0;
+        assert!(XtcReader::new(&bytes[..]).read_frame().is_err());
+    }
+}
@@ -8,11 +8,16 @@
 //!     println!("{} particles in a snapshot", snapshot.particles.len());
 //! }
 //! ```
-use crate::error::{Error, Result};
+use crate::boundary::SimulationBox;
+use crate::error::{Error, ErrorKind, Result};
 use crate::particle::{Attribute, Particle};
 use crate::snapshot::Snapshot;
 use crate::coordinate::{CoordKind, Coordinate};
-use std::io::{BufRead, Write}; // to use read_line
+use crate::analysis::Accumulator;
+use crate::reader::TrajectoryReader;
+use crate::trajectory::{Trajectory, VecTrajectory};
+use crate::parse::{tokenize, Fields};
+use std::io::Write; // to use write_all
 
 /// Particle contained in a xyz file.
 ///
@@ -29,6 +34,30 @@ pub struct XYZParticle<T> {
     pub name : std::string::String,
     /// coordinate of this particle.
     pub xyz  : Coordinate<T>,
+    /// identifier of this particle written in the file, if the format has
+    /// one. It may differ from the index in the snapshot.
+    pub id   : std::option::Option<usize>,
+    /// charge of this particle, if it is written in the fifth column.
+    pub charge: std::option::Option<f64>,
+}
+
+impl<T> XYZParticle<T> {
+    /// construct XYZParticle.
+    pub fn new(name: std::string::String, xyz: Coordinate<T>) -> Self {
+        XYZParticle{name, xyz, id: None, charge: None}
+    }
+
+    /// sets the identifier of this particle.
+    pub fn with_id(mut self, id: usize) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    /// sets the charge of this particle.
+    pub fn with_charge(mut self, charge: f64) -> Self {
+        self.charge = Some(charge);
+        self
+    }
 }
 
 impl<T> XYZParticle<T>
@@ -36,27 +65,68 @@ where
     T: std::str::FromStr,
     Error: std::convert::From<<T as std::str::FromStr>::Err>
 {
-    /// construct XYZParticle.
-    pub fn new(name: std::string::String, xyz: Coordinate<T>) -> Self {
-        XYZParticle{name: name, xyz: xyz}
+    // "H 1.00 1.00 1.00" -> XYZParticle
+    // "H 1.00 1.00 1.00 -0.5" -> XYZParticle with charge, if `format.charge`
+    // "H 1.00 1.00" -> XYZParticle at z = 0, if `format.planar`
+    fn from_line(line: &str, kind: CoordKind, format: LineFormat) -> Result<Self> {
+        let (name, x, y, z, q) = Self::split_line(line, format)?;
+        let mut particle = XYZParticle::new(name.to_string(), Coordinate::build(kind, x, y, z));
+        particle.charge = q;
+        Ok(particle)
     }
 
-    // "H 1.00 1.00 1.00" -> XYZParticle
-    fn from_line(line: &str, kind: CoordKind) -> Result<Self> {
-        let elems: std::vec::Vec<&str> = line.split_whitespace().collect();
+    // overwrites self by the line, reusing the allocated name.
+    fn assign_line(&mut self, line: &str, kind: CoordKind, format: LineFormat) -> Result<()> {
+        let (name, x, y, z, q) = Self::split_line(line, format)?;
+        self.name.clear();
+        self.name.push_str(name);
+        self.xyz    = Coordinate::build(kind, x, y, z);
+        self.id     = None;
+        self.charge = q;
+        Ok(())
+    }
 
-        if elems.len() != 4 {
-            return Err(Error::invalid_format(
-                format!("invalid XYZ format: {}", line)
-            ));
+    fn split_line(line: &str, format: LineFormat)
+        -> Result<(&str, T, T, T, std::option::Option<f64>)>
+    {
+        let expected = format.columns();
+        let mut fields = [""; 5];
+        let mut found  = 0;
+        for field in tokenize(line, &Fields::Whitespace) {
+            if found == expected {
+                found += 1;
+                break;
+            }
+            fields[found] = field;
+            found += 1;
+        }
+        if found != expected {
+            return Err(Error::invalid_format(format!(
+                "invalid XYZ format: expected {} columns: {}", expected, line)));
         }
+        let z = if format.planar {"0"} else {fields[3]};
+        let q = if format.charge {
+            Some(fields[expected - 1].parse::<f64>().map_err(
+                <Error as std::convert::From<std::num::ParseFloatError>>::from)?)
+        } else {
+            None
+        };
+        Ok((fields[0], fields[1].parse()?, fields[2].parse()?, z.parse()?, q))
+    }
+}
 
-        let name = elems[0].to_string();
-        let x    = elems[1].parse()?;
-        let y    = elems[2].parse()?;
-        let z    = elems[3].parse()?;
+// Columns in a particle line other than the name and x, y.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct LineFormat {
+    // the last column is a charge
+    charge: bool,
+    // z is omitted and considered to be zero
+    planar: bool,
+}
 
-        Ok(XYZParticle::new(name, Coordinate::build(kind, x, y, z)))
+impl LineFormat {
+    fn columns(&self) -> usize {
+        (if self.planar {3} else {4}) + (if self.charge {1} else {0})
     }
 }
 
@@ -68,16 +138,21 @@ where
     type Err = Error;
     /// read xyz line such as "H   1.00 1.00 1.00" as a position of particle.
     fn from_str(line: &str) -> Result<Self> {
-         Self::from_line(line, CoordKind::Position)
+         Self::from_line(line, CoordKind::Position, LineFormat::default())
     }
 }
 
 impl<T:std::fmt::Display> std::fmt::Display for XYZParticle<T> {
     /// Display xyz line like "H   1.00 1.00 1.00". The width of the fields
-    /// are fixed.
+    /// are fixed. If the particle has a charge, it is written in the fifth
+    /// column.
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{:8} {:.16} {:.16} {:.16}",
-               self.name, self.xyz[0], self.xyz[1], self.xyz[2])
+               self.name, self.xyz[0], self.xyz[1], self.xyz[2])?;
+        match self.charge {
+            Some(q) => write!(f, " {:.16}", q),
+            None    => Ok(()),
+        }
     }
 }
 
@@ -87,29 +162,31 @@ impl<T: nalgebra::Scalar> Particle<T> for XYZParticle<T> {
         None
     }
     fn pos(&self) -> Option<nalgebra::Vector3<T>> {
-        return if let Coordinate::Position{x, y, z} = self.xyz {
+        if let Coordinate::Position{x, y, z} = self.xyz {
             Some(nalgebra::Vector3::new(x, y, z))
         } else {
             None
         }
     }
     fn vel(&self) -> Option<nalgebra::Vector3<T>> {
-        return if let Coordinate::Velocity{x, y, z} = self.xyz {
+        if let Coordinate::Velocity{x, y, z} = self.xyz {
             Some(nalgebra::Vector3::new(x, y, z))
         } else {
             None
         }
     }
     fn force(&self) -> Option<nalgebra::Vector3<T>> {
-        return if let Coordinate::Force{x, y, z} = self.xyz {
+        if let Coordinate::Force{x, y, z} = self.xyz {
             Some(nalgebra::Vector3::new(x, y, z))
         } else {
             None
         }
     }
     fn attribute(&self, name: &str) -> Option<Attribute> {
-        return match name {
+        match name {
             "name" => Some(Attribute::String(self.name.clone())),
+            "id"   => self.id.map(|id| Attribute::Integer(id as i64)),
+            "charge" => self.charge.map(Attribute::Float),
             _ => None,
         }
     }
@@ -123,13 +200,28 @@ pub struct XYZSnapshot<T> {
     pub comment:   std::string::String,
     /// Vec of particles contained in the snapshot.
     pub particles: std::vec::Vec<XYZParticle<T>>,
+    /// The number of particles written in the file. It is Some only if the
+    /// particles are truncated while reading (see `XYZReader::max_atoms`).
+    pub declared_count: std::option::Option<usize>,
+    /// Values about the snapshot that depend on the format. The plain xyz
+    /// format has nothing to put here, but readers of other formats that use
+    /// `XYZSnapshot` store e.g. "timestep", "time" and "box" (as
+    /// `Attribute::Matrix` whose columns are the lattice vectors). Values are
+    /// shared between clones of the snapshot.
+    pub metadata: std::collections::HashMap<std::string::String, std::sync::Arc<Attribute>>,
 }
 
 impl<T> XYZSnapshot<T> {
     /// Constructs snapshot.
     pub fn new(comment: std::string::String,
                particles: std::vec::Vec<XYZParticle<T>>) -> Self {
-        XYZSnapshot{comment: comment, particles: particles}
+        XYZSnapshot{comment, particles, declared_count: None,
+                    metadata: std::collections::HashMap::new()}
+    }
+
+    /// Sets a value about the snapshot, overwriting the previous one.
+    pub fn set_metadata(&mut self, key: &str, value: Attribute) {
+        self.metadata.insert(key.to_string(), std::sync::Arc::new(value));
     }
 
     /// Gets CoordKind in the XYZSnapshot. Returns None if the snapshot does not
@@ -138,6 +230,254 @@ impl<T> XYZSnapshot<T> {
     pub fn which(&self) -> std::option::Option<CoordKind> {
         self.particles.first().map(|p| p.xyz.which())
     }
+
+    /// Iterates over particles with their indices in the snapshot.
+    pub fn enumerate_particles(&self)
+        -> impl Iterator<Item = (usize, &XYZParticle<T>)>
+    {
+        self.particles.iter().enumerate()
+    }
+
+    /// Iterates over positions with the indices of the particles. Particles
+    /// that do not have a position are skipped, but the indices still refer
+    /// to the position in the snapshot.
+    pub fn enumerate_positions(&self)
+        -> impl Iterator<Item = (usize, nalgebra::Vector3<T>)> + '_
+    where
+        T: nalgebra::Scalar
+    {
+        self.particles.iter().enumerate().filter_map(|(i, p)| p.pos().map(|r| (i, r)))
+    }
+
+    /// Returns true if all the particles have the same coordinate kind.
+    /// An empty snapshot is consistent.
+    ///
+    /// If it is false, `positions()`, `velocities()` and `forces()` return None
+    /// because some of the particles do not have the requested kind.
+    pub fn is_kind_consistent(&self) -> bool {
+        match self.which() {
+            Some(kind) => self.particles.iter().all(|p| p.xyz.which() == kind),
+            None       => true,
+        }
+    }
+
+    /// Returns the coordinate kind of the particles, or an error of
+    /// `ErrorKind::InconsistentKind` if the particles have different kinds.
+    /// Returns Ok(None) if the snapshot is empty.
+    pub fn check_kind(&self) -> Result<std::option::Option<CoordKind>> {
+        let kind = match self.which() {
+            Some(kind) => kind,
+            None       => return Ok(None),
+        };
+        match self.particles.iter().position(|p| p.xyz.which() != kind) {
+            Some(i) => Err(Error::inconsistent_kind(format!(
+                "particle {} is {:?} while particle 0 is {:?}",
+                i, self.particles[i].xyz.which(), kind))),
+            None => Ok(Some(kind)),
+        }
+    }
+
+    /// Compares two snapshots with a tolerance.
+    ///
+    /// Returns true if both have the same number of particles with the same
+    /// names and coordinate kinds, and every component of the coordinates
+    /// differs by no more than `tol`. Comments and metadata are not compared.
+    pub fn approx_eq(&self, other: &Self, tol: T) -> bool
+    where
+        T: nalgebra::Real
+    {
+        self.particles.len() == other.particles.len() &&
+        self.particles.iter().zip(other.particles.iter()).all(|(p, q)| {
+            p.name == q.name && p.xyz.approx_eq(&q.xyz, tol, T::zero())
+        })
+    }
+
+    /// Returns the componentwise minimum and maximum corners of the positions.
+    /// Returns None if the snapshot is empty or does not have positions.
+    pub fn bounding_box(&self)
+        -> std::option::Option<(nalgebra::Vector3<T>, nalgebra::Vector3<T>)>
+    where
+        T: nalgebra::Real
+    {
+        let positions = self.positions()?;
+        let (first, rest) = positions.split_first()?;
+        Some(rest.iter().fold((*first, *first), |(lower, upper), r| {
+            (lower.zip_map(r, |a, b| a.min(b)), upper.zip_map(r, |a, b| a.max(b)))
+        }))
+    }
+
+    /// Returns the size of the bounding box, i.e. `max - min`.
+    /// Returns None if the snapshot is empty or does not have positions.
+    pub fn extent(&self) -> std::option::Option<nalgebra::Vector3<T>>
+    where
+        T: nalgebra::Real
+    {
+        self.bounding_box().map(|(lower, upper)| upper - lower)
+    }
+
+    /// Splits the snapshot into groups of particles that have the same name.
+    ///
+    /// Each group keeps the order of particles in the original snapshot, and
+    /// has the same comment and metadata.
+    pub fn split_by_name(&self)
+        -> std::collections::HashMap<std::string::String, XYZSnapshot<T>>
+    where
+        T: Clone
+    {
+        let mut groups = std::collections::HashMap::new();
+        for particle in self.particles.iter() {
+            groups.entry(particle.name.clone()).or_insert_with(|| {
+                let mut group = XYZSnapshot::new(self.comment.clone(), std::vec::Vec::new());
+                group.metadata = self.metadata.clone();
+                group
+            }).particles.push(particle.clone());
+        }
+        groups
+    }
+
+    /// Parses the step written in the comment line, e.g. `i = 100, ...` or
+    /// `Timestep: 100`. Keys `i`, `step` and `timestep` are recognized,
+    /// case-insensitively. Returns None if no step is found.
+    ///
+    /// If the comment has no step, the number of snapshots read so far
+    /// (`XYZReader::frames_read`) can be used instead.
+    pub fn parse_step(&self) -> std::option::Option<u64> {
+        find_comment_field(&self.comment, &["i", "step", "timestep"])
+    }
+
+    /// Parses the time written in the comment line, e.g. `time = 0.5` or
+    /// `t=0.5`. Keys `t` and `time` are recognized, case-insensitively.
+    /// Returns None if no time is found.
+    pub fn parse_time(&self) -> std::option::Option<f64> {
+        find_comment_field(&self.comment, &["t", "time"])
+    }
+
+    /// Parses the box written in the comment line in the extended xyz format,
+    /// e.g. `Lattice="10.0 0.0 0.0 0.0 10.0 0.0 0.0 0.0 10.0"`, where the three
+    /// lattice vectors are written in order. The key is case-insensitive.
+    ///
+    /// Returns Ok(None) if the comment has no `Lattice`, and fails if it does
+    /// not contain nine numbers or the vectors are linearly dependent.
+    pub fn parse_lattice(&self) -> Result<std::option::Option<SimulationBox<T>>>
+    where
+        T: nalgebra::Real
+    {
+        let value = match find_quoted_field(&self.comment, "Lattice") {
+            Some(value) => value,
+            None        => return Ok(None),
+        };
+        let elements = value.split_whitespace().map(|x| x.parse::<f64>())
+            .collect::<std::result::Result<std::vec::Vec<_>, _>>()?;
+        if elements.len() != 9 {
+            return Err(Error::invalid_format(format!(
+                "Lattice should have 9 elements: \"{}\"", value)));
+        }
+        let v = |i: usize| nalgebra::Vector3::new(nalgebra::convert(elements[3 * i]),
+                                                  nalgebra::convert(elements[3 * i + 1]),
+                                                  nalgebra::convert(elements[3 * i + 2]));
+        SimulationBox::new(v(0), v(1), v(2)).map(Some)
+    }
+
+    /// Converts the precision of the coordinates, e.g. from f32 to f64.
+    /// Names, ids, charges, kinds, the comment and metadata are kept. To
+    /// reduce the precision, use `XYZSnapshot::<f64>::to_f32`.
+    pub fn map_precision<U>(&self) -> XYZSnapshot<U>
+    where
+        T: Copy,
+        U: std::convert::From<T>
+    {
+        self.map_values(U::from)
+    }
+
+    fn map_values<U, F: Fn(T) -> U>(&self, f: F) -> XYZSnapshot<U>
+    where
+        T: Copy
+    {
+        XYZSnapshot{
+            comment: self.comment.clone(),
+            particles: self.particles.iter()
+                .map(|p| XYZParticle{name: p.name.clone(), xyz: p.xyz.map(&f),
+                                     id: p.id, charge: p.charge})
+                .collect(),
+            declared_count: self.declared_count,
+            metadata: self.metadata.clone(),
+        }
+    }
+
+    /// Applies `f` to the position of each particle in place.
+    /// Particles that do not have a position are left as they are.
+    pub fn apply_transform<F>(&mut self, f: F)
+    where
+        T: nalgebra::Scalar,
+        F: Fn(&mut nalgebra::Vector3<T>)
+    {
+        for particle in self.particles.iter_mut() {
+            if let Some(mut v) = particle.pos() {
+                f(&mut v);
+                particle.xyz = Coordinate::from_vector(CoordKind::Position, v);
+            }
+        }
+    }
+}
+
+impl XYZSnapshot<f32> {
+    /// Converts the coordinates into f64.
+    pub fn to_f64(&self) -> XYZSnapshot<f64> {
+        self.map_precision()
+    }
+}
+
+impl XYZSnapshot<f64> {
+    /// Converts the coordinates into f32. The values are rounded to the
+    /// nearest f32.
+    pub fn to_f32(&self) -> XYZSnapshot<f32> {
+        self.map_values(|x| x as f32)
+    }
+}
+
+// Finds the first value that follows one of the keys in the comment line.
+// Keys and values are separated by `=`, `:` or just spaces, and fields are
+// separated by spaces, commas or semicolons.
+fn find_comment_field<V: std::str::FromStr>(comment: &str, keys: &[&str])
+    -> std::option::Option<V>
+{
+    let mut tokens = std::vec::Vec::new();
+    let mut start  = None;
+    for (i, c) in comment.char_indices() {
+        let is_delim = c.is_whitespace() || c == ',' || c == ';' || c == '=' || c == ':';
+        if is_delim {
+            if let Some(s) = start.take() {
+                tokens.push(&comment[s .. i]);
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(s) = start {
+        tokens.push(&comment[s ..]);
+    }
+    tokens.windows(2).find_map(|pair| {
+        if keys.iter().any(|k| k.eq_ignore_ascii_case(pair[0])) {
+            pair[1].parse().ok()
+        } else {
+            None
+        }
+    })
+}
+
+// finds `key="value"` in the comment and returns the value without quotes.
+// The key should be at the beginning of the comment or after a whitespace,
+// so that e.g. `superlattice="..."` does not match `lattice`.
+fn find_quoted_field<'a>(comment: &'a str, key: &str) -> std::option::Option<&'a str> {
+    // ascii lowercase keeps the byte offsets
+    let pattern = format!("{}=\"", key.to_ascii_lowercase());
+    let lower   = comment.to_ascii_lowercase();
+    let (found, _) = lower.match_indices(&pattern).find(|(i, _)| {
+        comment[.. *i].chars().next_back().is_none_or(char::is_whitespace)
+    })?;
+    let start = found + pattern.len();
+    let len   = comment[start ..].find('"')?;
+    Some(&comment[start .. start + len])
 }
 
 impl<T> std::ops::Index<usize> for XYZSnapshot<T> {
@@ -156,27 +496,6 @@ impl<T: nalgebra::Scalar> Snapshot<T> for XYZSnapshot<T> {
     fn masses(&self) -> std::option::Option<std::vec::Vec<T>> {
         None
     }
-    fn positions(&self)
-        -> std::option::Option<std::vec::Vec<nalgebra::Vector3<T>>>
-    {
-        self.particles.iter()
-            .map(|p| p.pos())
-            .collect::<std::option::Option<std::vec::Vec<_>>>()
-    }
-    fn velocities(&self)
-        -> std::option::Option<std::vec::Vec<nalgebra::Vector3<T>>>
-    {
-        self.particles.iter()
-            .map(|p| p.vel())
-            .collect::<std::option::Option<std::vec::Vec<_>>>()
-    }
-    fn forces(&self)
-        -> std::option::Option<std::vec::Vec<nalgebra::Vector3<T>>>
-    {
-        self.particles.iter()
-            .map(|p| p.force())
-            .collect::<std::option::Option<std::vec::Vec<_>>>()
-    }
     fn attributes(&self, name: &str)
         -> std::option::Option<std::vec::Vec<Attribute>>
     {
@@ -184,8 +503,78 @@ impl<T: nalgebra::Scalar> Snapshot<T> for XYZSnapshot<T> {
             .map(|p| p.attribute(name))
             .collect::<std::option::Option<std::vec::Vec<_>>>()
     }
+    fn metadata(&self, key: &str) -> std::option::Option<&Attribute> {
+        self.metadata.get(key).map(|v| v.as_ref())
+    }
+}
+
+/// Options of `XYZReader`, that can be combined in a builder style.
+///
+/// By default, all the particles are read, non-finite coordinates are
+/// accepted, `#` lines are not skipped, comments are trimmed, and particle
+/// lines have all of x, y and z (not `assume_2d`) without a charge column.
+/// It is the same as the reader constructed by `XYZReader::new` or
+/// `XYZReader::open_pos`.
+///
+/// ```
+/// use trajan::coordinate::CoordKind;
+/// use trajan::xyz::{XYZReader, XYZReaderOptions};
+/// let options = XYZReaderOptions::new().max_atoms(10).skip_hash_comments(true);
+/// let contents: &[u8] = b"# header\n1\ncomment\nH 1.0 2.0 3.0\n";
+/// let mut reader = XYZReader::<f64, _>::with_options(
+///     CoordKind::Position, contents, &options);
+/// assert_eq!(reader.read_snapshot().unwrap().particles.len(), 1);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct XYZReaderOptions {
+    max_atoms: std::option::Option<usize>,
+    reject_nonfinite: bool,
+    skip_hash_comments: bool,
+    preserve_comment_whitespace: bool,
+    charge: bool,
+    assume_2d: bool,
+}
+
+impl XYZReaderOptions {
+    /// Constructs the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// See `XYZReader::max_atoms`.
+    pub fn max_atoms(mut self, n: usize) -> Self {
+        self.max_atoms = Some(n);
+        self
+    }
+    /// See `XYZReader::reject_nonfinite`.
+    pub fn reject_nonfinite(mut self, reject: bool) -> Self {
+        self.reject_nonfinite = reject;
+        self
+    }
+    /// See `XYZReader::skip_hash_comments`.
+    pub fn skip_hash_comments(mut self, skip: bool) -> Self {
+        self.skip_hash_comments = skip;
+        self
+    }
+    /// See `XYZReader::preserve_comment_whitespace`.
+    pub fn preserve_comment_whitespace(mut self, preserve: bool) -> Self {
+        self.preserve_comment_whitespace = preserve;
+        self
+    }
+    /// See `XYZReader::with_charge`.
+    pub fn with_charge(mut self, charge: bool) -> Self {
+        self.charge = charge;
+        self
+    }
+    /// See `XYZReader::assume_2d`.
+    pub fn assume_2d(mut self, planar: bool) -> Self {
+        self.assume_2d = planar;
+        self
+    }
 }
 
+// A function applied to each position after parsing.
+type PositionFn<T> = std::boxed::Box<dyn Fn(&mut Coordinate<T>) + Send + Sync>;
+
 /// Reads XYZSnapshot.
 ///
 /// It can be used as a iterator that reads snapshots until it reaches to the
@@ -198,6 +587,9 @@ impl<T: nalgebra::Scalar> Snapshot<T> for XYZSnapshot<T> {
 /// is also required. To specify the precision, you can use `.f64()` and
 /// `.f32()` functions.
 ///
+/// Each particle line is expected to have three coordinates. Files written
+/// for two-dimensional systems can be read with `assume_2d`.
+///
 /// ```no_run
 /// use trajan::xyz::XYZReader;
 /// let reader = XYZReader::open_pos("example.xyz").unwrap().f64();
@@ -205,270 +597,1835 @@ impl<T: nalgebra::Scalar> Snapshot<T> for XYZSnapshot<T> {
 ///     println!("{} particles in a snapshot", snapshot.particles.len());
 /// }
 /// ```
+///
+/// The reader operates on any `std::io::BufRead`, so an already-buffered
+/// source like the standard input can be read without double-buffering.
+///
+/// ```no_run
+/// use trajan::xyz::XYZReader;
+/// use trajan::coordinate::CoordKind;
+/// let stdin  = std::io::stdin();
+/// let reader = XYZReader::from_bufread(CoordKind::Position, stdin.lock()).f64();
+/// for snapshot in reader {
+///     println!("{} particles in a snapshot", snapshot.particles.len());
+/// }
+/// ```
 pub struct XYZReader<T, R> {
     pub kind: CoordKind,
-    bufreader: std::io::BufReader<R>,
+    bufreader: R,
+    line: std::string::String,
+    finite_check: std::option::Option<fn(&T) -> bool>,
+    max_atoms: std::option::Option<usize>,
+    frames_read: usize,
+    position_fn: std::option::Option<PositionFn<T>>,
+    format: LineFormat,
+    preserve_comment: bool,
+    skip_hash: bool,
+    skipped_lines: std::vec::Vec<std::string::String>,
+    at_start: bool,
+    // the number of lines read so far. None after seeking to an offset.
+    lineno: std::option::Option<usize>,
+    // true after a count line could not be read. The beginning of the next
+    // snapshot is unknown until it seeks.
+    misaligned: bool,
     _marker: std::marker::PhantomData<T>,
 }
 
-impl<T, R> XYZReader<T, R>
+impl<T, R> XYZReader<T, std::io::BufReader<R>>
 where
     R: std::io::Read,
     T: std::str::FromStr,
     Error: std::convert::From<<T as std::str::FromStr>::Err>
 {
-    /// constructing XYZReader.
+    /// constructing XYZReader. `inner` is wrapped by `std::io::BufReader`.
     pub fn new(kind: CoordKind, inner: R) -> Self {
-        XYZReader::<T, R>{
-            kind: kind,
-            bufreader: std::io::BufReader::new(inner),
-            _marker: std::marker::PhantomData
-        }
-    }
-
-    /// Reads one snapshot from underlying `R: std::io::Read`.
-    /// Fails if the file is formatted in an invalid way or reaches to the end.
-    pub fn read_snapshot(&mut self) -> Result<XYZSnapshot<T>> {
-        let mut line = std::string::String::new();
-
-        self.bufreader.read_line(&mut line)?;
-        let num = line.trim().parse::<usize>()?;
-        line.clear();
-
-        // comment line
-        self.bufreader.read_line(&mut line)?;
-        let comment = line.trim().to_string();
-        line.clear();
-
-        let mut particles = std::vec::Vec::with_capacity(num);
-        for _ in 0 .. num {
-            self.bufreader.read_line(&mut line)?;
-            particles.push(XYZParticle::from_line(line.as_str(), self.kind)?);
-            line.clear();
-        }
-        Ok(XYZSnapshot::new(comment, particles))
+        XYZReader::from_bufread(kind, std::io::BufReader::new(inner))
     }
 }
 
-impl<T> XYZReader<T, std::fs::File>
+impl<T, R> XYZReader<T, R>
 where
+    R: std::io::BufRead,
     T: std::str::FromStr,
     Error: std::convert::From<<T as std::str::FromStr>::Err>
 {
-    /// Opens file and constructs XYZReader by using the file.
-    pub fn open<P>(kind: CoordKind, path: P) -> Result<Self>
-    where
-        P: std::convert::AsRef<std::path::Path>
-    {
-        let f = std::fs::File::open(path)?;
-        Ok(XYZReader::<T, std::fs::File>{
-            kind: kind,
-            bufreader: std::io::BufReader::new(f),
+    /// constructing XYZReader from an already-buffered reader, e.g.
+    /// `std::io::StdinLock`. It is used as it is, without additional buffer.
+    pub fn from_bufread(kind: CoordKind, inner: R) -> Self {
+        XYZReader::<T, R>{
+            kind,
+            bufreader: inner,
+            line: std::string::String::new(),
+            finite_check: None,
+            max_atoms: None,
+            frames_read: 0,
+            position_fn: None,
+            format: LineFormat::default(),
+            preserve_comment: false,
+            skip_hash: false,
+            skipped_lines: std::vec::Vec::new(),
+            at_start: true,
+            lineno: Some(0),
+            misaligned: false,
             _marker: std::marker::PhantomData
-        })
+        }
     }
 
-    /// Opens file and constructs XYZReader by using the file.
-    /// The coordinate is considered to be Position.
-    pub fn open_pos<P>(path: P) -> Result<Self>
+    /// constructing XYZReader from an already-buffered reader with options.
+    /// See `XYZReaderOptions` for the details.
+    pub fn with_options(kind: CoordKind, inner: R, options: &XYZReaderOptions) -> Self
     where
-        P: std::convert::AsRef<std::path::Path>
+        T: nalgebra::Real
     {
-        let f = std::fs::File::open(path)?;
-        Ok(XYZReader::<T, std::fs::File>{
-            kind: CoordKind::Position,
-            bufreader: std::io::BufReader::new(f),
-            _marker: std::marker::PhantomData
-        })
+        let mut reader = XYZReader::from_bufread(kind, inner)
+            .reject_nonfinite(options.reject_nonfinite)
+            .skip_hash_comments(options.skip_hash_comments)
+            .preserve_comment_whitespace(options.preserve_comment_whitespace)
+            .with_charge(options.charge)
+            .assume_2d(options.assume_2d);
+        reader.max_atoms = options.max_atoms;
+        reader
     }
-    /// Opens file and constructs XYZReader by using the file.
-    /// The coordinate is considered to be Velocity.
-    pub fn open_vel<P>(path: P) -> Result<Self>
-    where
-        P: std::convert::AsRef<std::path::Path>
-    {
-        let f = std::fs::File::open(path)?;
-        Ok(XYZReader::<T, std::fs::File>{
-            kind: CoordKind::Velocity,
-            bufreader: std::io::BufReader::new(f),
-            _marker: std::marker::PhantomData
-        })
+
+    /// Changes the kind of coordinates. Snapshots read after this call are
+    /// interpreted as the new kind.
+    pub fn set_kind(&mut self, kind: CoordKind) {
+        self.kind = kind;
     }
-    /// Opens file and constructs XYZReader by using the file.
-    /// The coordinate is considered to be Force.
-    pub fn open_force<P>(path: P) -> Result<Self>
+
+    /// Changes the kind of coordinates, in a chainable way.
+    /// ```no_run
+    /// use trajan::xyz::XYZReader;
+    /// use trajan::coordinate::CoordKind;
+    /// let reader = XYZReader::open_pos("example.xyz").unwrap().f64()
+    ///     .with_kind(CoordKind::Velocity);
+    /// ```
+    pub fn with_kind(mut self, kind: CoordKind) -> Self {
+        self.set_kind(kind);
+        self
+    }
+
+    /// Returns the number of snapshots read so far.
+    pub fn frames_read(&self) -> usize {
+        self.frames_read
+    }
+
+    /// Limits the number of particles read from each snapshot, in a chainable
+    /// way. Only the first `n` particles are parsed and the rest are skipped.
+    /// The number of particles written in the file is stored in
+    /// `XYZSnapshot::declared_count` if some are skipped.
+    ///
+    /// ```no_run
+    /// use trajan::xyz::XYZReader;
+    /// let reader = XYZReader::open_pos("example.xyz").unwrap().f64().max_atoms(10);
+    /// for snapshot in reader {
+    ///     assert!(snapshot.particles.len() <= 10);
+    /// }
+    /// ```
+    pub fn max_atoms(mut self, n: usize) -> Self {
+        self.max_atoms = Some(n);
+        self
+    }
+
+    /// Makes the reader interpret positions as fractional coordinates in the
+    /// box, in a chainable way. They are converted into cartesian coordinates
+    /// while reading. Velocities and forces are left as they are.
+    ///
+    /// ```no_run
+    /// use trajan::boundary::SimulationBox;
+    /// use trajan::xyz::XYZReader;
+    /// let b = SimulationBox::cuboid(nalgebra::Vector3::new(10.0, 10.0, 10.0)).unwrap();
+    /// let reader = XYZReader::open_pos("scaled.xyz").unwrap().f64().fractional(b);
+    /// ```
+    pub fn fractional(mut self, boundary: SimulationBox<T>) -> Self
     where
-        P: std::convert::AsRef<std::path::Path>
+        T: nalgebra::Real
     {
-        let f = std::fs::File::open(path)?;
-        Ok(XYZReader::<T, std::fs::File>{
-            kind: CoordKind::Force,
-            bufreader: std::io::BufReader::new(f),
-            _marker: std::marker::PhantomData
-        })
+        self.position_fn = Some(std::boxed::Box::new(move |c: &mut Coordinate<T>| {
+            if let Coordinate::Position{..} = c {
+                let frac = nalgebra::Vector3::new(*c.x(), *c.y(), *c.z());
+                *c = Coordinate::from_vector(CoordKind::Position,
+                                             boundary.fractional_to_cartesian(&frac));
+            }
+        }));
+        self
     }
-}
 
-/// methods for explicitly specialized type, f32.
-impl<R> XYZReader<f32, R> {
-    /// An empty function that does nothing.
-    /// It is provided in order to set type of `XYZReader` without explicitly write
-    /// complicated type parameter, such that
+    /// Makes the reader expect a charge in the fifth column, like
+    /// `H 1.0 2.0 3.0 0.41`, in a chainable way. The charge is stored in
+    /// `XYZParticle::charge` and can be retrieved by `attribute("charge")`.
+    /// Lines that do not have exactly 5 columns are rejected.
+    ///
     /// ```no_run
     /// use trajan::xyz::XYZReader;
-    /// let r = XYZReader::<f32, _>::open_pos("example.xyz").unwrap();
-    /// //                 ^^^^^^^^^^ why we need the second `_`?
+    /// let reader = XYZReader::open_pos("charged.xyz").unwrap().f64().with_charge(true);
     /// ```
-    /// By implementing this dummy function, rustc can deduce the type in the
-    /// following, simpler and easier way.
+    pub fn with_charge(mut self, charge: bool) -> Self {
+        self.format.charge = charge;
+        self
+    }
+
+    /// Makes the reader accept two-dimensional lines like `H 1.0 2.0`, in a
+    /// chainable way. The z coordinate is set to zero. Lines that have z are
+    /// rejected. If a charge is also expected, it is in the fourth column.
+    ///
     /// ```no_run
     /// use trajan::xyz::XYZReader;
-    /// let r = XYZReader::open_pos("hoge").unwrap().f32();
-    /// //                                          ^^^^^^ clear!
+    /// let reader = XYZReader::open_pos("membrane.xyz").unwrap().f64().assume_2d(true);
     /// ```
-    pub fn f32(self) -> Self {self}
-}
-/// methods for explicitly specialized type, f64.
-impl<R> XYZReader<f64, R> {
-    /// An empty function that does nothing.
-    /// It is provided in order to set type of `XYZReader` without explicitly write
-    /// complicated type parameter, such that
+    pub fn assume_2d(mut self, planar: bool) -> Self {
+        self.format.planar = planar;
+        self
+    }
+
+    /// Makes the reader keep leading and trailing whitespaces in comments, in a
+    /// chainable way. Only the line feed is removed, so that the comment can
+    /// be written again byte by byte. By default, comments are trimmed.
+    ///
     /// ```no_run
     /// use trajan::xyz::XYZReader;
-    /// let r = XYZReader::<f64, _>::open_pos("hoge").unwrap();
-    /// //               ^^^^^^^^^^ why we need the second `_`?
+    /// let reader = XYZReader::open_pos("example.xyz").unwrap().f64()
+    ///     .preserve_comment_whitespace(true);
     /// ```
-    /// By implementing this dummy function, rustc can deduce the type in the
-    /// following, simpler and easier way.
+    pub fn preserve_comment_whitespace(mut self, preserve: bool) -> Self {
+        self.preserve_comment = preserve;
+        self
+    }
+
+    /// Makes the reader skip lines starting with `#` before the number of
+    /// particles, in a chainable way. Lines in a snapshot, including the
+    /// comment line, are not skipped. The skipped lines before the last
+    /// snapshot are available from `skipped_lines`.
+    ///
     /// ```no_run
     /// use trajan::xyz::XYZReader;
-    /// let r = XYZReader::open_pos("hoge").unwrap().f64();
-    /// //                                          ^^^^^^ clear!
+    /// let reader = XYZReader::open_pos("example.xyz").unwrap().f64()
+    ///     .skip_hash_comments(true);
     /// ```
-    pub fn f64(self) -> Self {self}
-}
+    pub fn skip_hash_comments(mut self, skip: bool) -> Self {
+        self.skip_hash = skip;
+        self
+    }
 
-/// Enables XYZReader to be used as a Iterator of XYZSnapShot.
-impl<T, R> std::iter::Iterator for XYZReader<T, R>
-where
-    R: std::io::Read,
-    T: std::str::FromStr,
-    Error: std::convert::From<<T as std::str::FromStr>::Err>
-{
-    type Item = XYZSnapshot<T>;
-    fn next(&mut self) -> std::option::Option<Self::Item> {
-        self.read_snapshot().ok()
+    /// Returns the `#` lines skipped before the snapshot read last. They are
+    /// trimmed like comments. See `skip_hash_comments`.
+    pub fn skipped_lines(&self) -> &[std::string::String] {
+        &self.skipped_lines
     }
-}
 
-/// Writes XYZSnapshot.
-///
-/// ```no_run
-/// use trajan::xyz::{XYZReader, XYZWriter};
-/// let reader     = XYZReader::open_pos("example.xyz").unwrap().f64();
-/// let mut writer = XYZWriter::new(std::io::stdout());
-/// for snapshot in reader {
-///     writer.write_snapshot(&snapshot).unwrap();
+    /// Makes the reader fail on NaN or infinite coordinates, in a chainable
+    /// way. By default, they are accepted as they are parsed.
+    ///
+    /// ```no_run
+    /// use trajan::xyz::XYZReader;
+    /// let reader = XYZReader::open_pos("example.xyz").unwrap().f64()
+    ///     .reject_nonfinite(true);
+    /// ```
+    pub fn reject_nonfinite(mut self, reject: bool) -> Self
+    where
+        T: nalgebra::Real
+    {
+        self.finite_check = if reject {Some(is_finite::<T>)} else {None};
+        self
+    }
+
+    /// Transforms each snapshot by `f` while reading, e.g. to wrap particles
+    /// into the box. Snapshots are read one by one, so the whole trajectory
+    /// is never held in memory.
+    ///
+    /// ```no_run
+    /// use trajan::boundary::SimulationBox;
+    /// use trajan::xyz::{XYZReader, XYZWriter};
+    /// let b = SimulationBox::cuboid(nalgebra::Vector3::new(10.0, 10.0, 10.0)).unwrap();
+    /// let reader = XYZReader::open_pos("example.xyz").unwrap().f64();
+    /// let mut writer = XYZWriter::open("wrapped.xyz").unwrap();
+    /// for snapshot in reader.map_snapshots(|mut s| {s.apply_transform(|r| *r = b.wrap(*r)); s}) {
+    ///     writer.write_snapshot(&snapshot).unwrap();
+    /// }
+    /// ```
+    pub fn map_snapshots<F>(self, f: F) -> MapSnapshots<Self, F>
+    where
+        F: FnMut(XYZSnapshot<T>) -> XYZSnapshot<T>
+    {
+        MapSnapshots{iter: self, f}
+    }
+
+    /// Reads one snapshot from underlying `R: std::io::BufRead`.
+    /// Fails if the file is formatted in an invalid way or reaches to the end.
+    pub fn read_snapshot(&mut self) -> Result<XYZSnapshot<T>> {
+        let mut snapshot = XYZSnapshot::new(std::string::String::new(), std::vec::Vec::new());
+        if self.read_snapshot_into(&mut snapshot)? {
+            Ok(snapshot)
+        } else {
+            let eof = std::io::Error::new(std::io::ErrorKind::UnexpectedEof,
+                                          "no snapshot remains");
+            Err(<Error as std::convert::From<std::io::Error>>::from(eof))
+        }
+    }
+
+    /// Reads one snapshot into `buf`, reusing its memory.
+    ///
+    /// The particles and the comment in `buf` are overwritten. The capacity
+    /// of `buf.particles` and names of particles are reused, so reading many
+    /// snapshots into the same buffer does not allocate once the buffer
+    /// becomes large enough. Blank lines before a snapshot are skipped, as in
+    /// `count_frames`. Returns false if it has already reached EOF.
+    /// Fails if the file is formatted in an invalid way. The error has the
+    /// number of the invalid line, available through `Error::line`. Even if a
+    /// particle line is invalid, the rest of the snapshot is skipped so that
+    /// the next call reads the next snapshot.
+    pub fn read_snapshot_into(&mut self, buf: &mut XYZSnapshot<T>) -> Result<bool> {
+        self.skipped_lines.clear();
+        self.misaligned = false;
+        loop {
+            if self.next_line()? == 0 {
+                return Ok(false);
+            }
+            if self.at_start {
+                strip_bom(&mut self.line);
+                self.at_start = false;
+            }
+            if self.skip_hash && self.line.trim_start().starts_with('#') {
+                self.skipped_lines.push(self.line.trim().to_string());
+                continue;
+            }
+            if !self.line.trim().is_empty() {
+                break;
+            }
+        }
+        let num = match parse_count(&self.line) {
+            Ok(num) => num,
+            Err(e)  => {
+                self.misaligned = true;
+                return Err(at_line(e, self.lineno));
+            }
+        };
+
+        // comment line
+        self.next_line()?;
+        buf.comment.clear();
+        if self.preserve_comment {
+            buf.comment.push_str(self.line.trim_end_matches(&['\n', '\r'][..]));
+        } else {
+            buf.comment.push_str(self.line.trim());
+        }
+        buf.metadata.clear();
+
+        let n_read = self.max_atoms.map_or(num, |n| n.min(num));
+        buf.declared_count = if n_read < num {Some(num)} else {None};
+
+        buf.particles.truncate(n_read);
+        let mut error = None;
+        for i in 0 .. n_read {
+            self.next_line()?;
+            if error.is_some() {
+                continue;
+            }
+            let lineno = self.lineno;
+            let parsed = if let Some(particle) = buf.particles.get_mut(i) {
+                particle.assign_line(self.line.as_str(), self.kind, self.format)
+            } else {
+                XYZParticle::from_line(self.line.as_str(), self.kind, self.format)
+                    .map(|particle| buf.particles.push(particle))
+            };
+            if let Err(e) = parsed {
+                error = Some(at_line(e, lineno));
+                continue;
+            }
+            if let Some(ref f) = self.position_fn {
+                f(&mut buf.particles[i].xyz);
+            }
+            if let Some(check) = self.finite_check {
+                let xyz = &buf.particles[i].xyz;
+                if !(check(xyz.x()) && check(xyz.y()) && check(xyz.z())) {
+                    error = Some(at_line(Error::invalid_format(format!(
+                        "non-finite coordinate: {}", self.line.trim())), lineno));
+                }
+            }
+        }
+        // skip the rest to keep the stream aligned to the next snapshot
+        for _ in n_read .. num {
+            if self.next_line()? == 0 {
+                return Err(at_line(Error::invalid_format(format!(
+                    "snapshot is truncated: {} particles are declared", num)),
+                    self.lineno.map(|n| n + 1)));
+            }
+        }
+        if let Some(e) = error {
+            return Err(e);
+        }
+        self.frames_read += 1;
+        Ok(true)
+    }
+
+    /// Reads all the snapshots until EOF and returns the result of each one,
+    /// so that invalid snapshots in a file concatenated from different
+    /// sources can be found. The i-th element corresponds to the i-th
+    /// snapshot in the file, and an error has the number of the invalid line.
+    ///
+    /// Unlike the iterator, it does not stop at the first invalid snapshot.
+    /// Snapshots after an invalid particle line are still read, but if the
+    /// line of the number of particles is invalid or an I/O error occurs,
+    /// the error is the last element because the next snapshot cannot be
+    /// found.
+    ///
+    /// ```no_run
+    /// use trajan::xyz::XYZReader;
+    /// let mut reader = XYZReader::open_pos("concatenated.xyz").unwrap().f64();
+    /// for (i, frame) in reader.read_all_frames().iter().enumerate() {
+    ///     if let Err(e) = frame {
+    ///         eprintln!("frame {} is invalid: {}", i, e);
+    ///     }
+    /// }
+    /// ```
+    pub fn read_all_frames(&mut self) -> std::vec::Vec<Result<XYZSnapshot<T>>> {
+        let mut frames = std::vec::Vec::new();
+        loop {
+            let mut snapshot = XYZSnapshot::new(std::string::String::new(), std::vec::Vec::new());
+            match self.read_snapshot_into(&mut snapshot) {
+                Ok(true)  => frames.push(Ok(snapshot)),
+                Ok(false) => break,
+                Err(e)    => {
+                    let fatal = self.misaligned || *e.kind() == ErrorKind::Io;
+                    frames.push(Err(e));
+                    if fatal {
+                        break;
+                    }
+                }
+            }
+        }
+        frames
+    }
+
+    // Reads the next line into `self.line` and counts it.
+    fn next_line(&mut self) -> Result<usize> {
+        self.line.clear();
+        let n = self.bufreader.read_line(&mut self.line)?;
+        if 0 < n {
+            self.lineno = self.lineno.map(|l| l + 1);
+        }
+        Ok(n)
+    }
+
+    /// Calls `f` for each snapshot until it reaches to EOF.
+    ///
+    /// A snapshot buffer is reused through `read_snapshot_into`, so it is
+    /// faster than the iterator that allocates each snapshot. If reading
+    /// fails or `f` returns an error, it stops and returns the error.
+    ///
+    /// ```no_run
+    /// use trajan::xyz::XYZReader;
+    /// let mut reader = XYZReader::open_pos("example.xyz").unwrap().f64();
+    /// reader.for_each_snapshot(|snapshot| {
+    ///     println!("{}", snapshot.comment);
+    ///     Ok(())
+    /// }).unwrap();
+    /// ```
+    pub fn for_each_snapshot<F>(&mut self, mut f: F) -> Result<()>
+    where
+        F: FnMut(&XYZSnapshot<T>) -> Result<()>
+    {
+        let mut buf = XYZSnapshot::new(std::string::String::new(), std::vec::Vec::new());
+        while self.read_snapshot_into(&mut buf)? {
+            f(&buf)?;
+        }
+        Ok(())
+    }
+
+    /// Feeds all the snapshots into the accumulator and returns the result.
+    ///
+    /// Snapshots are read one by one, so an arbitrarily long file can be
+    /// analysed with constant memory.
+    pub fn accumulate<A>(self, mut acc: A) -> A::Output
+    where
+        T: nalgebra::Scalar,
+        A: Accumulator<T>,
+    {
+        for snapshot in self {
+            acc.observe(&snapshot);
+        }
+        acc.finalize()
+    }
+}
+
+impl<T, R> XYZReader<T, R>
+where
+    R: std::io::BufRead + std::io::Seek,
+    T: std::str::FromStr,
+    Error: std::convert::From<<T as std::str::FromStr>::Err>
+{
+    /// Reads one snapshot that starts at the byte `offset` from the beginning.
+    ///
+    /// The offset should point to the line of the number of particles, e.g.
+    /// one recorded by an external index. Fails if it does not point to the
+    /// beginning of a line. After this, reading continues from the next
+    /// snapshot. Since the number of lines before the offset is unknown,
+    /// errors after seeking to a non-zero offset do not have line numbers.
+    ///
+    /// ```no_run
+    /// use trajan::xyz::XYZReader;
+    /// let mut reader = XYZReader::open_pos("example.xyz").unwrap().f64();
+    /// let snapshot = reader.read_snapshot_at(1024).unwrap();
+    /// ```
+    pub fn read_snapshot_at(&mut self, offset: u64) -> Result<XYZSnapshot<T>> {
+        if offset != 0 {
+            // the previous byte should be the end of a line
+            self.bufreader.seek(std::io::SeekFrom::Start(offset - 1))?;
+            let mut prev = [0u8; 1];
+            self.bufreader.read_exact(&mut prev)?;
+            if prev[0] != b'\n' {
+                return Err(Error::invalid_format(format!(
+                    "offset {} is not at the beginning of a line", offset)));
+            }
+            self.lineno = None;
+            self.misaligned = false;
+        } else {
+            self.bufreader.seek(std::io::SeekFrom::Start(0))?;
+            self.at_start = true;
+            self.lineno   = Some(0);
+            self.misaligned = false;
+        }
+        self.read_snapshot()
+    }
+}
+
+impl<T> XYZReader<T, std::io::BufReader<std::fs::File>>
+where
+    T: std::str::FromStr,
+    Error: std::convert::From<<T as std::str::FromStr>::Err>
+{
+    /// Opens file and constructs XYZReader by using the file.
+    pub fn open<P>(kind: CoordKind, path: P) -> Result<Self>
+    where
+        P: std::convert::AsRef<std::path::Path>
+    {
+        let f = std::fs::File::open(path)?;
+        Ok(XYZReader::new(kind, f))
+    }
+
+    /// Opens file and constructs XYZReader by using the file.
+    /// The coordinate is considered to be Position.
+    pub fn open_pos<P>(path: P) -> Result<Self>
+    where
+        P: std::convert::AsRef<std::path::Path>
+    {
+        Self::open(CoordKind::Position, path)
+    }
+    /// Opens file and constructs XYZReader by using the file.
+    /// The coordinate is considered to be Position, followed by a charge in
+    /// the fifth column (see `with_charge`).
+    pub fn open_pos_with_charge<P>(path: P) -> Result<Self>
+    where
+        P: std::convert::AsRef<std::path::Path>
+    {
+        Ok(Self::open(CoordKind::Position, path)?.with_charge(true))
+    }
+    /// Opens file and constructs XYZReader by using the file.
+    /// The coordinate is considered to be Velocity.
+    pub fn open_vel<P>(path: P) -> Result<Self>
+    where
+        P: std::convert::AsRef<std::path::Path>
+    {
+        Self::open(CoordKind::Velocity, path)
+    }
+    /// Opens file and constructs XYZReader by using the file.
+    /// The coordinate is considered to be Force.
+    pub fn open_force<P>(path: P) -> Result<Self>
+    where
+        P: std::convert::AsRef<std::path::Path>
+    {
+        Self::open(CoordKind::Force, path)
+    }
+}
+
+/// methods for explicitly specialized type, f32.
+impl<R> XYZReader<f32, R> {
+    /// An empty function that does nothing.
+    /// It is provided in order to set type of `XYZReader` without explicitly write
+    /// complicated type parameter, such that
+    /// ```no_run
+    /// use trajan::xyz::XYZReader;
+    /// let r = XYZReader::<f32, _>::open_pos("example.xyz").unwrap();
+    /// //                 ^^^^^^^^^^ why we need the second `_`?
+    /// ```
+    /// By implementing this dummy function, rustc can deduce the type in the
+    /// following, simpler and easier way.
+    /// ```no_run
+    /// use trajan::xyz::XYZReader;
+    /// let r = XYZReader::open_pos("hoge").unwrap().f32();
+    /// //                                          ^^^^^^ clear!
+    /// ```
+    pub fn f32(self) -> Self {self}
+}
+/// methods for explicitly specialized type, f64.
+impl<R> XYZReader<f64, R> {
+    /// An empty function that does nothing.
+    /// It is provided in order to set type of `XYZReader` without explicitly write
+    /// complicated type parameter, such that
+    /// ```no_run
+    /// use trajan::xyz::XYZReader;
+    /// let r = XYZReader::<f64, _>::open_pos("hoge").unwrap();
+    /// //               ^^^^^^^^^^ why we need the second `_`?
+    /// ```
+    /// By implementing this dummy function, rustc can deduce the type in the
+    /// following, simpler and easier way.
+    /// ```no_run
+    /// use trajan::xyz::XYZReader;
+    /// let r = XYZReader::open_pos("hoge").unwrap().f64();
+    /// //                                          ^^^^^^ clear!
+    /// ```
+    pub fn f64(self) -> Self {self}
+}
+
+// Removes the UTF-8 byte order mark that some tools write at the beginning
+// of a file.
+fn strip_bom(line: &mut std::string::String) {
+    if line.starts_with('\u{feff}') {
+        line.drain(.. '\u{feff}'.len_utf8());
+    }
+}
+
+// Parses the number of particles. Annotations after the number, like
+// `100 atoms`, are ignored.
+fn parse_count(line: &str) -> Result<usize> {
+    match line.split_whitespace().next() {
+        Some(token) => Ok(token.parse::<usize>()?),
+        None => Err(Error::invalid_format("missing the number of particles".to_string())),
+    }
+}
+
+// Adds the line number to the error if it is known.
+fn at_line(e: Error, line: std::option::Option<usize>) -> Error {
+    match line {
+        Some(line) => e.with_line(line),
+        None       => e,
+    }
+}
+
+// NaN fails both comparisons.
+fn is_finite<T: nalgebra::Real>(x: &T) -> bool {
+    x.abs() <= T::max_value()
+}
+
+/// Enables XYZReader to be used as a Iterator of XYZSnapShot.
+impl<T, R> std::iter::Iterator for XYZReader<T, R>
+where
+    R: std::io::BufRead,
+    T: std::str::FromStr,
+    Error: std::convert::From<<T as std::str::FromStr>::Err>
+{
+    type Item = XYZSnapshot<T>;
+    fn next(&mut self) -> std::option::Option<Self::Item> {
+        self.read_snapshot().ok()
+    }
+}
+
+/// Enables XYZReader to be used as a generic `TrajectoryReader`.
+impl<T, R> TrajectoryReader<T> for XYZReader<T, R>
+where
+    R: std::io::BufRead,
+    T: nalgebra::Scalar + std::str::FromStr,
+    Error: std::convert::From<<T as std::str::FromStr>::Err>
+{
+    type Snapshot = XYZSnapshot<T>;
+    fn read_snapshot(&mut self) -> Result<std::option::Option<Self::Snapshot>> {
+        let mut snapshot = XYZSnapshot::new(std::string::String::new(), std::vec::Vec::new());
+        if self.read_snapshot_into(&mut snapshot)? {
+            Ok(Some(snapshot))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// An iterator adaptor that transforms each snapshot while reading.
+///
+/// It is constructed by `XYZReader::map_snapshots`. See it for the details.
+pub struct MapSnapshots<I, F> {
+    iter: I,
+    f:    F,
+}
+
+impl<T, I, F> std::iter::Iterator for MapSnapshots<I, F>
+where
+    I: std::iter::Iterator<Item = XYZSnapshot<T>>,
+    F: FnMut(XYZSnapshot<T>) -> XYZSnapshot<T>,
+{
+    type Item = XYZSnapshot<T>;
+    fn next(&mut self) -> std::option::Option<Self::Item> {
+        self.iter.next().map(&mut self.f)
+    }
+    fn size_hint(&self) -> (usize, std::option::Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+/// An iterator adaptor that re-tags coordinates of each snapshot.
+///
+/// It is constructed by `ReinterpretKindExt::reinterpret_kind`. See it for
+/// the details.
+pub struct ReinterpretKind<I> {
+    iter: I,
+    kind: CoordKind,
+}
+
+impl<T, I> std::iter::Iterator for ReinterpretKind<I>
+where
+    I: std::iter::Iterator<Item = XYZSnapshot<T>>
+{
+    type Item = XYZSnapshot<T>;
+    fn next(&mut self) -> std::option::Option<Self::Item> {
+        let kind = self.kind;
+        self.iter.next().map(|mut snapshot| {
+            snapshot.particles = snapshot.particles.into_iter().map(|mut p| {
+                p.xyz = p.xyz.into_kind(kind);
+                p
+            }).collect();
+            snapshot
+        })
+    }
+    fn size_hint(&self) -> (usize, std::option::Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+/// Adds `reinterpret_kind` to any iterator of `XYZSnapshot`.
+///
+/// It is useful when the kind of coordinates in a file is labeled wrongly.
+/// Since it wraps an iterator, it can be chained with other adaptors.
+/// ```no_run
+/// use trajan::coordinate::CoordKind;
+/// use trajan::xyz::{XYZReader, ReinterpretKindExt};
+/// let reader = XYZReader::open_pos("example.xyz").unwrap().f64();
+/// for snapshot in reader.step_by(10).reinterpret_kind(CoordKind::Velocity) {
+///     assert_eq!(snapshot.which(), Some(CoordKind::Velocity));
 /// }
 /// ```
-pub struct XYZWriter<W: std::io::Write> {
-    bufwriter: std::io::BufWriter<W>,
+pub trait ReinterpretKindExt<T>: std::iter::Iterator<Item = XYZSnapshot<T>> + Sized {
+    /// Yields snapshots with each coordinate re-tagged as `kind`. The values
+    /// are not changed.
+    fn reinterpret_kind(self, kind: CoordKind) -> ReinterpretKind<Self> {
+        ReinterpretKind{iter: self, kind}
+    }
 }
 
-impl<W: std::io::Write> XYZWriter<W> {
-    /// Constructs XYZReader.
-    pub fn new(inner: W) -> Self {
-        XYZWriter{
-            bufwriter: std::io::BufWriter::new(inner),
+impl<T, I> ReinterpretKindExt<T> for I
+where
+    I: std::iter::Iterator<Item = XYZSnapshot<T>>
+{}
+
+/// Counts the number of snapshots in a file without parsing particles.
+///
+/// Only the first line of each snapshot is parsed, and the rest are skipped.
+/// It is much faster than reading all the snapshots. Fails if the last
+/// snapshot is truncated. Blank lines between snapshots are ignored, but `#`
+/// lines are not; use `count_frames_with_options` for a file read with
+/// `XYZReader::skip_hash_comments`.
+///
+/// The file is read sequentially and no byte offset is kept, so files larger
+/// than 4 GB can be counted even on 32-bit targets.
+///
+/// ```no_run
+/// let n = trajan::xyz::count_frames("example.xyz").unwrap();
+/// println!("{} snapshots", n);
+/// ```
+pub fn count_frames<P>(path: P) -> Result<usize>
+where
+    P: std::convert::AsRef<std::path::Path>
+{
+    let f = std::fs::File::open(path)?;
+    scan_frames(std::io::BufReader::new(f), false)
+}
+
+/// Counts the number of snapshots like `count_frames`, skipping `#` lines
+/// before each snapshot in the same way as `XYZReader` with the options.
+/// The other options do not change the number of snapshots.
+///
+/// ```no_run
+/// use trajan::xyz::XYZReaderOptions;
+/// let options = XYZReaderOptions::new().skip_hash_comments(true);
+/// let n = trajan::xyz::count_frames_with_options("example.xyz", &options).unwrap();
+/// println!("{} snapshots", n);
+/// ```
+pub fn count_frames_with_options<P>(path: P, options: &XYZReaderOptions) -> Result<usize>
+where
+    P: std::convert::AsRef<std::path::Path>
+{
+    let f = std::fs::File::open(path)?;
+    scan_frames(std::io::BufReader::new(f), options.skip_hash_comments)
+}
+
+/// Reads all the snapshots in a file into a trajectory.
+///
+/// Unlike the iterator of `XYZReader` that stops at the first error, it
+/// fails if any snapshot is formatted in an invalid way, so a broken file
+/// does not silently become a short trajectory.
+///
+/// ```no_run
+/// use trajan::coordinate::CoordKind;
+/// let traj = trajan::xyz::read_xyz::<f64, _>("example.xyz", CoordKind::Position).unwrap();
+/// println!("{} snapshots", traj.snapshots.len());
+/// ```
+pub fn read_xyz<T, P>(path: P, kind: CoordKind) -> Result<VecTrajectory<XYZSnapshot<T>>>
+where
+    T: std::str::FromStr,
+    Error: std::convert::From<<T as std::str::FromStr>::Err>,
+    P: std::convert::AsRef<std::path::Path>
+{
+    let mut reader    = XYZReader::open(kind, path)?;
+    let mut snapshots = std::vec::Vec::new();
+    loop {
+        let mut snapshot = XYZSnapshot::new(std::string::String::new(), std::vec::Vec::new());
+        if !reader.read_snapshot_into(&mut snapshot)? {
+            break;
         }
+        snapshots.push(snapshot);
     }
+    Ok(VecTrajectory::new(snapshots))
+}
+
+/// Reads all the snapshots in a file in double precision. See `read_xyz`.
+pub fn read_xyz_f64<P>(path: P, kind: CoordKind) -> Result<VecTrajectory<XYZSnapshot<f64>>>
+where
+    P: std::convert::AsRef<std::path::Path>
+{
+    read_xyz(path, kind)
+}
+
+/// Reads all the snapshots in a file in single precision. See `read_xyz`.
+pub fn read_xyz_f32<P>(path: P, kind: CoordKind) -> Result<VecTrajectory<XYZSnapshot<f32>>>
+where
+    P: std::convert::AsRef<std::path::Path>
+{
+    read_xyz(path, kind)
+}
+
+fn scan_frames<R: std::io::BufRead>(mut reader: R, skip_hash: bool) -> Result<usize> {
+    let mut line   = std::string::String::new();
+    let mut frames = 0;
+    let mut first  = true;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(frames);
+        }
+        if first {
+            strip_bom(&mut line);
+            first = false;
+        }
+        if line.trim().is_empty() || (skip_hash && line.trim_start().starts_with('#')) {
+            continue;
+        }
+        let num = parse_count(&line)?;
+
+        // comment line and particles
+        let n_lines = num.checked_add(1).ok_or_else(|| Error::invalid_format(
+            format!("too many particles: {}", num)))?;
+        for i in 0 .. n_lines {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(Error::invalid_format(format!(
+                    "snapshot {} is truncated: {} of {} lines found",
+                    frames, i, n_lines)));
+            }
+        }
+        frames += 1;
+    }
+}
+
+/// Reads several xyz files as one sequence of snapshots.
+///
+/// The files are read in the given order; the paths are not sorted. When a
+/// file reaches its end, the next one is opened. An error that occurs while
+/// opening or reading a file is reported with the path of the file.
+///
+/// ```no_run
+/// use trajan::xyz::MultiFileReader;
+/// use trajan::coordinate::CoordKind;
+/// let paths = vec!["run1.xyz".into(), "run2.xyz".into()];
+/// let reader = MultiFileReader::<f64>::new(CoordKind::Position, &paths);
+/// for snapshot in reader {
+///     println!("{} particles in a snapshot", snapshot.particles.len());
+/// }
+/// ```
+pub struct MultiFileReader<T> {
+    pub kind: CoordKind,
+    paths:   std::collections::VecDeque<std::path::PathBuf>,
+    current: std::option::Option<(std::path::PathBuf,
+                                  XYZReader<T, std::io::BufReader<std::fs::File>>)>,
+}
+
+impl<T> MultiFileReader<T>
+where
+    T: std::str::FromStr,
+    Error: std::convert::From<<T as std::str::FromStr>::Err>
+{
+    /// Constructs MultiFileReader. Files are opened when they are needed.
+    pub fn new(kind: CoordKind, paths: &[std::path::PathBuf]) -> Self {
+        MultiFileReader{kind, paths: paths.iter().cloned().collect(), current: None}
+    }
+
+    /// Returns the path of the file that is currently read.
+    pub fn current_path(&self) -> std::option::Option<&std::path::Path> {
+        self.current.as_ref().map(|(path, _)| path.as_path())
+    }
+
+    /// Reads the next snapshot into `snapshot`, advancing to the next file if
+    /// needed. Returns false after all the files are read.
+    pub fn read_snapshot_into(&mut self, snapshot: &mut XYZSnapshot<T>) -> Result<bool> {
+        loop {
+            if self.current.is_none() {
+                let path = match self.paths.pop_front() {
+                    Some(path) => path,
+                    None       => return Ok(false),
+                };
+                let reader = XYZReader::open(self.kind, &path)
+                    .map_err(|e| Self::with_path(&path, e))?;
+                self.current = Some((path, reader));
+            }
+            let (path, reader) = self.current.as_mut().unwrap();
+            match reader.read_snapshot_into(snapshot) {
+                Ok(true)  => return Ok(true),
+                Ok(false) => self.current = None,
+                Err(e)    => return Err(Self::with_path(path, e)),
+            }
+        }
+    }
+
+    fn with_path(path: &std::path::Path, e: Error) -> Error {
+        e.with_prefix(&path.display().to_string())
+    }
+}
+
+impl<T> std::iter::Iterator for MultiFileReader<T>
+where
+    T: std::str::FromStr,
+    Error: std::convert::From<<T as std::str::FromStr>::Err>
+{
+    type Item = XYZSnapshot<T>;
+    fn next(&mut self) -> std::option::Option<Self::Item> {
+        let mut snapshot = XYZSnapshot::new(std::string::String::new(), std::vec::Vec::new());
+        match self.read_snapshot_into(&mut snapshot) {
+            Ok(true) => Some(snapshot),
+            _        => None,
+        }
+    }
+}
+
+impl<T> TrajectoryReader<T> for MultiFileReader<T>
+where
+    T: nalgebra::Scalar + std::str::FromStr,
+    Error: std::convert::From<<T as std::str::FromStr>::Err>
+{
+    type Snapshot = XYZSnapshot<T>;
+    fn read_snapshot(&mut self) -> Result<std::option::Option<Self::Snapshot>> {
+        let mut snapshot = XYZSnapshot::new(std::string::String::new(), std::vec::Vec::new());
+        if self.read_snapshot_into(&mut snapshot)? {
+            Ok(Some(snapshot))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Writes XYZSnapshot.
+///
+/// ```no_run
+/// use trajan::xyz::{XYZReader, XYZWriter};
+/// let reader     = XYZReader::open_pos("example.xyz").unwrap().f64();
+/// let mut writer = XYZWriter::new(std::io::stdout());
+/// for snapshot in reader {
+///     writer.write_snapshot(&snapshot).unwrap();
+/// }
+/// ```
+///
+/// By default, the comment stored in a snapshot is written as it is. To
+/// generate comments from snapshots, use `with_comment_fn`. Coordinates are
+/// written with 16 digits after the decimal point by `write_snapshot`, and in
+/// the shortest form that is read back as the same value by
+/// `write_snapshot_extxyz`. Both can be changed by `with_number_format`.
+pub struct XYZWriter<T, W: std::io::Write> {
+    bufwriter:  std::io::BufWriter<W>,
+    comment_fn: std::option::Option<CommentFn<T>>,
+    number_format: std::option::Option<(NumberFormat, WriteNumberFn<T>)>,
+    frames_written: usize,
+}
+
+/// How `XYZWriter` writes coordinates and charges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumberFormat {
+    /// `precision` digits after the decimal point, like `0.0000000123`.
+    Fixed{precision: usize},
+    /// scientific notation with `precision` digits after the decimal point,
+    /// like `1.23e-8`. It is suitable for values in reduced units that may
+    /// be very small or very large.
+    Scientific{precision: usize},
+}
+
+impl std::default::Default for NumberFormat {
+    fn default() -> Self {
+        NumberFormat::Fixed{precision: 16}
+    }
+}
+
+impl NumberFormat {
+    fn write<T>(self, w: &mut dyn std::io::Write, x: &T) -> std::io::Result<()>
+    where
+        T: std::fmt::Display + std::fmt::LowerExp
+    {
+        match self {
+            NumberFormat::Fixed{precision}      => write!(w, "{:.*}", precision, x),
+            NumberFormat::Scientific{precision} => write!(w, "{:.*e}", precision, x),
+        }
+    }
+}
+
+/// A function that generates a comment line from a snapshot.
+type CommentFn<T> = std::boxed::Box<dyn Fn(&XYZSnapshot<T>) -> std::string::String>;
+
+// `NumberFormat::write` for the coordinates. It is kept as a function
+// pointer so that only `with_number_format` requires `LowerExp`.
+type WriteNumberFn<T> = fn(NumberFormat, &mut dyn std::io::Write, &T) -> std::io::Result<()>;
+
+impl<T, W: std::io::Write> XYZWriter<T, W> {
+    /// Constructs XYZWriter.
+    pub fn new(inner: W) -> Self {
+        XYZWriter{
+            bufwriter:  std::io::BufWriter::new(inner),
+            comment_fn: None,
+            number_format: None,
+            frames_written: 0,
+        }
+    }
+
+    /// Sets a function that generates the comment line of each snapshot.
+    /// The generated comment overrides the comment stored in the snapshot.
+    ///
+    /// ```no_run
+    /// use trajan::xyz::{XYZReader, XYZWriter};
+    /// let reader     = XYZReader::open_pos("example.xyz").unwrap().f64();
+    /// let mut writer = XYZWriter::new(std::io::stdout())
+    ///     .with_comment_fn(|s| format!("{} particles", s.particles.len()));
+    /// for snapshot in reader {
+    ///     writer.write_snapshot(&snapshot).unwrap();
+    /// }
+    /// ```
+    pub fn with_comment_fn<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&XYZSnapshot<T>) -> std::string::String + 'static
+    {
+        self.comment_fn = Some(std::boxed::Box::new(f));
+        self
+    }
+
+    /// Sets the format of coordinates and charges.
+    ///
+    /// ```no_run
+    /// use trajan::xyz::{NumberFormat, XYZReader, XYZWriter};
+    /// let reader     = XYZReader::open_pos("lj.xyz").unwrap().f64();
+    /// let mut writer = XYZWriter::new(std::io::stdout())
+    ///     .with_number_format(NumberFormat::Scientific{precision: 8});
+    /// for snapshot in reader {
+    ///     writer.write_snapshot(&snapshot).unwrap();
+    /// }
+    /// ```
+    pub fn with_number_format(mut self, format: NumberFormat) -> Self
+    where
+        T: std::fmt::Display + std::fmt::LowerExp
+    {
+        self.number_format = Some((format, NumberFormat::write::<T>));
+        self
+    }
+
+    // Writes a coordinate or a charge in the format set by
+    // `with_number_format`, or with `precision` digits if it is not set.
+    // The shortest form is used if `precision` is None.
+    fn write_number(&mut self, x: &T, precision: std::option::Option<usize>)
+        -> std::io::Result<()>
+    where
+        T: std::fmt::Display
+    {
+        match (self.number_format, precision) {
+            (Some((format, write)), _) => write(format, &mut self.bufwriter, x),
+            (None, Some(precision))    => write!(self.bufwriter, "{:.*}", precision, x),
+            (None, None)               => write!(self.bufwriter, "{}", x),
+        }
+    }
+
+    // Writes a charge or a value of an attribute in the same way as
+    // `write_number`.
+    fn write_f64(&mut self, x: f64, precision: std::option::Option<usize>)
+        -> std::io::Result<()>
+    {
+        match (self.number_format, precision) {
+            (Some((format, _)), _)  => format.write(&mut self.bufwriter, &x),
+            (None, Some(precision)) => write!(self.bufwriter, "{:.*}", precision, x),
+            (None, None)            => write!(self.bufwriter, "{}", x),
+        }
+    }
+
+    /// writes a snapshot.
+    pub fn write_snapshot(&mut self, ss: &XYZSnapshot<T>) -> Result<()>
+    where
+        T: std::fmt::Display
+    {
+        let generated = self.comment_fn.as_ref().map(|f| f(ss));
+        self.write_with_comment(ss, generated.as_deref().unwrap_or(&ss.comment))
+    }
+
+    /// Writes a snapshot with the comment generated from it, like a value
+    /// computed by the `analysis` module. It overrides both the comment
+    /// stored in the snapshot and `with_comment_fn`, so that a comment can
+    /// borrow local variables, e.g. a reference structure.
+    ///
+    /// ```no_run
+    /// use trajan::analysis::radius_of_gyration;
+    /// use trajan::xyz::{XYZReader, XYZWriter};
+    /// let reader     = XYZReader::open_pos("protein.xyz").unwrap().f64();
+    /// let mut writer = XYZWriter::open("annotated.xyz").unwrap();
+    /// for snapshot in reader {
+    ///     writer.write_snapshot_annotated(&snapshot, |s| {
+    ///         format!("Rg={}", radius_of_gyration(s).unwrap())
+    ///     }).unwrap();
+    /// }
+    /// ```
+    pub fn write_snapshot_annotated<F>(&mut self, ss: &XYZSnapshot<T>, f: F) -> Result<()>
+    where
+        T: std::fmt::Display,
+        F: FnOnce(&XYZSnapshot<T>) -> std::string::String
+    {
+        self.write_with_comment(ss, &f(ss))
+    }
+
+    fn write_with_comment(&mut self, ss: &XYZSnapshot<T>, comment: &str) -> Result<()>
+    where
+        T: std::fmt::Display
+    {
+        self.bufwriter.write_all(ss.particles.len().to_string().as_bytes())?;
+        self.bufwriter.write_all(b"\n")?;
+        self.bufwriter.write_all(comment.as_bytes())?;
+        self.bufwriter.write_all(b"\n")?;
+        for particle in &ss.particles {
+            write!(self.bufwriter, "{:8}", particle.name)?;
+            for x in particle.xyz.iter() {
+                self.bufwriter.write_all(b" ")?;
+                self.write_number(x, Some(16))?;
+            }
+            if let Some(q) = particle.charge {
+                self.bufwriter.write_all(b" ")?;
+                self.write_f64(q, Some(16))?;
+            }
+            self.bufwriter.write_all(b"\n")?;
+        }
+        self.frames_written += 1;
+        Ok(())
+    }
+
+    /// Writes a snapshot in the extended xyz format with the box, so that
+    /// viewers like OVITO or ASE can read the box from the comment line.
+    ///
+    /// The comment line has `Lattice` (the three lattice vectors in order)
+    /// and `Properties` that describes the columns: the name, the coordinate
+    /// (`pos`, `velo` or `force`, depending on the kind), and then the
+    /// attributes listed in `properties`, e.g. `"charge"`. The comment stored
+    /// in the snapshot is not written.
+    ///
+    /// Fails without writing anything if a particle does not have one of the
+    /// attributes, the types of an attribute differ between particles, an
+    /// attribute cannot be written in a column (a matrix, or a string that
+    /// contains whitespace), or the particles have different kinds.
+    ///
+    /// ```no_run
+    /// use trajan::boundary::SimulationBox;
+    /// use trajan::xyz::{XYZReader, XYZWriter};
+    /// let b = SimulationBox::cuboid(nalgebra::Vector3::new(10.0, 10.0, 10.0)).unwrap();
+    /// let mut writer = XYZWriter::open("boxed.xyz").unwrap();
+    /// for snapshot in XYZReader::open_pos("example.xyz").unwrap().f64() {
+    ///     writer.write_snapshot_extxyz(&snapshot, &b, &[]).unwrap();
+    /// }
+    /// ```
+    pub fn write_snapshot_extxyz(&mut self, ss: &XYZSnapshot<T>,
+                                 boundary: &SimulationBox<T>,
+                                 properties: &[&str]) -> Result<()>
+    where
+        T: nalgebra::Real + std::fmt::Display
+    {
+        let coordinate = match ss.check_kind()? {
+            Some(CoordKind::Velocity) => "velo",
+            Some(CoordKind::Force)    => "force",
+            _                         => "pos",
+        };
+        let mut descriptor = format!("species:S:1:{}:R:3", coordinate);
+        let mut columns    = std::vec::Vec::with_capacity(properties.len());
+        for name in properties {
+            let column: std::vec::Vec<Attribute> = ss.particles.iter()
+                .map(|p| p.attribute(name))
+                .collect::<std::option::Option<_>>()
+                .ok_or_else(|| Error::invalid_condition(format!(
+                    "a particle does not have attribute \"{}\"", name)))?;
+            descriptor += &format!(":{}:{}", name, extxyz_type(name, &column)?);
+            columns.push(column);
+        }
+        let lattice: std::vec::Vec<_> = boundary.lattice().iter().map(|x| x.to_string()).collect();
+
+        writeln!(self.bufwriter, "{}", ss.particles.len())?;
+        writeln!(self.bufwriter, "Lattice=\"{}\" Properties={}", lattice.join(" "), descriptor)?;
+        for (i, particle) in ss.particles.iter().enumerate() {
+            self.bufwriter.write_all(particle.name.as_bytes())?;
+            for x in particle.xyz.iter() {
+                self.bufwriter.write_all(b" ")?;
+                self.write_number(x, None)?;
+            }
+            for column in columns.iter() {
+                match column[i] {
+                    Attribute::Vector(ref v) => for x in v.iter() {
+                        self.bufwriter.write_all(b" ")?;
+                        self.write_f64(*x, None)?;
+                    },
+                    Attribute::Float(x) => {
+                        self.bufwriter.write_all(b" ")?;
+                        self.write_f64(x, None)?;
+                    }
+                    Attribute::Integer(x)    => write!(self.bufwriter, " {}", x)?,
+                    Attribute::String(ref x) => write!(self.bufwriter, " {}", x)?,
+                    _ => unreachable!("checked by extxyz_type"),
+                }
+            }
+            self.bufwriter.write_all(b"\n")?;
+        }
+        self.frames_written += 1;
+        Ok(())
+    }
+
+    /// Writes every `stride`-th snapshot of the trajectory, i.e. snapshots
+    /// 0, stride, 2 * stride, ... to thin it. Fails if `stride` is zero.
+    ///
+    /// ```no_run
+    /// use trajan::trajectory::VecTrajectory;
+    /// use trajan::xyz::{XYZReader, XYZWriter};
+    /// let traj: VecTrajectory<_> = XYZReader::open_pos("example.xyz").unwrap().f64().collect();
+    /// let mut writer = XYZWriter::open("thinned.xyz").unwrap();
+    /// writer.write_trajectory_strided(&traj, 10).unwrap();
+    /// ```
+    pub fn write_trajectory_strided<Tr>(&mut self, traj: &Tr, stride: usize) -> Result<()>
+    where
+        T: nalgebra::Scalar + std::fmt::Display,
+        Tr: Trajectory<T> + std::ops::Index<usize, Output = XYZSnapshot<T>>,
+    {
+        if stride == 0 {
+            return Err(Error::invalid_condition("stride must be positive".to_string()));
+        }
+        for i in (0 .. traj.len()).step_by(stride) {
+            self.write_snapshot(&traj[i])?;
+        }
+        Ok(())
+    }
+
+    /// Returns the number of snapshots written by this writer. Snapshots
+    /// already in the file, e.g. opened by `open_append`, are not counted.
+    pub fn frames_written(&self) -> usize {
+        self.frames_written
+    }
+
+    /// Writes the buffered data into the underlying writer.
+    ///
+    /// The buffer is also flushed when the writer is dropped, but errors are
+    /// ignored there. Call this to make sure the data is written, e.g. before
+    /// reading the file again.
+    pub fn flush(&mut self) -> Result<()> {
+        self.bufwriter.flush()?;
+        Ok(())
+    }
+
+    /// Flushes the buffer and returns the underlying writer.
+    pub fn into_inner(self) -> Result<W> {
+        self.bufwriter.into_inner().map_err(|e| Error::from(e.into_error()))
+    }
+}
+
+// type and the number of columns of an attribute in the extended xyz
+// `Properties`. The type is determined by the first particle.
+fn extxyz_type(name: &str, column: &[Attribute]) -> Result<&'static str> {
+    let ty = match column.first() {
+        Some(Attribute::Float(_))   => "R:1",
+        Some(Attribute::Integer(_)) => "I:1",
+        Some(Attribute::String(_))  => "S:1",
+        Some(Attribute::Vector(_))  => "R:3",
+        Some(_) => return Err(Error::invalid_condition(format!(
+            "attribute \"{}\" cannot be written in a column", name))),
+        // no particle. any type is fine.
+        None => "R:1",
+    };
+    let consistent = column.iter().all(|x| {
+        std::mem::discriminant(x) == std::mem::discriminant(&column[0]) && match x {
+            Attribute::String(x) => !x.is_empty() && !x.contains(char::is_whitespace),
+            _ => true,
+        }
+    });
+    if !consistent {
+        return Err(Error::invalid_condition(format!(
+            "attribute \"{}\" has different types or a string with whitespace", name)));
+    }
+    Ok(ty)
+}
+
+impl<T> XYZWriter<T, std::fs::File> {
+    /// opens a file in path and construct XYZWriter using the file.
+    /// The file is created if it does not exist, and truncated if it does.
+    pub fn open<P>(path: P) -> Result<Self>
+    where
+        P: std::convert::AsRef<std::path::Path>
+    {
+        let f = std::fs::File::create(path)?;
+        Ok(XYZWriter::new(f))
+    }
+
+    /// opens a file in path and construct XYZWriter that appends snapshots
+    /// to the end of the file. The file is created if it does not exist.
+    /// Since each snapshot has its own number of particles, the file remains
+    /// valid after appending. If the file does not end with a line feed, it
+    /// is written before the first snapshot.
+    pub fn open_append<P>(path: P) -> Result<Self>
+    where
+        P: std::convert::AsRef<std::path::Path>
+    {
+        use std::io::{Read, Seek};
+        let mut f = std::fs::OpenOptions::new()
+            .create(true).read(true).append(true).open(path)?;
+        if f.seek(std::io::SeekFrom::End(0))? != 0 {
+            let mut last = [0u8; 1];
+            f.seek(std::io::SeekFrom::End(-1))?;
+            f.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                f.write_all(b"\n")?;
+            }
+        }
+        Ok(XYZWriter::new(f))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A path in the temporary directory that does not collide with another
+    // test process running at the same time.
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("trajan_{}_{}.xyz", name, std::process::id()))
+    }
+
+    #[test]
+    fn create_xyz_particle() {
+        let p = XYZParticle::new(
+            "H".to_string(), Coordinate::Position{x:1.0, y:2.0, z:3.0});
+        assert_eq!(p.name, "H");
+        assert_eq!(p.xyz,  Coordinate::Position{x:1.0, y:2.0, z:3.0});
+    }
+    #[test]
+    fn access_xyz_particle() {
+        let p = XYZParticle::new(
+            "H".to_string(), Coordinate::Position{x:1.0, y:2.0, z:3.0});
+
+        assert_eq!(p.mass(),  None);
+        assert_eq!(p.pos(),   Some(nalgebra::Vector3::new(1.0, 2.0, 3.0)));
+        assert_eq!(p.vel(),   None);
+        assert_eq!(p.force(), None);
+
+        if let Attribute::String(name) = p.attribute("name").unwrap() {
+            assert_eq!(name, "H");
+        } else {
+            panic!("attribute \"name\" should be a String");
+        }
+    }
+    #[test]
+    fn access_xyz_particle_through_trait_object() {
+        let p = XYZParticle::new(
+            "H".to_string(), Coordinate::Position{x:1.0, y:2.0, z:3.0});
+        let particle: &dyn Particle<f64, Value = f64> = &p;
+
+        let name = std::string::String::from("name");
+        assert_eq!(particle.attribute("name").unwrap().as_string(), Some("H"));
+        assert_eq!(particle.attribute(&name).unwrap().as_string(), Some("H"));
+        assert!(particle.attribute("charge").is_none());
+    }
+    #[test]
+    fn read_xyz_line() {
+        {
+            let p = XYZParticle::from_line("H 1.0 2.0 3.0", CoordKind::Position,
+                                           LineFormat::default()).unwrap();
+            assert_eq!(p.name, "H");
+            assert_eq!(p.xyz,  Coordinate::Position{x:1.0, y:2.0, z:3.0});
+        }
+        {
+            let p = "H 1.0 2.0 3.0".parse::<XYZParticle<f64>>().unwrap();
+            assert_eq!(p.name, "H");
+            assert_eq!(p.xyz,  Coordinate::Position{x:1.0, y:2.0, z:3.0});
+        }
+    }
+    #[test]
+    fn construct_xyz_snapshot() {
+        let empty = XYZSnapshot::<f64>::new("test".to_string(), vec![]);
+        assert_eq!(empty.comment, "test");
+        assert_eq!(empty.which(), None);
+        assert!(empty.particles.is_empty());
+
+        let s = XYZSnapshot::<f64>::new("test".to_string(), vec![
+            "H 1.0 2.0 3.0".parse().unwrap(),
+            "C 3.0 2.0 1.0".parse().unwrap(),
+        ]);
+        assert_eq!(s.comment, "test");
+        assert_eq!(s.which(), Some(CoordKind::Position));
+        assert_eq!(s.particles.len(), 2);
+    }
+
+    #[test]
+    fn read_xyz() {
+        let contents: &[u8] = b"\
+            2
+            t = 1
+            H 1.0 2.0 3.0
+            C 3.0 2.0 1.0
+            2
+            t = 2
+            H 1.1 2.1 3.1
+            C 3.1 2.1 1.1
+            2
+            t = 3
+            H 1.2 2.2 3.2
+            C 3.2 2.2 1.2"
+            ;
+        let mut reader = XYZReader::new(CoordKind::Position, contents).f32();
+        let s1 = reader.read_snapshot().unwrap();
+        let s2 = reader.read_snapshot().unwrap();
+        let s3 = reader.read_snapshot().unwrap();
+
+        assert_eq!(s1.comment, "t = 1");
+        assert_eq!(s2.comment, "t = 2");
+        assert_eq!(s3.comment, "t = 3");
+
+        assert_eq!(s1.particles[0].name, "H");
+        assert_eq!(s1.particles[1].name, "C");
+        assert_eq!(s2.particles[0].name, "H");
+        assert_eq!(s2.particles[1].name, "C");
+        assert_eq!(s3.particles[0].name, "H");
+        assert_eq!(s3.particles[1].name, "C");
+
+        assert_eq!(s1.particles[0].xyz, Coordinate::Position{x:1.0,y:2.0,z:3.0});
+        assert_eq!(s1.particles[1].xyz, Coordinate::Position{x:3.0,y:2.0,z:1.0});
+        assert_eq!(s2.particles[0].xyz, Coordinate::Position{x:1.1,y:2.1,z:3.1});
+        assert_eq!(s2.particles[1].xyz, Coordinate::Position{x:3.1,y:2.1,z:1.1});
+        assert_eq!(s3.particles[0].xyz, Coordinate::Position{x:1.2,y:2.2,z:3.2});
+        assert_eq!(s3.particles[1].xyz, Coordinate::Position{x:3.2,y:2.2,z:1.2});
+    }
+    #[test]
+    fn switch_kind_while_reading() {
+        let contents: &[u8] = b"\
+            1
+            t = 1
+            H 1.0 2.0 3.0
+            1
+            t = 2
+            H 1.1 2.1 3.1";
+        let mut reader = XYZReader::new(CoordKind::Position, contents).f64();
+        let s1 = reader.read_snapshot().unwrap();
+        reader.set_kind(CoordKind::Velocity);
+        let s2 = reader.read_snapshot().unwrap();
+
+        assert_eq!(s1.which(), Some(CoordKind::Position));
+        assert_eq!(s2.which(), Some(CoordKind::Velocity));
+        assert_eq!(s2.particles[0].xyz, Coordinate::Velocity{x:1.1, y:2.1, z:3.1});
+
+        let mut reader = XYZReader::new(CoordKind::Position, contents).f64()
+            .with_kind(CoordKind::Force);
+        assert_eq!(reader.read_snapshot().unwrap().which(), Some(CoordKind::Force));
+    }
+    #[test]
+    fn read_xyz_into_buffer() {
+        let contents: &[u8] = b"\
+            3
+            t = 1
+            H 1.0 2.0 3.0
+            C 3.0 2.0 1.0
+            O 0.0 0.0 0.0
+            2
+            t = 2
+            He 1.1 2.1 3.1
+            N 3.1 2.1 1.1";
+        let mut reader = XYZReader::new(CoordKind::Position, contents).f64();
+        let mut buf = XYZSnapshot::new(std::string::String::new(), std::vec::Vec::new());
+
+        assert!(reader.read_snapshot_into(&mut buf).unwrap());
+        assert_eq!(buf.comment, "t = 1");
+        assert_eq!(buf.particles.len(), 3);
+        let capacity = buf.particles.capacity();
+
+        assert!(reader.read_snapshot_into(&mut buf).unwrap());
+        assert_eq!(buf.comment, "t = 2");
+        assert_eq!(buf.particles.len(), 2);
+        assert_eq!(buf.particles.capacity(), capacity);
+        assert_eq!(buf.particles[0].name, "He");
+        assert_eq!(buf.particles[1].xyz, Coordinate::Position{x:3.1,y:2.1,z:1.1});
+
+        assert!(!reader.read_snapshot_into(&mut buf).unwrap());
+    }
+    #[test]
+    fn read_fractional_coordinates() {
+        let contents: &[u8] = b"\
+            2
+            scaled
+            H 0.5 0.5 0.5
+            C 0.1 0.0 1.0
+            ";
+        let b = SimulationBox::new(nalgebra::Vector3::new(10.0, 0.0, 0.0),
+                                   nalgebra::Vector3::new(5.0, 10.0, 0.0),
+                                   nalgebra::Vector3::new(0.0, 0.0, 10.0)).unwrap();
+        let mut reader = XYZReader::new(CoordKind::Position, contents).f64().fractional(b);
+        let snapshot = reader.read_snapshot().unwrap();
+        let ps = snapshot.positions().unwrap();
+        assert!((ps[0] - nalgebra::Vector3::new(7.5, 5.0, 5.0)).norm() < 1e-12);
+        assert!((ps[1] - nalgebra::Vector3::new(1.0, 0.0, 10.0)).norm() < 1e-12);
+    }
+    #[test]
+    fn read_first_atoms() {
+        let contents: &[u8] = b"\
+            5
+            t = 1
+            H 1.0 2.0 3.0
+            C 3.0 2.0 1.0
+            O 0.0 0.0 0.0
+            N 1.0 1.0 1.0
+            S 2.0 2.0 2.0
+            1
+            t = 2
+            H 1.1 2.1 3.1
+            ";
+        let mut reader = XYZReader::new(CoordKind::Position, contents).f64().max_atoms(2);
+        let first = reader.read_snapshot().unwrap();
+        assert_eq!(first.particles.len(), 2);
+        assert_eq!(first.particles[1].name, "C");
+        assert_eq!(first.declared_count, Some(5));
+
+        let second = reader.read_snapshot().unwrap();
+        assert_eq!(second.comment, "t = 2");
+        assert_eq!(second.particles.len(), 1);
+        assert_eq!(second.declared_count, None);
+    }
+    #[test]
+    fn reinterpret_kind_while_iterating() {
+        let contents: &[u8] = b"\
+            1
+            t = 1
+            H 1.0 2.0 3.0
+            1
+            t = 2
+            H 1.1 2.1 3.1
+            1
+            t = 3
+            H 1.2 2.2 3.2
+            ";
+        let reader = XYZReader::new(CoordKind::Position, contents).f64();
+        let snapshots = reader.step_by(2).reinterpret_kind(CoordKind::Force)
+            .collect::<std::vec::Vec<_>>();
+        assert_eq!(snapshots.len(), 2);
+        for snapshot in snapshots.iter() {
+            assert_eq!(snapshot.which(), Some(CoordKind::Force));
+        }
+        assert_eq!(snapshots[1].particles[0].xyz, Coordinate::Force{x: 1.2, y: 2.2, z: 3.2});
+    }
+    #[test]
+    fn reject_nonfinite_coordinates() {
+        let contents: &[u8] = b"\
+            2
+            t = 1
+            H 1.0 2.0 3.0
+            C nan 2.0 1.0
+            1
+            t = 2
+            H 1.0 -inf 3.0
+            ";
+        let mut reader = XYZReader::new(CoordKind::Position, contents).f64();
+        let snapshot = reader.read_snapshot().unwrap();
+        assert!(snapshot.particles[1].xyz.x().is_nan());
+
+        let mut reader = XYZReader::new(CoordKind::Position, contents).f64()
+            .reject_nonfinite(true);
+        let err = reader.read_snapshot().unwrap_err();
+        assert!(format!("{}", err).contains("C nan 2.0 1.0"));
+        assert!(reader.read_snapshot().is_err());
+    }
+    #[test]
+    fn for_each_xyz_snapshot() {
+        let contents: &[u8] = b"\
+            1
+            t = 1
+            H 1.0 2.0 3.0
+            1
+            t = 2
+            H 1.1 2.1 3.1
+            1
+            t = 3
+            H 1.2 2.2 3.2
+            ";
+        let mut reader = XYZReader::new(CoordKind::Position, contents).f64();
+        let mut comments = std::vec::Vec::new();
+        reader.for_each_snapshot(|s| {
+            comments.push(s.comment.clone());
+            Ok(())
+        }).unwrap();
+        assert_eq!(comments, vec!["t = 1", "t = 2", "t = 3"]);
+
+        // an error from the callback stops the iteration.
+        let mut reader = XYZReader::new(CoordKind::Position, contents).f64();
+        let mut count = 0;
+        let result = reader.for_each_snapshot(|s| {
+            count += 1;
+            if s.comment == "t = 2" {
+                Err(Error::invalid_condition("stop".to_string()))
+            } else {
+                Ok(())
+            }
+        });
+        assert!(result.is_err());
+        assert_eq!(count, 2);
+    }
+    #[test]
+    fn write_xyz() {
+        let s1 = XYZSnapshot::<f32>::new("test".to_string(), vec![
+            "H 1.0 2.0 3.0".parse().unwrap(),
+            "C 3.0 2.0 1.0".parse().unwrap(),
+        ]);
+        let write_result = {
+            let mut buffer = Vec::new();
+            {
+                let mut writer = XYZWriter::new(&mut buffer);
+                writer.write_snapshot(&s1).unwrap();
+            }
+            buffer
+        };
+        eprintln!("{:?}", write_result);
+        let mut reader = XYZReader::new(CoordKind::Position, write_result.as_slice());
+        let s2 = reader.read_snapshot().unwrap();
+
+        assert_eq!(s1, s2);
+    }
+    #[test]
+    fn read_xyz_from_bufread() {
+        let contents: &[u8] = b"\
+            2
+            t = 1
+            H 1.0 2.0 3.0
+            C 3.0 2.0 1.0
+            2
+            t = 2
+            H 1.1 2.1 3.1
+            C 3.1 2.1 1.1"
+            ;
+        // `&[u8]` and `std::io::Cursor` already implement `BufRead`.
+        let reader = XYZReader::from_bufread(
+            CoordKind::Position, std::io::Cursor::new(contents)).f64();
+        let snapshots: std::vec::Vec<_> = reader.collect();
+
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[0].comment, "t = 1");
+        assert_eq!(snapshots[1].comment, "t = 2");
+        assert_eq!(snapshots[0].particles[1].xyz, Coordinate::Position{x:3.0,y:2.0,z:1.0});
+        assert_eq!(snapshots[1].particles[0].xyz, Coordinate::Position{x:1.1,y:2.1,z:3.1});
+    }
+    #[test]
+    fn write_xyz_with_comment_fn() {
+        let s1 = XYZSnapshot::<f32>::new("".to_string(), vec![
+            "H 1.0 2.0 3.0".parse().unwrap(),
+            "C 3.0 2.0 1.0".parse().unwrap(),
+        ]);
+        let write_result = {
+            let mut buffer = Vec::new();
+            {
+                let mut writer = XYZWriter::new(&mut buffer)
+                    .with_comment_fn(|s| format!("frame with {} particles", s.particles.len()));
+                writer.write_snapshot(&s1).unwrap();
+            }
+            buffer
+        };
+        let mut reader = XYZReader::new(CoordKind::Position, write_result.as_slice()).f32();
+        let s2 = reader.read_snapshot().unwrap();
 
-    /// writes a snapshot.
-    pub fn write_snapshot<T>(&mut self, ss: &XYZSnapshot<T>) -> Result<()>
-    where
-        T: std::fmt::Display
-    {
-        self.bufwriter.write(ss.particles.len().to_string().as_bytes())?;
-        self.bufwriter.write(b"\n")?;
-        self.bufwriter.write(ss.comment.as_bytes())?;
-        self.bufwriter.write(b"\n")?;
-        for particle in &ss.particles {
-            self.bufwriter.write(particle.to_string().as_bytes())?;
-            self.bufwriter.write(b"\n")?;
+        assert_eq!(s2.comment, "frame with 2 particles");
+        assert_eq!(s2.particles, s1.particles);
+    }
+    #[test]
+    fn write_xyz_annotated() {
+        use crate::analysis::radius_of_gyration;
+        let s1 = XYZSnapshot::<f64>::new("original".to_string(), vec![
+            "H -1.0 0.0 0.0".parse().unwrap(),
+            "H  1.0 0.0 0.0".parse().unwrap(),
+        ]);
+        let mut buffer = Vec::new();
+        {
+            let mut writer = XYZWriter::new(&mut buffer)
+                .with_comment_fn(|_| "overridden".to_string());
+            writer.write_snapshot_annotated(&s1, |s| {
+                format!("Rg={}", radius_of_gyration(s).unwrap())
+            }).unwrap();
+            writer.write_snapshot(&s1).unwrap();
         }
-        Ok(())
+        let mut reader = XYZReader::new(CoordKind::Position, buffer.as_slice()).f64();
+        let s2 = reader.read_snapshot().unwrap();
+        assert_eq!(s2.comment, "Rg=1");
+        assert_eq!(s2.particles, s1.particles);
+        assert_eq!(reader.read_snapshot().unwrap().comment, "overridden");
     }
-}
+    #[test]
+    fn bounding_box_of_snapshot() {
+        let s = XYZSnapshot::<f64>::new("".to_string(), vec![
+            "H -1.0  2.0  0.5".parse().unwrap(),
+            "C  3.0 -4.0  0.0".parse().unwrap(),
+            "O  0.0  1.0 -2.5".parse().unwrap(),
+        ]);
+        let (lower, upper) = s.bounding_box().unwrap();
+        assert_eq!(lower, nalgebra::Vector3::new(-1.0, -4.0, -2.5));
+        assert_eq!(upper, nalgebra::Vector3::new( 3.0,  2.0,  0.5));
+        assert_eq!(s.extent().unwrap(), nalgebra::Vector3::new(4.0, 6.0, 3.0));
 
-impl XYZWriter<std::fs::File> {
-    /// opens a file in path and construct XYZWriter using the file.
-    pub fn open<P>(path: P) -> Result<Self>
-    where
-        P: std::convert::AsRef<std::path::Path>
-    {
-        let f = std::fs::File::open(path)?;
-        Ok(XYZWriter{bufwriter: std::io::BufWriter::new(f)})
+        let empty = XYZSnapshot::<f64>::new("".to_string(), vec![]);
+        assert_eq!(empty.bounding_box(), None);
+        assert_eq!(empty.extent(), None);
     }
-}
+    #[test]
+    fn split_snapshot_by_name() {
+        let snapshot = XYZSnapshot::new("mixture".to_string(), vec![
+            XYZParticle::new("A".to_string(), Coordinate::Velocity{x:1.0, y:0.0, z:0.0}),
+            XYZParticle::new("B".to_string(), Coordinate::Velocity{x:2.0, y:0.0, z:0.0}),
+            XYZParticle::new("A".to_string(), Coordinate::Velocity{x:3.0, y:0.0, z:0.0}),
+            XYZParticle::new("A".to_string(), Coordinate::Velocity{x:4.0, y:0.0, z:0.0}),
+        ]);
+        let groups = snapshot.split_by_name();
+        assert_eq!(groups.len(), 2);
 
+        let a = &groups["A"];
+        assert_eq!(a.comment, "mixture");
+        assert_eq!(a.which(), Some(CoordKind::Velocity));
+        let xs = a.particles.iter().map(|p| *p.xyz.x()).collect::<std::vec::Vec<_>>();
+        assert_eq!(xs, vec![1.0, 3.0, 4.0]);
+        assert_eq!(groups["B"].particles.len(), 1);
+    }
+    #[test]
+    fn parse_step_and_time_in_comment() {
+        let snapshot = |comment: &str| XYZSnapshot::<f64>::new(comment.to_string(), vec![]);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        // ASE / CP2K
+        let s = snapshot("i =      100, time =      50.000, E =     -17.1");
+        assert_eq!(s.parse_step(), Some(100));
+        assert_eq!(s.parse_time(), Some(50.0));
+        // LAMMPS dump
+        let s = snapshot("Atoms. Timestep: 2000");
+        assert_eq!(s.parse_step(), Some(2000));
+        assert_eq!(s.parse_time(), None);
+        // compact
+        let s = snapshot("step=5;t=1.5e-3");
+        assert_eq!(s.parse_step(), Some(5));
+        assert_eq!(s.parse_time(), Some(1.5e-3));
+        // key with an invalid value is skipped
+        let s = snapshot("Step NaN Step 12");
+        assert_eq!(s.parse_step(), Some(12));
+        let s = snapshot("no metadata here");
+        assert_eq!(s.parse_step(), None);
+        assert_eq!(s.parse_time(), None);
+    }
     #[test]
-    fn create_xyz_particle() {
-        let p = XYZParticle::new(
-            "H".to_string(), Coordinate::Position{x:1.0, y:2.0, z:3.0});
-        assert_eq!(p.name, "H");
-        assert_eq!(p.xyz,  Coordinate::Position{x:1.0, y:2.0, z:3.0});
+    fn count_frames_while_reading() {
+        let contents: &[u8] = b"\
+            1
+            t = 1
+            H 1.0 2.0 3.0
+            1
+            t = 2
+            H 1.1 2.1 3.1
+            ";
+        let mut reader = XYZReader::new(CoordKind::Position, contents).f64();
+        assert_eq!(reader.frames_read(), 0);
+        let _ = reader.read_snapshot().unwrap();
+        let _ = reader.read_snapshot().unwrap();
+        assert_eq!(reader.frames_read(), 2);
+        assert!(reader.read_snapshot().is_err());
+        assert_eq!(reader.frames_read(), 2);
     }
     #[test]
-    fn access_xyz_particle() {
-        let p = XYZParticle::new(
-            "H".to_string(), Coordinate::Position{x:1.0, y:2.0, z:3.0});
+    fn approx_eq_snapshots() {
+        let s1 = XYZSnapshot::<f64>::new("a".to_string(), vec![
+            "H 1.0 2.0 3.0".parse().unwrap(),
+        ]);
+        let s2 = XYZSnapshot::<f64>::new("b".to_string(), vec![
+            "H 1.0000000001 2.0 3.0".parse().unwrap(),
+        ]);
+        let s3 = XYZSnapshot::<f64>::new("a".to_string(), vec![
+            "C 1.0 2.0 3.0".parse().unwrap(),
+        ]);
+        assert_ne!(s1, s2);
+        assert!(s1.approx_eq(&s2, 1e-6));
+        assert!(!s1.approx_eq(&s2, 1e-12));
+        assert!(!s1.approx_eq(&s3, 1e-6));
+    }
+    #[test]
+    fn flush_xyz_writer() {
+        let path = temp_path("flush_xyz_writer");
+        let snapshot = XYZSnapshot::new("t = 1".to_string(), vec![
+            XYZParticle::new("H".to_string(), Coordinate::Position{x:1.0, y:2.0, z:3.0}),
+        ]);
+        let mut writer = XYZWriter::open(&path).unwrap();
+        writer.write_snapshot(&snapshot).unwrap();
+        writer.flush().unwrap();
 
-        assert_eq!(p.mass(),  None);
-        assert_eq!(p.pos(),   Some(nalgebra::Vector3::new(1.0, 2.0, 3.0)));
-        assert_eq!(p.vel(),   None);
-        assert_eq!(p.force(), None);
+        // the writer is still alive here
+        let mut reader = XYZReader::open_pos(&path).unwrap().f64();
+        assert_eq!(reader.read_snapshot().unwrap(), snapshot);
 
-        if let Attribute::String(name) = p.attribute("name").unwrap() {
-            assert_eq!(name, "H");
-        } else {
-            assert!(false);
-        }
+        writer.write_snapshot(&snapshot).unwrap();
+        let file = writer.into_inner().unwrap();
+        drop(file);
+        assert_eq!(count_frames(&path).unwrap(), 2);
+        std::fs::remove_file(&path).unwrap();
     }
     #[test]
-    fn read_xyz_line() {
+    fn append_to_existing_file() {
+        let path = temp_path("append_to_existing_file");
+        let _ = std::fs::remove_file(&path);
+        let snapshot = |t: usize| XYZSnapshot::new(format!("t = {}", t), vec![
+            XYZParticle::new("H".to_string(), Coordinate::Position{x: t as f64, y: 0.0, z: 0.0}),
+        ]);
         {
-            let p = XYZParticle::from_line("H 1.0 2.0 3.0", CoordKind::Position).unwrap();
-            assert_eq!(p.name, "H");
-            assert_eq!(p.xyz,  Coordinate::Position{x:1.0, y:2.0, z:3.0});
+            let mut writer = XYZWriter::open_append(&path).unwrap();
+            writer.write_snapshot(&snapshot(1)).unwrap();
+            writer.write_snapshot(&snapshot(2)).unwrap();
+            assert_eq!(writer.frames_written(), 2);
+            writer.flush().unwrap();
         }
         {
-            let p = "H 1.0 2.0 3.0".parse::<XYZParticle<f64>>().unwrap();
-            assert_eq!(p.name, "H");
-            assert_eq!(p.xyz,  Coordinate::Position{x:1.0, y:2.0, z:3.0});
+            let mut writer = XYZWriter::open_append(&path).unwrap();
+            assert_eq!(writer.frames_written(), 0);
+            writer.write_snapshot(&snapshot(3)).unwrap();
+            writer.write_snapshot(&snapshot(4)).unwrap();
+            writer.flush().unwrap();
         }
-    }
-    #[test]
-    fn construct_xyz_snapshot() {
-        let empty = XYZSnapshot::<f64>::new("test".to_string(), vec![]);
-        assert_eq!(empty.comment, "test");
-        assert_eq!(empty.which(), None);
-        assert!(empty.particles.is_empty());
+        let times: std::vec::Vec<_> = XYZReader::<f64, _>::open_pos(&path).unwrap()
+            .map(|s| s.parse_time().unwrap()).collect();
+        assert_eq!(times, vec![1.0, 2.0, 3.0, 4.0]);
 
-        let s = XYZSnapshot::<f64>::new("test".to_string(), vec![
-            "H 1.0 2.0 3.0".parse().unwrap(),
-            "C 3.0 2.0 1.0".parse().unwrap(),
-        ]);
-        assert_eq!(s.comment, "test");
-        assert_eq!(s.which(), Some(CoordKind::Position));
-        assert_eq!(s.particles.len(), 2);
+        // written by another program without the last line feed
+        std::fs::write(&path, b"1\nt = 5\nH 5.0 0.0 0.0").unwrap();
+        {
+            let mut writer = XYZWriter::open_append(&path).unwrap();
+            writer.write_snapshot(&snapshot(6)).unwrap();
+            writer.flush().unwrap();
+        }
+        let times: std::vec::Vec<_> = XYZReader::<f64, _>::open_pos(&path).unwrap()
+            .map(|s| s.parse_time().unwrap()).collect();
+        assert_eq!(times, vec![5.0, 6.0]);
+        std::fs::remove_file(&path).unwrap();
     }
 
     #[test]
-    fn read_xyz() {
+    fn count_xyz_frames() {
         let contents: &[u8] = b"\
             2
             t = 1
@@ -481,50 +2438,582 @@ mod tests {
             2
             t = 3
             H 1.2 2.2 3.2
-            C 3.2 2.2 1.2"
-            ;
-        let mut reader = XYZReader::new(CoordKind::Position, contents).f32();
-        let s1 = reader.read_snapshot().unwrap();
-        let s2 = reader.read_snapshot().unwrap();
-        let s3 = reader.read_snapshot().unwrap();
+            C 3.2 2.2 1.2
+            ";
+        assert_eq!(scan_frames(contents, false).unwrap(), 3);
 
-        assert_eq!(s1.comment, "t = 1");
-        assert_eq!(s2.comment, "t = 2");
-        assert_eq!(s3.comment, "t = 3");
+        let truncated: &[u8] = b"\
+            2
+            t = 1
+            H 1.0 2.0 3.0
+            C 3.0 2.0 1.0
+            2
+            t = 2
+            H 1.1 2.1 3.1";
+        assert!(scan_frames(truncated, false).is_err());
 
-        assert_eq!(s1.particles[0].name, "H");
-        assert_eq!(s1.particles[1].name, "C");
-        assert_eq!(s2.particles[0].name, "H");
-        assert_eq!(s2.particles[1].name, "C");
-        assert_eq!(s3.particles[0].name, "H");
-        assert_eq!(s3.particles[1].name, "C");
+        let huge = format!("{}\nt = 1\n", usize::MAX);
+        assert!(scan_frames(huge.as_bytes(), false).is_err());
 
-        assert_eq!(s1.particles[0].xyz, Coordinate::Position{x:1.0,y:2.0,z:3.0});
-        assert_eq!(s1.particles[1].xyz, Coordinate::Position{x:3.0,y:2.0,z:1.0});
-        assert_eq!(s2.particles[0].xyz, Coordinate::Position{x:1.1,y:2.1,z:3.1});
-        assert_eq!(s2.particles[1].xyz, Coordinate::Position{x:3.1,y:2.1,z:1.1});
-        assert_eq!(s3.particles[0].xyz, Coordinate::Position{x:1.2,y:2.2,z:3.2});
-        assert_eq!(s3.particles[1].xyz, Coordinate::Position{x:3.2,y:2.2,z:1.2});
+        let path = temp_path("count_xyz_frames");
+        std::fs::write(&path, contents).unwrap();
+        assert_eq!(count_frames(&path).unwrap(), 3);
+        std::fs::remove_file(&path).unwrap();
     }
+
     #[test]
-    fn write_xyz() {
-        let s1 = XYZSnapshot::<f32>::new("test".to_string(), vec![
-            "H 1.0 2.0 3.0".parse().unwrap(),
-            "C 3.0 2.0 1.0".parse().unwrap(),
+    fn read_whole_file() {
+        let contents: &[u8] = b"1\nt = 0\nH 0.0 0.0 0.0\n\
+                                1\nt = 1\nH 1.0 0.0 0.0\n\
+                                1\nt = 2\nH 2.0 0.0 0.0\n";
+        let path = temp_path("read_whole_xyz_file");
+        std::fs::write(&path, contents).unwrap();
+        let traj = read_xyz_f64(&path, CoordKind::Position).unwrap();
+        assert_eq!(traj.snapshots.len(), 3);
+        assert_eq!(traj[2].particles[0].xyz, Coordinate::Position{x: 2.0, y: 0.0, z: 0.0});
+        assert_eq!(read_xyz_f32(&path, CoordKind::Velocity).unwrap()[1].which(),
+                   Some(CoordKind::Velocity));
+
+        // the iterator stops at the broken snapshot, but read_xyz fails
+        std::fs::write(&path, &contents[.. 40]).unwrap();
+        assert_eq!(XYZReader::open_pos(&path).unwrap().f64().count(), 1);
+        assert!(super::read_xyz::<f64, _>(&path, CoordKind::Position).is_err());
+        std::fs::remove_file(&path).unwrap();
+        assert!(read_xyz_f64(&path, CoordKind::Position).is_err());
+    }
+
+    #[test]
+    fn enumerate_particles_in_order() {
+        let s = XYZSnapshot::new("".to_string(), vec![
+            XYZParticle::new("A".to_string(), Coordinate::Position{x: 0.0, y: 0.0, z: 0.0}),
+            XYZParticle::new("B".to_string(), Coordinate::Velocity{x: 1.0, y: 0.0, z: 0.0}),
+            XYZParticle::new("C".to_string(), Coordinate::Position{x: 2.0, y: 0.0, z: 0.0}),
         ]);
-        let write_result = {
-            let mut buffer = Vec::new();
-            {
-                let mut writer = XYZWriter::new(&mut buffer);
-                writer.write_snapshot(&s1).unwrap();
+        let names: std::vec::Vec<_> = s.enumerate_particles()
+            .map(|(i, p)| (i, p.name.as_str())).collect();
+        assert_eq!(names, vec![(0, "A"), (1, "B"), (2, "C")]);
+
+        let positions: std::vec::Vec<_> = s.enumerate_positions().collect();
+        assert_eq!(positions, vec![(0, nalgebra::Vector3::new(0.0, 0.0, 0.0)),
+                                   (2, nalgebra::Vector3::new(2.0, 0.0, 0.0))]);
+    }
+
+    #[test]
+    fn reader_with_options() {
+        let contents: &[u8] = b"\
+            # header
+            3
+              water
+            O 0.0 0.0 0.0 -0.834
+            H 0.9572 0.0 0.0 0.417
+            H -0.2400 0.9266 0.0 0.417
+            # second
+            1
+            broken
+            O NaN 0.0 0.0 0.0
+            ";
+        let options = XYZReaderOptions::new()
+            .max_atoms(2)
+            .reject_nonfinite(true)
+            .skip_hash_comments(true)
+            .preserve_comment_whitespace(true)
+            .with_charge(true);
+        let mut reader = XYZReader::<f64, _>::with_options(CoordKind::Velocity, contents, &options);
+        let snapshot = reader.read_snapshot().unwrap();
+        assert_eq!(snapshot.which(), Some(CoordKind::Velocity));
+        assert_eq!(snapshot.particles.len(), 2);
+        assert_eq!(snapshot.declared_count, Some(3));
+        assert!(snapshot.comment.starts_with(" ") && snapshot.comment.trim() == "water");
+        assert_eq!(snapshot.particles[1].charge, Some(0.417));
+        assert_eq!(reader.skipped_lines(), &["# header"]);
+        assert!(reader.read_snapshot().is_err());
+
+        // the defaults are the same as the plain reader
+        let plain: &[u8] = b"1\n  comment\nH 1.0 2.0 3.0\n";
+        let mut reader = XYZReader::<f64, _>::with_options(
+            CoordKind::Position, plain, &XYZReaderOptions::default());
+        assert_eq!(reader.read_snapshot().unwrap(),
+                   XYZReader::<f64, _>::new(CoordKind::Position, plain).read_snapshot().unwrap());
+    }
+
+    #[test]
+    fn read_snapshot_at_offset() {
+        let contents = "2\nt = 1\nH 1.0 2.0 3.0\nC 3.0 2.0 1.0\n\
+                        1\nt = 2\nH 1.1 2.1 3.1\n\
+                        1\nt = 3\nH 1.2 2.2 3.2\n";
+        let second = contents.find("1\nt = 2").unwrap() as u64;
+        let mut reader = XYZReader::<f64, _>::new(CoordKind::Position,
+                                                  std::io::Cursor::new(contents.as_bytes()));
+        let snapshot = reader.read_snapshot_at(second).unwrap();
+        assert_eq!(snapshot.parse_time(), Some(2.0));
+        assert_eq!(reader.read_snapshot().unwrap().parse_time(), Some(3.0));
+        assert_eq!(reader.read_snapshot_at(0).unwrap().parse_time(), Some(1.0));
+
+        // in the middle of a particle line, "0 2.0 3.0" would be parsed as an
+        // empty snapshot if it were not checked
+        let middle = contents.find("0 2.0 3.0").unwrap() as u64;
+        assert!(reader.read_snapshot_at(middle).is_err());
+        assert!(reader.read_snapshot_at(contents.len() as u64 + 10).is_err());
+    }
+
+    #[test]
+    fn translate_while_streaming() {
+        let contents: &[u8] = b"\
+            1
+            t = 1
+            H 1.0 2.0 3.0
+            1
+            t = 2
+            H 1.5 2.5 3.5
+            ";
+        let shift  = nalgebra::Vector3::new(10.0, 0.0, -1.0);
+        let reader = XYZReader::<f64, _>::new(CoordKind::Position, contents)
+            .map_snapshots(|mut s| {s.apply_transform(|r| *r += shift); s});
+
+        let mut writer = XYZWriter::new(std::vec::Vec::new());
+        for snapshot in reader {
+            writer.write_snapshot(&snapshot).unwrap();
+        }
+        let written = writer.into_inner().unwrap();
+        let positions: std::vec::Vec<_> = XYZReader::<f64, _>::new(CoordKind::Position, written.as_slice())
+            .map(|s| s.positions().unwrap()[0]).collect();
+        assert_eq!(positions, vec![nalgebra::Vector3::new(11.0, 2.0, 2.0),
+                                   nalgebra::Vector3::new(11.5, 2.5, 2.5)]);
+    }
+
+    #[test]
+    fn skip_hash_header_lines() {
+        let contents: &[u8] = b"\
+            # generated by a script
+            # units: angstrom
+            2
+            first
+            H 1.0 2.0 3.0
+            # not a comment here
+            2
+            second
+            H 1.1 2.1 3.1
+            C 3.1 2.1 1.1
+            ";
+        let mut reader = XYZReader::<f64, _>::new(CoordKind::Position, contents)
+            .skip_hash_comments(true);
+        // `#` lines in a snapshot are parsed as particles
+        assert!(reader.read_snapshot().is_err());
+
+        let contents: &[u8] = b"\
+            # generated by a script
+            # units: angstrom
+            1
+            first
+            H 1.0 2.0 3.0
+            1
+            second
+            H 1.1 2.1 3.1
+            ";
+        let mut reader = XYZReader::<f64, _>::new(CoordKind::Position, contents)
+            .skip_hash_comments(true);
+        let first = reader.read_snapshot().unwrap();
+        assert_eq!(first.comment, "first");
+        assert_eq!(reader.skipped_lines(), &["# generated by a script", "# units: angstrom"]);
+        let second = reader.read_snapshot().unwrap();
+        assert_eq!(second.comment, "second");
+        assert!(reader.skipped_lines().is_empty());
+
+        let mut strict = XYZReader::<f64, _>::new(CoordKind::Position, contents);
+        assert!(strict.read_snapshot().is_err());
+
+        // counting follows the same rule
+        assert_eq!(scan_frames(contents, true).unwrap(), 2);
+        assert!(scan_frames(contents, false).is_err());
+        let path = temp_path("count_frames_skipping_hash_lines");
+        std::fs::write(&path, contents).unwrap();
+        let options = XYZReaderOptions::new().skip_hash_comments(true);
+        assert_eq!(count_frames_with_options(&path, &options).unwrap(), 2);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn preserve_comment_bytes() {
+        let contents = "1\n   fixed  width   \n\
+                        H        1.0000000000000000 2.0000000000000000 3.0000000000000000\n";
+        let mut reader = XYZReader::<f64, _>::new(CoordKind::Position, contents.as_bytes())
+            .preserve_comment_whitespace(true);
+        let snapshot = reader.read_snapshot().unwrap();
+        assert_eq!(snapshot.comment, "   fixed  width   ");
+
+        let mut writer = XYZWriter::new(std::vec::Vec::new());
+        writer.write_snapshot(&snapshot).unwrap();
+        assert_eq!(writer.into_inner().unwrap(), contents.as_bytes());
+
+        let mut reader = XYZReader::<f64, _>::new(CoordKind::Position, contents.as_bytes());
+        assert_eq!(reader.read_snapshot().unwrap().comment, "fixed  width");
+    }
+
+    #[test]
+    fn extxyz_lattice_round_trip() {
+        let b = SimulationBox::from_lengths_angles(nalgebra::Vector3::new(10.0, 12.0, 15.0),
+                                                   nalgebra::Vector3::new(90.0, 100.0, 120.0)).unwrap();
+        let snapshot = XYZSnapshot::<f64>::new("ignored".to_string(), vec![
+            XYZParticle::new("Na".to_string(), Coordinate::Position{x: 1.0, y: 2.0, z: 3.0}).with_charge(1.0),
+            XYZParticle::new("Cl".to_string(), Coordinate::Position{x: 4.0, y: 5.0, z: 6.0}).with_charge(-1.0),
+        ]);
+        let mut writer = XYZWriter::new(std::vec::Vec::new());
+        writer.write_snapshot_extxyz(&snapshot, &b, &["charge"]).unwrap();
+        assert!(writer.write_snapshot_extxyz(&snapshot, &b, &["id"]).is_err());
+        assert!(writer.write_snapshot_extxyz(&snapshot, &b, &["name"]).is_ok());
+        assert_eq!(writer.frames_written(), 2);
+        let written = writer.into_inner().unwrap();
+
+        let mut reader = XYZReader::<f64, _>::new(CoordKind::Position, written.as_slice())
+            .with_charge(true);
+        let read = reader.read_snapshot().unwrap();
+        assert!(read.comment.ends_with("Properties=species:S:1:pos:R:3:charge:R:1"));
+        assert_eq!(read.particles[1].charge, Some(-1.0));
+        assert!(read.approx_eq(&snapshot, 0.0));
+        let lattice = read.parse_lattice().unwrap().unwrap();
+        assert!((lattice.lattice() - b.lattice()).norm() < 1e-12);
+
+        let plain = XYZSnapshot::<f64>::new("step 10".to_string(), vec![]);
+        assert!(plain.parse_lattice().unwrap().is_none());
+        let broken = XYZSnapshot::<f64>::new("lattice=\"1 0 0 0 1 0\"".to_string(), vec![]);
+        assert!(broken.parse_lattice().is_err());
+
+        // only a whole key matches
+        let other = XYZSnapshot::<f64>::new(
+            "superlattice=\"2 0 0 0 2 0 0 0 2\" Lattice=\"3 0 0 0 3 0 0 0 3\"".to_string(), vec![]);
+        let lattice = other.parse_lattice().unwrap().unwrap();
+        assert!((lattice.lattice() - nalgebra::Matrix3::from_diagonal_element(3.0)).norm() < 1e-12);
+        let other = XYZSnapshot::<f64>::new("superlattice=\"2 0 0 0 2 0 0 0 2\"".to_string(), vec![]);
+        assert!(other.parse_lattice().unwrap().is_none());
+    }
+
+    #[test]
+    fn line_number_in_error() {
+        let contents: &[u8] = b"2\nfirst\nH 0.0 0.0 0.0\nH 1.0 0.0 0.0\n\
+                                2\nsecond\nH 0.0 0.0 0.0\nH 1.0 x 0.0\n\
+                                2\nthird\nH 0.0 0.0\n";
+        let mut reader = XYZReader::<f64, _>::new(CoordKind::Position, contents);
+        assert!(reader.read_snapshot().is_ok());
+        let err = reader.read_snapshot().unwrap_err();
+        assert_eq!(err.line(), Some(8));
+        match err.kind() {
+            crate::error::ErrorKind::InvalidFormat{error, ..} => assert!(error.starts_with("at line 8: ")),
+            _ => panic!("unexpected error: {}", err),
+        }
+        let mut reader = XYZReader::<f64, _>::new(CoordKind::Position, &contents[71 ..]);
+        assert_eq!(reader.read_snapshot().unwrap_err().line(), Some(3));
+
+        // the number of lines before an offset is unknown
+        let mut reader = XYZReader::<f64, _>::new(CoordKind::Position, std::io::Cursor::new(contents));
+        assert_eq!(reader.read_snapshot_at(71).unwrap_err().line(), None);
+        assert_eq!(reader.read_snapshot_at(0).unwrap().comment, "first");
+        assert_eq!(reader.read_snapshot().unwrap_err().line(), Some(8));
+    }
+
+    #[test]
+    fn read_all_frames_of_concatenated_file() {
+        // frames from different tools: the counts and the comments differ,
+        // and the third frame has an invalid particle line.
+        let contents: &[u8] = b"1\ni = 0\nH 0.0 0.0 0.0\n\
+                                2\nLattice=\"10 0 0 0 10 0 0 0 10\"\nO 0.0 0.0 0.0\nH 1.0 0.0 0.0\n\
+                                3\n\nC 0.0 0.0 0.0\nC 1.0 y 0.0\nC 2.0 0.0 0.0\n\
+                                2\ntime = 1.5\nN 0.0 0.0 0.0\nN 1.1 0.0 0.0\n";
+        let mut reader = XYZReader::<f64, _>::new(CoordKind::Position, contents);
+        let frames = reader.read_all_frames();
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[0].as_ref().unwrap().particles.len(), 1);
+        assert_eq!(frames[1].as_ref().unwrap().particles.len(), 2);
+        assert_eq!(frames[2].as_ref().unwrap_err().line(), Some(11));
+        assert_eq!(frames[3].as_ref().unwrap().comment, "time = 1.5");
+        assert_eq!(frames[3].as_ref().unwrap().particles[1].name, "N");
+
+        // the rest cannot be found after an invalid number of particles
+        let contents: &[u8] = b"1\nfirst\nH 0.0 0.0 0.0\nx\nsecond\nH 0.0 0.0 0.0\n";
+        let mut reader = XYZReader::<f64, _>::new(CoordKind::Position, contents);
+        let frames = reader.read_all_frames();
+        assert_eq!(frames.len(), 2);
+        assert!(frames[0].is_ok());
+        assert_eq!(frames[1].as_ref().unwrap_err().line(), Some(4));
+
+        // a count line that failed before does not stop the later frames
+        let contents: &[u8] = b"x\n1\nfirst\nH 0.0 0.0 0.0\n\
+                                1\nsecond\nH y 0.0 0.0\n1\nthird\nH 0.0 0.0 0.0\n";
+        let mut reader = XYZReader::<f64, _>::new(CoordKind::Position, contents);
+        assert_eq!(reader.read_snapshot().unwrap_err().line(), Some(1));
+        let frames = reader.read_all_frames();
+        assert_eq!(frames.len(), 3);
+        assert!(frames[0].is_ok());
+        assert_eq!(frames[1].as_ref().unwrap_err().line(), Some(7));
+        assert_eq!(frames[2].as_ref().unwrap().comment, "third");
+    }
+
+    #[test]
+    fn write_in_scientific_notation() {
+        let snapshot = XYZSnapshot::<f64>::new("reduced".to_string(), vec![
+            XYZParticle::new("LJ".to_string(), Coordinate::Position{x: 1.2345e-8, y: 6.0e3, z: -0.5}),
+        ]);
+        let mut writer = XYZWriter::new(std::vec::Vec::new())
+            .with_number_format(NumberFormat::Scientific{precision: 4});
+        writer.write_snapshot(&snapshot).unwrap();
+        let written = writer.into_inner().unwrap();
+        let text = std::string::String::from_utf8(written.clone()).unwrap();
+        assert_eq!(text.lines().nth(2), Some("LJ       1.2345e-8 6.0000e3 -5.0000e-1"));
+
+        let read = XYZReader::<f64, _>::new(CoordKind::Position, written.as_slice())
+            .read_snapshot().unwrap();
+        assert_eq!(read, snapshot);
+
+        let mut writer = XYZWriter::new(std::vec::Vec::new())
+            .with_number_format(NumberFormat::Fixed{precision: 2});
+        writer.write_snapshot(&snapshot).unwrap();
+        let text = std::string::String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert!(text.ends_with("LJ       0.00 6000.00 -0.50\n"));
+
+        // the extended xyz format keeps the shortest form by default
+        let charged = XYZSnapshot::<f64>::new("".to_string(), vec![
+            XYZParticle::new("LJ".to_string(), Coordinate::Position{x: 1.2345e-8, y: 6.0e3, z: -0.5})
+                .with_charge(0.25),
+        ]);
+        let b = SimulationBox::cuboid(nalgebra::Vector3::new(10.0, 10.0, 10.0)).unwrap();
+        let mut writer = XYZWriter::new(std::vec::Vec::new());
+        writer.write_snapshot_extxyz(&charged, &b, &["charge"]).unwrap();
+        let text = std::string::String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(text.lines().nth(2), Some("LJ 0.000000012345 6000 -0.5 0.25"));
+
+        let mut writer = XYZWriter::new(std::vec::Vec::new())
+            .with_number_format(NumberFormat::Scientific{precision: 2});
+        writer.write_snapshot_extxyz(&charged, &b, &["charge"]).unwrap();
+        let text = std::string::String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(text.lines().nth(2), Some("LJ 1.23e-8 6.00e3 -5.00e-1 2.50e-1"));
+    }
+
+    #[test]
+    fn write_every_third_snapshot() {
+        let traj: crate::trajectory::VecTrajectory<XYZSnapshot<f64>> = (0 .. 10).map(|i| {
+            XYZSnapshot::new(format!("t = {}", i), vec![
+                XYZParticle::new("H".to_string(), Coordinate::Position{x: i as f64, y: 0.0, z: 0.0}),
+            ])
+        }).collect();
+
+        let mut writer = XYZWriter::new(std::vec::Vec::new());
+        writer.write_trajectory_strided(&traj, 3).unwrap();
+        assert!(writer.write_trajectory_strided(&traj, 0).is_err());
+        let written = writer.into_inner().unwrap();
+
+        let reader = XYZReader::<f64, _>::new(CoordKind::Position, written.as_slice());
+        let times: std::vec::Vec<_> = reader.map(|s| s.parse_time().unwrap()).collect();
+        assert_eq!(times, vec![0.0, 3.0, 6.0, 9.0]);
+    }
+
+    #[test]
+    fn read_two_dimensional_lines() {
+        let contents: &[u8] = b"\
+            2
+            membrane
+            C 1.0 2.0
+            C 3.0 4.0
+            ";
+        let snapshot = XYZReader::<f64, _>::new(CoordKind::Position, contents)
+            .assume_2d(true).read_snapshot().unwrap();
+        assert_eq!(snapshot.positions().unwrap(), vec![nalgebra::Vector3::new(1.0, 2.0, 0.0),
+                                                       nalgebra::Vector3::new(3.0, 4.0, 0.0)]);
+        assert!(XYZReader::<f64, _>::new(CoordKind::Position, contents).read_snapshot().is_err());
+
+        let charged: &[u8] = b"1\nion\nNa 1.0 2.0 1.0\n";
+        let snapshot = XYZReader::<f64, _>::new(CoordKind::Position, charged)
+            .assume_2d(true).with_charge(true).read_snapshot().unwrap();
+        assert_eq!(snapshot.particles[0].xyz, Coordinate::Position{x: 1.0, y: 2.0, z: 0.0});
+        assert_eq!(snapshot.particles[0].charge, Some(1.0));
+        assert!(XYZReader::<f64, _>::new(CoordKind::Position, charged)
+            .assume_2d(true).read_snapshot().is_err());
+    }
+
+    #[test]
+    fn charge_column_round_trip() {
+        let contents: &[u8] = b"\
+            3
+            water
+            O 0.0 0.0 0.0 -0.834
+            H 0.9572 0.0 0.0 0.417
+            H -0.2400 0.9266 0.0 0.417
+            ";
+        let snapshot = XYZReader::<f64, _>::new(CoordKind::Position, contents)
+            .with_charge(true).read_snapshot().unwrap();
+        assert_eq!(snapshot.particles[0].attribute("charge"), Some(Attribute::Float(-0.834)));
+        assert_eq!(snapshot.positions().unwrap()[1], nalgebra::Vector3::new(0.9572, 0.0, 0.0));
+
+        let mut writer = XYZWriter::new(std::vec::Vec::new());
+        writer.write_snapshot(&snapshot).unwrap();
+        let written = writer.into_inner().unwrap();
+        let reread = XYZReader::<f64, _>::new(CoordKind::Position, written.as_slice())
+            .with_charge(true).read_snapshot().unwrap();
+        assert_eq!(reread, snapshot);
+
+        // the number of columns is checked
+        assert!(XYZReader::<f64, _>::new(CoordKind::Position, contents)
+            .read_snapshot().is_err());
+        let missing: &[u8] = b"1\ncomment\nO 0.0 0.0 0.0\n";
+        assert!(XYZReader::<f64, _>::new(CoordKind::Position, missing)
+            .with_charge(true).read_snapshot().is_err());
+    }
+
+    #[test]
+    fn particle_id_attribute() {
+        let p = XYZParticle::new("H".to_string(), Coordinate::Position{x: 0.0, y: 0.0, z: 0.0});
+        assert_eq!(p.attribute("id"), None);
+        let p = p.with_id(42);
+        assert_eq!(p.attribute("id"), Some(Attribute::Integer(42)));
+        assert_eq!(p.attribute("id").unwrap().as_integer(), Some(42));
+
+        let s = XYZSnapshot::new("".to_string(), vec![p.clone(), p.with_id(7)]);
+        assert_eq!(s.attributes("id"), Some(vec![Attribute::Integer(42), Attribute::Integer(7)]));
+    }
+
+    #[test]
+    fn mixed_coordinate_kinds() {
+        let mixed = XYZSnapshot::new("".to_string(), vec![
+            XYZParticle::new("H".to_string(), Coordinate::Position{x: 0.0, y: 0.0, z: 0.0}),
+            XYZParticle::new("C".to_string(), Coordinate::Velocity{x: 1.0, y: 0.0, z: 0.0}),
+        ]);
+        assert!(!mixed.is_kind_consistent());
+        assert_eq!(mixed.positions(), None);
+        match mixed.check_kind().unwrap_err().kind() {
+            crate::error::ErrorKind::InconsistentKind{..} => {}
+            kind => panic!("unexpected error: {}", kind),
+        }
+
+        let mut consistent = mixed.clone();
+        consistent.particles[1].xyz = consistent.particles[1].xyz.into_kind(CoordKind::Position);
+        assert!(consistent.is_kind_consistent());
+        assert_eq!(consistent.check_kind().unwrap(), Some(CoordKind::Position));
+
+        let empty = XYZSnapshot::<f64>::new("".to_string(), vec![]);
+        assert!(empty.is_kind_consistent());
+        assert_eq!(empty.check_kind().unwrap(), None);
+    }
+
+    #[test]
+    fn convert_precision() {
+        let snapshot = XYZSnapshot::new("precision".to_string(), vec![
+            XYZParticle::new("H".to_string(), Coordinate::Position{x: 0.1, y: 1.0 / 3.0, z: 1e3}),
+            XYZParticle::new("C".to_string(), Coordinate::Velocity{x: -2.7, y: 0.0, z: 1.23456}),
+        ]);
+        let single = snapshot.to_f32();
+        assert_eq!(single.comment, "precision");
+        assert_eq!(single.which(), Some(CoordKind::Position));
+
+        let double = single.to_f64();
+        assert_eq!(double.particles[1].name, "C");
+        assert_eq!(double.particles[1].xyz.which(), CoordKind::Velocity);
+        for (p, q) in snapshot.particles.iter().zip(double.particles.iter()) {
+            for i in 0 .. 3 {
+                let tol = f64::from(f32::EPSILON) * p.xyz[i].abs().max(1.0);
+                assert!((p.xyz[i] - q.xyz[i]).abs() <= tol);
             }
-            buffer
-        };
-        eprintln!("{:?}", write_result);
-        let mut reader = XYZReader::new(CoordKind::Position, write_result.as_slice());
-        let s2 = reader.read_snapshot().unwrap();
+        }
+        assert_eq!(single.map_precision::<f64>(), double);
+    }
 
-        assert_eq!(s1, s2);
+    #[test]
+    fn convert_precision_keeps_charge_and_id() {
+        let snapshot = XYZSnapshot::new("ions".to_string(), vec![
+            XYZParticle::new("Na".to_string(), Coordinate::Position{x: 0.0, y: 0.0, z: 0.0})
+                .with_id(7).with_charge(1.0),
+            XYZParticle::new("Cl".to_string(), Coordinate::Position{x: 2.5, y: 0.0, z: 0.0})
+                .with_id(8).with_charge(-1.0),
+        ]);
+        let converted = snapshot.to_f32().to_f64();
+        assert_eq!(converted.particles[0].id, Some(7));
+        assert_eq!(converted.particles[0].charge, Some(1.0));
+        assert_eq!(converted.particles[1].id, Some(8));
+        assert_eq!(converted.particles[1].charge, Some(-1.0));
+        assert_eq!(converted, snapshot);
     }
-}
 
+    #[test]
+    fn byte_order_mark() {
+        let plain: &[u8] = b"2\nwater\nH 1.0 2.0 3.0\nO 3.0 2.0 1.0\n1\nion\nNa 0.0 0.0 0.0\n";
+        let with_bom = [&b"\xEF\xBB\xBF"[..], plain].concat();
+
+        let expected: std::vec::Vec<_> =
+            XYZReader::<f64, _>::new(CoordKind::Position, plain).collect();
+        let snapshots: std::vec::Vec<_> =
+            XYZReader::<f64, _>::new(CoordKind::Position, with_bom.as_slice()).collect();
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots, expected);
+        assert_eq!(scan_frames(with_bom.as_slice(), false).unwrap(), 2);
+
+        let mut reader = XYZReader::<f64, _>::new(CoordKind::Position,
+                                                  std::io::Cursor::new(with_bom.clone()));
+        assert_eq!(reader.read_snapshot_at(0).unwrap(), expected[0]);
+
+        // only the beginning of the stream is checked
+        let middle = [&b"1\nfirst\nH 0.0 0.0 0.0\n\xEF\xBB\xBF"[..], plain].concat();
+        let mut reader = XYZReader::<f64, _>::new(CoordKind::Position, middle.as_slice());
+        assert!(reader.read_snapshot().is_ok());
+        assert!(reader.read_snapshot().is_err());
+    }
+
+    #[test]
+    fn annotated_count_line() {
+        let contents: &[u8] = b"\
+            3 atoms
+            water
+            O 0.0 0.0 0.0
+            H 1.0 0.0 0.0
+            H 0.0 1.0 0.0
+            ";
+        let mut reader = XYZReader::<f64, _>::new(CoordKind::Position, contents);
+        let snapshot = reader.read_snapshot().unwrap();
+        assert_eq!(snapshot.particles.len(), 3);
+        assert_eq!(snapshot.comment, "water");
+        assert_eq!(scan_frames(contents, false).unwrap(), 1);
+
+        let invalid: &[u8] = b"\
+            three atoms
+            water
+            ";
+        let mut reader = XYZReader::<f64, _>::new(CoordKind::Position, invalid);
+        assert!(reader.read_snapshot().is_err());
+    }
+
+    #[test]
+    fn read_multiple_files() {
+        let first: &[u8] = b"\
+            1
+            t = 1
+            H 1.0 2.0 3.0
+            1
+            t = 2
+            H 1.1 2.1 3.1
+            ";
+        let second: &[u8] = b"\
+            1
+            t = 3
+            H 1.2 2.2 3.2
+            1
+            t = 4
+            H 1.3 2.3 3.3
+            ";
+        let paths = vec![temp_path("read_multiple_files_1"),
+                         temp_path("read_multiple_files_2")];
+        std::fs::write(&paths[0], first).unwrap();
+        std::fs::write(&paths[1], second).unwrap();
+
+        let reader = MultiFileReader::<f64>::new(CoordKind::Position, &paths);
+        let times: std::vec::Vec<_> = reader.map(|s| s.parse_time().unwrap()).collect();
+        assert_eq!(times, vec![1.0, 2.0, 3.0, 4.0]);
+
+        let missing = vec![paths[0].clone(), temp_path("read_multiple_files_none")];
+        let mut reader = MultiFileReader::<f64>::new(CoordKind::Position, &missing);
+        assert_eq!(reader.read_snapshot().unwrap().unwrap().parse_time(), Some(1.0));
+        assert_eq!(reader.read_snapshot().unwrap().unwrap().parse_time(), Some(2.0));
+        let err = reader.read_snapshot().unwrap_err();
+        assert_eq!(*err.kind(), ErrorKind::Io);
+        assert!(err.detail().contains("read_multiple_files_none"));
+
+        for path in paths.iter() {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}